@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{BaseRecord, DeliveryResult, Producer, ProducerContext, ThreadedProducer};
+use rdkafka::{ClientContext, Message, Offset, TopicPartitionList};
+use tokio::sync::mpsc;
+
+use crate::kafka_config::{kafka_config, KafkaConfig};
+
+/// How long a [`Subscriber::poll`] waits for messages before returning empty-handed.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Most messages a [`Subscriber::poll`] returns at once.
+const MAX_POLL_MESSAGES: usize = 500;
+
+/// How long publishing to Kafka waits for room in the producer queue when it's full.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long [`flush_event_bus`] waits for queued messages to be delivered.
+const SHUTDOWN_FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Topic the stocks service queues orders on for the consumer, `KAFKA_TOPIC`.
+pub fn orders_topic() -> &'static str {
+    &kafka_config().topic
+}
+
+/// Topic the orders the consumer keeps failing to process are moved to, `KAFKA_DEAD_LETTER_TOPIC`.
+pub fn dead_letter_topic() -> &'static str {
+    &kafka_config().dead_letter_topic
+}
+
+/// Topic the [`crate::trade_events::TradeEvent`]s are published on, `KAFKA_TRADE_EVENTS_TOPIC`.
+pub fn trade_events_topic() -> &'static str {
+    &kafka_config().trade_events_topic
+}
+
+/// Where a consumer group that hasn't read a topic yet starts reading it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum StartFrom {
+    Earliest,
+    Latest,
+}
+
+/// When a subscriber commits the offsets of the messages it reads, which a restarted member of its
+/// group resumes from.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Commit {
+    /// Once they're returned by [`Subscriber::poll`].
+    OnPoll,
+    /// Only through [`Subscriber::commit`], for subscribers that must not lose the messages they
+    /// were handling when they stopped.
+    Manual,
+}
+
+/// A message read from a topic.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Event {
+    /// Messages published with the same key land on the same Kafka partition, so they're read in
+    /// the order they were published.
+    pub key: Option<String>,
+    pub payload: Vec<u8>,
+    /// Partition of the topic the message was read from, and its position in it.
+    pub partition: i32,
+    pub offset: i64,
+}
+
+/// Where the services publish the messages other services react to.
+pub trait EventBus: Send + Sync {
+    fn publish(&self, topic: &str, key: Option<&str>, payload: &[u8]) -> Result<(), String>;
+
+    /// Reads `topic` as a member of `group`. Each message reaches one subscriber per group.
+    fn subscribe(&self, topic: &str, group: &str, start_from: StartFrom, commit: Commit) -> Result<Box<dyn Subscriber>, String>;
+
+    /// Waits up to `timeout` for the messages published so far to be delivered.
+    fn flush(&self, _timeout: Duration) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+pub trait Subscriber: Send {
+    /// Waits a moment for new messages and returns the ones received, possibly none.
+    fn poll(&mut self) -> Result<Vec<Event>, String>;
+
+    /// Makes `next_offset` of `partition` where the group resumes reading, once every message
+    /// before it has been handled.
+    fn commit(&mut self, partition: i32, next_offset: i64) -> Result<(), String>;
+}
+
+/// Publishes to and reads from Kafka as set up by a [`KafkaConfig`].
+///
+/// Messages are published through a single producer that batches them in the background, so
+/// [`EventBus::publish`] returns once a message is queued. Failed deliveries are logged.
+pub struct KafkaEventBus {
+    config: KafkaConfig,
+    producer: ThreadedProducer<DeliveryReporter>,
+}
+
+impl KafkaEventBus {
+    pub fn new(config: KafkaConfig) -> Result<Self, String> {
+        let producer = config
+            .producer_config()
+            .create_with_context(DeliveryReporter)
+            .map_err(|e| e.to_string())?;
+        Ok(KafkaEventBus { config, producer })
+    }
+
+    /// A bus using the [`kafka_config`] of the process.
+    pub fn from_env() -> Self {
+        KafkaEventBus::new(kafka_config().clone()).expect("Can't create the Kafka producer")
+    }
+}
+
+/// Logs the messages the brokers didn't acknowledge once the producer gave up retrying them.
+struct DeliveryReporter;
+
+impl ClientContext for DeliveryReporter {}
+
+impl ProducerContext for DeliveryReporter {
+    type DeliveryOpaque = ();
+
+    fn delivery(&self, result: &DeliveryResult<'_>, _: Self::DeliveryOpaque) {
+        if let Err((e, message)) = result {
+            println!(
+                "Can't deliver message to {}: {}, payload: {:?}",
+                message.topic(),
+                e,
+                message.payload().map(String::from_utf8_lossy)
+            );
+        }
+    }
+}
+
+impl EventBus for KafkaEventBus {
+    fn publish(&self, topic: &str, key: Option<&str>, payload: &[u8]) -> Result<(), String> {
+        let deadline = Instant::now() + PUBLISH_TIMEOUT;
+        let mut record = BaseRecord::<str, [u8]>::to(topic).payload(payload);
+        if let Some(key) = key {
+            record = record.key(key);
+        }
+        loop {
+            match self.producer.send(record) {
+                Ok(()) => return Ok(()),
+                Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), returned)) if Instant::now() < deadline => {
+                    record = returned;
+                    thread::sleep(Duration::from_millis(100));
+                }
+                Err((e, _)) => return Err(e.to_string()),
+            }
+        }
+    }
+
+    fn subscribe(&self, topic: &str, group: &str, start_from: StartFrom, commit: Commit) -> Result<Box<dyn Subscriber>, String> {
+        let consumer: BaseConsumer = self.config
+            .client_config()
+            .set("group.id", group)
+            .set("enable.auto.commit", "false")
+            .set("enable.partition.eof", "false")
+            .set("auto.offset.reset", match start_from {
+                StartFrom::Earliest => "earliest",
+                StartFrom::Latest => "latest",
+            })
+            .create()
+            .map_err(|e| e.to_string())?;
+        consumer.subscribe(&[topic]).map_err(|e| e.to_string())?;
+        Ok(Box::new(KafkaSubscriber { consumer, topic: topic.to_string(), commit }))
+    }
+
+    fn flush(&self, timeout: Duration) -> Result<(), String> {
+        self.producer.flush(timeout).map_err(|e| e.to_string())
+    }
+}
+
+struct KafkaSubscriber {
+    consumer: BaseConsumer,
+    topic: String,
+    commit: Commit,
+}
+
+impl Subscriber for KafkaSubscriber {
+    fn poll(&mut self) -> Result<Vec<Event>, String> {
+        let mut events = Vec::new();
+        let mut timeout = POLL_TIMEOUT;
+        while events.len() < MAX_POLL_MESSAGES {
+            let Some(message) = self.consumer.poll(timeout) else {
+                break;
+            };
+            let message = message.map_err(|e| e.to_string())?;
+            events.push(Event {
+                key: message.key().map(|key| String::from_utf8_lossy(key).to_string()),
+                payload: message.payload().unwrap_or_default().to_vec(),
+                partition: message.partition(),
+                offset: message.offset(),
+            });
+            timeout = Duration::ZERO;
+        }
+        if self.commit == Commit::OnPoll && !events.is_empty() {
+            self.consumer.commit_consumer_state(CommitMode::Sync).map_err(|e| e.to_string())?;
+        }
+        Ok(events)
+    }
+
+    fn commit(&mut self, partition: i32, next_offset: i64) -> Result<(), String> {
+        let mut offsets = TopicPartitionList::new();
+        offsets
+            .add_partition_offset(&self.topic, partition, Offset::Offset(next_offset))
+            .map_err(|e| e.to_string())?;
+        self.consumer.commit(&offsets, CommitMode::Sync).map_err(|e| e.to_string())
+    }
+}
+
+/// Keeps published messages in memory and hands them to subscribers in the same process, for
+/// local development and tests. Clones share the same messages.
+#[derive(Clone, Default)]
+pub struct InMemoryEventBus {
+    log: Arc<Log>,
+}
+
+#[derive(Default)]
+struct Log {
+    state: Mutex<LogState>,
+    published: Condvar,
+}
+
+#[derive(Default)]
+struct LogState {
+    /// Topic and message, in publishing order. Messages are on partition 0 at their index.
+    messages: Vec<(String, Event)>,
+    /// Where each group is in `messages`, by topic and group.
+    groups: HashMap<(String, String), GroupState>,
+}
+
+#[derive(Default)]
+struct GroupState {
+    /// Position the members of the group have read up to.
+    fetched: usize,
+    /// Position a group without members resumes from.
+    committed: usize,
+    members: usize,
+}
+
+impl InMemoryEventBus {
+    /// Payloads published to `topic` so far, oldest first.
+    pub fn messages(&self, topic: &str) -> Vec<String> {
+        self.log.state
+            .lock()
+            .unwrap()
+            .messages
+            .iter()
+            .filter(|(message_topic, _)| message_topic == topic)
+            .map(|(_, event)| String::from_utf8_lossy(&event.payload).to_string())
+            .collect()
+    }
+
+    /// Forgets every message, and where groups were in them.
+    pub fn clear(&self) {
+        let mut state = self.log.state.lock().unwrap();
+        state.messages.clear();
+        state.groups.clear();
+    }
+}
+
+impl EventBus for InMemoryEventBus {
+    fn publish(&self, topic: &str, key: Option<&str>, payload: &[u8]) -> Result<(), String> {
+        let mut state = self.log.state.lock().unwrap();
+        let event = Event {
+            key: key.map(str::to_string),
+            payload: payload.to_vec(),
+            partition: 0,
+            offset: state.messages.len() as i64,
+        };
+        state.messages.push((topic.to_string(), event));
+        self.log.published.notify_all();
+        Ok(())
+    }
+
+    fn subscribe(&self, topic: &str, group: &str, start_from: StartFrom, commit: Commit) -> Result<Box<dyn Subscriber>, String> {
+        let key = (topic.to_string(), group.to_string());
+        let mut state = self.log.state.lock().unwrap();
+        let start = match start_from {
+            StartFrom::Earliest => 0,
+            StartFrom::Latest => state.messages.len(),
+        };
+        let group = state.groups.entry(key.clone()).or_insert(GroupState {
+            fetched: start,
+            committed: start,
+            members: 0,
+        });
+        // Like a Kafka group after a restart, a group that lost its members rereads what they
+        // didn't commit.
+        if group.members == 0 {
+            group.fetched = group.committed;
+        }
+        group.members += 1;
+        Ok(Box::new(InMemorySubscriber { log: self.log.clone(), key, commit }))
+    }
+}
+
+struct InMemorySubscriber {
+    log: Arc<Log>,
+    key: (String, String),
+    commit: Commit,
+}
+
+impl Subscriber for InMemorySubscriber {
+    fn poll(&mut self) -> Result<Vec<Event>, String> {
+        let state = self.log.state.lock().unwrap();
+        let (mut state, _) = self.log.published
+            .wait_timeout_while(state, POLL_TIMEOUT, |state| {
+                let fetched = state.groups.get(&self.key).map_or(0, |group| group.fetched);
+                !state.messages[fetched.min(state.messages.len())..].iter().any(|(topic, _)| *topic == self.key.0)
+            })
+            .unwrap();
+        let end = state.messages.len();
+        let group = state.groups.entry(self.key.clone()).or_default();
+        let fetched = group.fetched.min(end);
+        group.fetched = end;
+        if self.commit == Commit::OnPoll {
+            group.committed = end;
+        }
+        Ok(state.messages[fetched..]
+            .iter()
+            .filter(|(topic, _)| *topic == self.key.0)
+            .map(|(_, event)| event.clone())
+            .collect())
+    }
+
+    fn commit(&mut self, _partition: i32, next_offset: i64) -> Result<(), String> {
+        let mut state = self.log.state.lock().unwrap();
+        state.groups.entry(self.key.clone()).or_default().committed = next_offset as usize;
+        Ok(())
+    }
+}
+
+impl Drop for InMemorySubscriber {
+    fn drop(&mut self) {
+        if let Some(group) = self.log.state.lock().unwrap().groups.get_mut(&self.key) {
+            group.members = group.members.saturating_sub(1);
+        }
+    }
+}
+
+/// Payloads handed to async code by [`spawn_receiver`].
+pub type Payloads = mpsc::UnboundedReceiver<Vec<u8>>;
+
+/// Polls `subscriber` on its own thread and passes the payloads to async code, until the
+/// receiver is dropped. Failed polls are logged and retried.
+pub fn spawn_receiver(mut subscriber: Box<dyn Subscriber>) -> Payloads {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    thread::spawn(move || {
+        while !sender.is_closed() {
+            match subscriber.poll() {
+                Ok(events) => {
+                    for event in events {
+                        if sender.send(event.payload).is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(e) => {
+                    println!("Can't poll messages: {}", e);
+                    thread::sleep(POLL_TIMEOUT);
+                }
+            }
+        }
+    });
+    receiver
+}
+
+static EVENT_BUS: OnceLock<Box<dyn EventBus>> = OnceLock::new();
+
+/// The process-wide bus, unless another one was installed first: an [`InMemoryEventBus`] when
+/// `EVENT_BUS` is `memory`, a [`KafkaEventBus`] otherwise.
+pub fn event_bus() -> &'static dyn EventBus {
+    EVENT_BUS
+        .get_or_init(|| match env::var("EVENT_BUS").as_deref() {
+            Ok("memory") => Box::new(InMemoryEventBus::default()),
+            _ => Box::new(KafkaEventBus::from_env()),
+        })
+        .as_ref()
+}
+
+/// Whether `EVENT_BUS` selects the in-memory bus, which only reaches this process.
+pub fn is_in_memory() -> bool {
+    env::var("EVENT_BUS").as_deref() == Ok("memory")
+}
+
+/// Delivers the messages still queued by the process-wide bus, for services shutting down.
+pub fn flush_event_bus() {
+    if let Err(e) = event_bus().flush(SHUTDOWN_FLUSH_TIMEOUT) {
+        println!("Can't deliver the queued messages: {}", e);
+    }
+}
+
+/// Makes `bus` the process-wide bus. Fails, giving it back, once a bus is in use.
+pub fn install_event_bus(bus: Box<dyn EventBus>) -> Result<(), Box<dyn EventBus>> {
+    EVENT_BUS.set(bus)
+}
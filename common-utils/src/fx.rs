@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+
+/// Currency of instruments and cash that don't say otherwise.
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Source of exchange rates between ISO 4217 currency codes.
+pub trait FxRateSource {
+    /// Units of `to` that one unit of `from` is worth, or `None` when the pair is unknown.
+    fn rate(&self, from: &str, to: &str) -> Option<BigDecimal>;
+
+    fn convert(&self, amount: &BigDecimal, from: &str, to: &str) -> Option<BigDecimal> {
+        self.rate(from, to).map(|rate| amount * rate)
+    }
+}
+
+/// Fixed rates quoted against USD, for running without a rates provider.
+pub struct StaticFxRates {
+    usd_rates: HashMap<String, BigDecimal>,
+}
+
+impl StaticFxRates {
+    /// `usd_rates` maps each currency code to the USD value of one unit of it.
+    pub fn new(usd_rates: HashMap<String, BigDecimal>) -> Self {
+        let mut usd_rates: HashMap<String, BigDecimal> = usd_rates
+            .into_iter()
+            .map(|(code, rate)| (code.to_uppercase(), rate))
+            .collect();
+        usd_rates.insert(DEFAULT_CURRENCY.to_string(), BigDecimal::from(1));
+        StaticFxRates { usd_rates }
+    }
+
+    /// The built-in table, with rates overridden or added by `FX_RATES`, e.g. `EUR=1.06,GBP=1.22`.
+    /// Pairs without a code or a positive rate are ignored.
+    pub fn from_env() -> Self {
+        let mut rates = StaticFxRates::default();
+        if let Ok(overrides) = env::var("FX_RATES") {
+            for pair in overrides.split(',') {
+                match pair.split_once('=').map(|(code, rate)| (code.trim(), BigDecimal::from_str(rate.trim()))) {
+                    Some((code, Ok(rate))) if !code.is_empty() && rate > BigDecimal::from(0) => {
+                        rates.usd_rates.insert(code.to_uppercase(), rate);
+                    }
+                    _ => println!("Ignoring invalid FX rate: {}", pair),
+                }
+            }
+        }
+        rates
+    }
+}
+
+impl Default for StaticFxRates {
+    fn default() -> Self {
+        let usd_rates = [
+            ("EUR", "1.06"),
+            ("GBP", "1.22"),
+            ("CAD", "0.73"),
+            ("CHF", "1.11"),
+            ("JPY", "0.0067"),
+            ("MXN", "0.055"),
+        ]
+        .iter()
+        .map(|(code, rate)| (code.to_string(), BigDecimal::from_str(rate).unwrap()))
+        .collect();
+        StaticFxRates::new(usd_rates)
+    }
+}
+
+impl FxRateSource for StaticFxRates {
+    fn rate(&self, from: &str, to: &str) -> Option<BigDecimal> {
+        let from_usd = self.usd_rates.get(&from.to_uppercase())?;
+        let to_usd = self.usd_rates.get(&to.to_uppercase())?;
+        if from.eq_ignore_ascii_case(to) {
+            return Some(BigDecimal::from(1));
+        }
+        if to_usd == &BigDecimal::from(0) {
+            return None;
+        }
+        Some(from_usd / to_usd)
+    }
+}
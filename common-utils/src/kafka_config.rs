@@ -0,0 +1,107 @@
+use std::env;
+use std::sync::OnceLock;
+
+use rdkafka::ClientConfig;
+
+/// Settings shared by every Kafka client of the services.
+#[derive(Clone)]
+pub struct KafkaConfig {
+    /// Comma-separated `host:port` list of bootstrap brokers.
+    pub brokers: String,
+    pub client_id: String,
+    /// `plaintext`, `ssl`, `sasl_plaintext` or `sasl_ssl`.
+    pub security_protocol: String,
+    /// `PLAIN`, `SCRAM-SHA-256` or `SCRAM-SHA-512`. Kerberos (`GSSAPI`) isn't built in.
+    pub sasl_mechanism: Option<String>,
+    pub sasl_username: Option<String>,
+    pub sasl_password: Option<String>,
+    /// CA certificate to verify the brokers with, instead of the system ones.
+    pub ssl_ca_location: Option<String>,
+    /// Broker acknowledgements a produced message waits for: `0`, `1` or `all`.
+    pub acks: String,
+    /// `none`, `gzip`, `snappy`, `lz4` or `zstd`.
+    pub compression: String,
+    /// Whether producers avoid duplicates and reordering when retrying, which needs `acks=all`.
+    pub idempotence: bool,
+    /// How long producers wait for more messages to send them in one batch.
+    pub linger_ms: u32,
+    /// Topic orders are queued on.
+    pub topic: String,
+    /// Topic the orders that keep failing are moved to.
+    pub dead_letter_topic: String,
+    /// Topic what happens to orders, trades and portfolios is published on.
+    pub trade_events_topic: String,
+}
+
+impl KafkaConfig {
+    /// Reads `KAFKA_BROKER` (`localhost:9092` by default), `KAFKA_CLIENT_ID` (`stocks`),
+    /// `KAFKA_SECURITY_PROTOCOL` (`plaintext`), `KAFKA_SASL_MECHANISM`, `KAFKA_SASL_USERNAME`,
+    /// `KAFKA_SASL_PASSWORD`, `KAFKA_SSL_CA_LOCATION`, `KAFKA_ACKS` (`all`), `KAFKA_COMPRESSION`
+    /// (`none`), `KAFKA_IDEMPOTENCE` (`true`), `KAFKA_LINGER_MS` (`5`), `KAFKA_TOPIC`
+    /// (`topic-stocks`), `KAFKA_DEAD_LETTER_TOPIC` (the orders topic suffixed with `-dlq`) and
+    /// `KAFKA_TRADE_EVENTS_TOPIC` (the orders topic suffixed with `-events`).
+    pub fn from_env() -> Self {
+        let topic = env_or("KAFKA_TOPIC", "topic-stocks");
+        KafkaConfig {
+            brokers: env_or("KAFKA_BROKER", "localhost:9092"),
+            client_id: env_or("KAFKA_CLIENT_ID", "stocks"),
+            security_protocol: env_or("KAFKA_SECURITY_PROTOCOL", "plaintext"),
+            sasl_mechanism: env::var("KAFKA_SASL_MECHANISM").ok(),
+            sasl_username: env::var("KAFKA_SASL_USERNAME").ok(),
+            sasl_password: env::var("KAFKA_SASL_PASSWORD").ok(),
+            ssl_ca_location: env::var("KAFKA_SSL_CA_LOCATION").ok(),
+            acks: env_or("KAFKA_ACKS", "all"),
+            compression: env_or("KAFKA_COMPRESSION", "none"),
+            idempotence: env_or("KAFKA_IDEMPOTENCE", "true") == "true",
+            linger_ms: env::var("KAFKA_LINGER_MS")
+                .ok()
+                .and_then(|ms| ms.parse().ok())
+                .unwrap_or(5),
+            dead_letter_topic: env_or("KAFKA_DEAD_LETTER_TOPIC", &format!("{}-dlq", topic)),
+            trade_events_topic: env_or("KAFKA_TRADE_EVENTS_TOPIC", &format!("{}-events", topic)),
+            topic,
+        }
+    }
+
+    /// Connection settings common to producers and consumers.
+    pub fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &self.brokers)
+            .set("client.id", &self.client_id)
+            .set("security.protocol", &self.security_protocol);
+        let optional = [
+            ("sasl.mechanism", &self.sasl_mechanism),
+            ("sasl.username", &self.sasl_username),
+            ("sasl.password", &self.sasl_password),
+            ("ssl.ca.location", &self.ssl_ca_location),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                config.set(key, value);
+            }
+        }
+        config
+    }
+
+    pub fn producer_config(&self) -> ClientConfig {
+        let mut config = self.client_config();
+        config
+            .set("acks", &self.acks)
+            .set("compression.type", &self.compression)
+            .set("enable.idempotence", self.idempotence.to_string())
+            .set("linger.ms", self.linger_ms.to_string());
+        config
+    }
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+static KAFKA_CONFIG: OnceLock<KafkaConfig> = OnceLock::new();
+
+/// The configuration of the process, read once with [`KafkaConfig::from_env`].
+pub fn kafka_config() -> &'static KafkaConfig {
+    KAFKA_CONFIG.get_or_init(KafkaConfig::from_env)
+}
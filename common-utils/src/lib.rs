@@ -1,9 +1,16 @@
 // extern crate curl;
 extern crate serde_json;
 
-use std::env;
+pub mod event_bus;
+pub mod fx;
+pub mod kafka_config;
+pub mod quote_cache;
+pub mod quote_client;
+pub mod trade_events;
+pub mod validation;
 
-use curl::easy::{Easy2, Handler, WriteError};
+use curl::easy::{Handler, WriteError};
+use quote_client::QuoteError;
 struct Collector(Vec<u8>);
 impl Handler for Collector {
     fn write(&mut self, data: &[u8]) -> Result<usize, WriteError> {
@@ -13,23 +20,22 @@ impl Handler for Collector {
 }
 use serde_json::Value;
 use serde::{Deserialize, Serialize};
-use kafka::producer::{Producer, Record};
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StockCode {
     pub status: StockStatus,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Stock {
     pub data: StockData,
     pub status: StockStatus,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StockStatus {
     pub rCode: i32,
 }
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct StockData {
     pub symbol: String,
     pub companyName: String,
@@ -45,7 +51,36 @@ pub struct StockData {
     pub keyStats: KeyStats,
     pub notifications: Vec<Notifications>
 }
-#[derive(Debug, Serialize, Deserialize)]
+impl StockData {
+    /// Price an order would fill at: the bid when Nasdaq reports one, otherwise the last sale.
+    pub fn price(&self) -> String {
+        let last_sale_price = self.primaryData.lastSalePrice.replace("$", "");
+        let bid_price = self.primaryData.bidPrice.replace("$", "");
+        if bid_price != "N/A" {
+            bid_price
+        } else {
+            last_sale_price
+        }
+    }
+
+    /// Price of the last sale, which positions are marked to.
+    pub fn last_sale_price(&self) -> String {
+        self.primaryData.lastSalePrice.replace("$", "").replace(",", "")
+    }
+
+    /// Move of the price since the previous close, `UNCH` or `N/A` when there's none.
+    pub fn net_change(&self) -> String {
+        self.primaryData.netChange.replace("+", "").replace(",", "")
+    }
+
+    pub fn percentage_change(&self) -> String {
+        self.primaryData.percentageChange
+            .replace("%", "")
+            .replace("+", "")
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ComplementData {
     pub lastSalePrice: String,
     pub netChange: String,
@@ -60,25 +95,25 @@ pub struct ComplementData {
     pub volume: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct KeyStats {
     pub fiftyTwoWeekHighLow: DefaultData,
     pub dayrange: DefaultData,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DefaultData {
     pub label: String,
     pub value: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Notifications {
     pub headline: String,
     pub eventTypes: Vec<EventTypes>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EventTypes {
     pub message: String,
     pub eventName: String,
@@ -86,7 +121,7 @@ pub struct EventTypes {
     pub id: String
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CustomResult {
     pub stock: Option<Stock>,
     pub success: bool,
@@ -94,55 +129,61 @@ pub struct CustomResult {
 }
 
 pub fn get_stock_from_nasdaq(symbol: String) -> CustomResult  {
-    let url = format!("https://api.nasdaq.com/api/quote/{}/info?assetclass=stocks", symbol);
-    let mut easy = Easy2::new(Collector(Vec::new()));
-    easy.get(true).unwrap();
-    easy.url(&url).unwrap();
-    easy.perform().unwrap();
-    assert_eq!(easy.response_code().unwrap(), 200);
-    let contents = easy.get_ref();
-
-    let body: &str = std::str::from_utf8(&contents.0).unwrap_or_else(|e| {
-        panic!("Failed to parse response from; error is {}", e);
-    });
-    let object: Value = serde_json::from_str(body).unwrap();
-    let stock_code: StockCode = serde_json::from_value(object).unwrap();
+    get_quote_from_nasdaq(symbol, "stocks")
+}
+
+/// Looks up `symbol` in Nasdaq's `asset_class` listings (`stocks`, `etf`, `index`, `crypto`,
+/// `options`...), since the same ticker can name different instruments in each. Quotes are
+/// served from the [`quote_cache::quote_cache`] when recent enough.
+pub fn get_quote_from_nasdaq(symbol: String, asset_class: &str) -> CustomResult  {
+    quote_cache::quote_cache().get(&symbol, asset_class)
+}
+
+/// Same as [`get_quote_from_nasdaq`] for async callers, which must not block on the request.
+pub async fn get_quote_from_nasdaq_async(symbol: String, asset_class: &str) -> CustomResult {
+    quote_cache::quote_cache().get_async(&symbol, asset_class).await
+}
+
+/// Requests a quote from Nasdaq, bypassing the cache.
+pub fn fetch_quote_from_nasdaq(symbol: &str, asset_class: &str) -> Result<CustomResult, QuoteError> {
+    quote_cache::quote_cache().fetch(symbol, asset_class)
+}
+
+/// Reads a Nasdaq quote response; symbols Nasdaq doesn't know give an unsuccessful result.
+fn parse_quote(body: &str) -> Result<CustomResult, QuoteError> {
+    let object: Value = serde_json::from_str(body).map_err(|e| QuoteError::Parse(e.to_string()))?;
+    let stock_code: StockCode = serde_json::from_value(object.clone())
+        .map_err(|e| QuoteError::Parse(e.to_string()))?;
 
     if stock_code.status.rCode != 200 {
-        return CustomResult {
+        return Ok(CustomResult {
             stock: None,
             success: false,
             message: "Symbol not exists".to_string(),
-        };
+        });
     }
 
-    let object: Value = serde_json::from_str(body).unwrap();
-    let stock: Stock = serde_json::from_value(object).unwrap();
-    return CustomResult {
+    let stock: Stock = serde_json::from_value(object).map_err(|e| QuoteError::Parse(e.to_string()))?;
+    Ok(CustomResult {
         stock: Some(stock),
         success: true,
         message: "success!".to_string(),
-    };
+    })
 }
 
-pub fn send_message_to_consumer(symbol: String, shares: i32, action: String) {
-    #[allow(unused_assignments)]
-    let mut url_kafka = "".to_string();
-    match env::var("KAFKA_BROKER") {
-        Ok(stream) => {
-            url_kafka = format!("{}", stream);
-        }
-        Err(_e) => {
-            url_kafka = "localhost:9092".to_string();
-        }
-    };
-    let hosts = vec![url_kafka];
-    let mut producer =
-    Producer::from_hosts(hosts)
-        .create()
-        .unwrap();
-
-    let buf = format!("{},{},{}", symbol, shares, action);
-    producer.send(&Record::from_value("topic-stocks", buf.as_bytes())).unwrap();
-    println!("Symbol: {symbol}, Shares: {shares}");
+/// Queues an order of `user_id` for the consumer as `symbol,quantity,action,order_id` on the
+/// [`event_bus::event_bus`], keyed by [`order_key`]. The consumer re-reads the order row before
+/// executing it, so `quantity` (shares, or `$amount` for dollar orders) is informational only.
+pub fn send_message_to_consumer(symbol: String, quantity: String, action: String, order_id: i32, user_id: i32) {
+    let buf = format!("{},{},{},{}", symbol, quantity, action, order_id);
+    event_bus::event_bus()
+        .publish(event_bus::orders_topic(), Some(&order_key(user_id, &symbol)), buf.as_bytes())
+        .expect("Can't queue the order");
+    println!("Symbol: {symbol}, Quantity: {quantity}, Order: {order_id}");
+}
+
+/// Key of the orders of `user_id` in `symbol`, `user_id/symbol`. Orders with the same key touch
+/// the same position, so the consumer executes them one at a time, in the order they were queued.
+pub fn order_key(user_id: i32, symbol: &str) -> String {
+    format!("{}/{}", user_id, symbol)
 }
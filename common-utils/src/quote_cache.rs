@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use crate::quote_client::{quote_client, QuoteError};
+use crate::CustomResult;
+
+type Fetch = dyn Fn(&str, &str) -> Result<CustomResult, QuoteError> + Send + Sync;
+type AsyncFetch = dyn Fn(String, String) -> Pin<Box<dyn Future<Output = Result<CustomResult, QuoteError>> + Send>> + Send + Sync;
+
+/// In-memory cache of quotes keyed by asset class and symbol.
+///
+/// Quotes younger than `ttl` are served as they are. Quotes up to `stale_while_revalidate` past
+/// the TTL are still served while a background thread fetches a new one. Concurrent misses for
+/// the same key share a single fetch, whether they come from blocking or async callers. Failed
+/// fetches aren't cached, and quotes too old to be served are dropped whenever another is stored.
+#[derive(Clone)]
+pub struct QuoteCache {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    ttl: Duration,
+    stale_while_revalidate: Duration,
+    fetch: Box<Fetch>,
+    fetch_async: Box<AsyncFetch>,
+    entries: Mutex<HashMap<String, Entry>>,
+    loaded: Condvar,
+    loaded_async: Notify,
+}
+
+#[derive(Default)]
+struct Entry {
+    quote: Option<(CustomResult, Instant)>,
+    loading: bool,
+}
+
+/// What a lookup that can't be served from the cache has to do.
+enum Miss {
+    Load,
+    Wait,
+}
+
+impl QuoteCache {
+    pub fn new<F, Fut>(
+        ttl: Duration,
+        stale_while_revalidate: Duration,
+        fetch: impl Fn(&str, &str) -> Result<CustomResult, QuoteError> + Send + Sync + 'static,
+        fetch_async: F,
+    ) -> Self
+    where
+        F: Fn(String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<CustomResult, QuoteError>> + Send + 'static,
+    {
+        QuoteCache {
+            inner: Arc::new(Inner {
+                ttl,
+                stale_while_revalidate,
+                fetch: Box::new(fetch),
+                fetch_async: Box::new(move |symbol, asset_class| Box::pin(fetch_async(symbol, asset_class))),
+                entries: Mutex::new(HashMap::new()),
+                loaded: Condvar::new(),
+                loaded_async: Notify::new(),
+            }),
+        }
+    }
+
+    pub fn get(&self, symbol: &str, asset_class: &str) -> CustomResult {
+        let key = cache_key(symbol, asset_class);
+        let mut entries = self.inner.entries.lock().unwrap();
+        loop {
+            match self.lookup(&mut entries, &key, symbol, asset_class) {
+                Ok(quote) => return quote,
+                Err(Miss::Load) => {
+                    drop(entries);
+                    let load = Load { cache: self, key: &key };
+                    return load.finish((self.inner.fetch)(symbol, asset_class));
+                }
+                Err(Miss::Wait) => entries = self.inner.loaded.wait(entries).unwrap(),
+            }
+        }
+    }
+
+    /// Same as [`QuoteCache::get`] without blocking the async runtime while fetching or waiting.
+    pub async fn get_async(&self, symbol: &str, asset_class: &str) -> CustomResult {
+        let key = cache_key(symbol, asset_class);
+        loop {
+            let loaded = self.inner.loaded_async.notified();
+            let lookup = {
+                let mut entries = self.inner.entries.lock().unwrap();
+                self.lookup(&mut entries, &key, symbol, asset_class)
+            };
+            match lookup {
+                Ok(quote) => return quote,
+                Err(Miss::Load) => {
+                    let load = Load { cache: self, key: &key };
+                    let result = (self.inner.fetch_async)(symbol.to_string(), asset_class.to_string()).await;
+                    return load.finish(result);
+                }
+                Err(Miss::Wait) => loaded.await,
+            }
+        }
+    }
+
+    /// How many quotes are cached or being loaded.
+    pub fn len(&self) -> usize {
+        self.inner.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Requests a quote from the provider, neither reading nor updating the cache.
+    pub fn fetch(&self, symbol: &str, asset_class: &str) -> Result<CustomResult, QuoteError> {
+        (self.inner.fetch)(symbol, asset_class)
+    }
+
+    /// Serves the cached quote when it's usable, otherwise tells the caller to load it (marking it
+    /// as loading) or to wait for the caller already loading it.
+    fn lookup(
+        &self,
+        entries: &mut HashMap<String, Entry>,
+        key: &str,
+        symbol: &str,
+        asset_class: &str,
+    ) -> Result<CustomResult, Miss> {
+        let entry = entries.entry(key.to_string()).or_default();
+        if let Some((quote, fetched_at)) = &entry.quote {
+            let age = fetched_at.elapsed();
+            if age < self.inner.ttl {
+                return Ok(quote.clone());
+            }
+            if age < self.inner.ttl + self.inner.stale_while_revalidate {
+                let quote = quote.clone();
+                if !entry.loading {
+                    entry.loading = true;
+                    self.revalidate(key.to_string(), symbol, asset_class);
+                }
+                return Ok(quote);
+            }
+        }
+        if entry.loading {
+            return Err(Miss::Wait);
+        }
+        entry.loading = true;
+        Err(Miss::Load)
+    }
+
+    fn revalidate(&self, key: String, symbol: &str, asset_class: &str) {
+        let cache = self.clone();
+        let symbol = symbol.to_string();
+        let asset_class = asset_class.to_string();
+        thread::spawn(move || {
+            let load = Load { cache: &cache, key: &key };
+            load.finish((cache.inner.fetch)(&symbol, &asset_class));
+        });
+    }
+}
+
+/// A fetch in progress for `key`. Dropping it without finishing, because the fetch panicked or
+/// its future was cancelled, lets the next lookup load the quote instead of waiting forever. Keys
+/// left without a quote are forgotten.
+struct Load<'a> {
+    cache: &'a QuoteCache,
+    key: &'a str,
+}
+
+impl Load<'_> {
+    fn finish(self, result: Result<CustomResult, QuoteError>) -> CustomResult {
+        let quote = match result {
+            Ok(quote) => {
+                let mut entries = self.cache.inner.entries.lock().unwrap();
+                let max_age = self.cache.inner.ttl + self.cache.inner.stale_while_revalidate;
+                entries.retain(|_, entry| {
+                    entry.loading || entry.quote.as_ref().is_some_and(|(_, fetched_at)| fetched_at.elapsed() < max_age)
+                });
+                entries.entry(self.key.to_string()).or_default().quote = Some((quote.clone(), Instant::now()));
+                quote
+            }
+            Err(e) => CustomResult {
+                stock: None,
+                success: false,
+                message: e.to_string(),
+            },
+        };
+        drop(self);
+        quote
+    }
+}
+
+impl Drop for Load<'_> {
+    fn drop(&mut self) {
+        let mut entries = self.cache.inner.entries.lock().unwrap();
+        match entries.get_mut(self.key) {
+            Some(Entry { quote: Some(_), loading }) => *loading = false,
+            _ => {
+                entries.remove(self.key);
+            }
+        }
+        self.cache.inner.loaded.notify_all();
+        self.cache.inner.loaded_async.notify_waiters();
+    }
+}
+
+fn cache_key(symbol: &str, asset_class: &str) -> String {
+    format!("{}:{}", asset_class, symbol)
+}
+
+static QUOTE_CACHE: OnceLock<QuoteCache> = OnceLock::new();
+
+/// The process-wide cache in front of the [`quote_client`], configured with `QUOTE_CACHE_TTL_SECS`
+/// (5 by default) and `QUOTE_CACHE_STALE_SECS` (30 by default).
+pub fn quote_cache() -> &'static QuoteCache {
+    QUOTE_CACHE.get_or_init(|| {
+        QuoteCache::new(
+            Duration::from_secs(env_secs("QUOTE_CACHE_TTL_SECS", 5)),
+            Duration::from_secs(env_secs("QUOTE_CACHE_STALE_SECS", 30)),
+            |symbol, asset_class| quote_client().get_quote_blocking(symbol, asset_class),
+            |symbol, asset_class| async move { quote_client().get_quote(&symbol, &asset_class).await },
+        )
+    })
+}
+
+/// Makes `cache` the process-wide cache, e.g. one over a fake provider in tests. Fails, giving it
+/// back, once quotes have been requested.
+pub fn install_quote_cache(cache: QuoteCache) -> Result<(), QuoteCache> {
+    QUOTE_CACHE.set(cache)
+}
+
+fn env_secs(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|secs| secs.parse().ok())
+        .unwrap_or(default)
+}
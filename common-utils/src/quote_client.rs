@@ -0,0 +1,291 @@
+use std::env;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use curl::easy::Easy2;
+use rand::Rng;
+
+use crate::{parse_quote, Collector, CustomResult};
+
+#[derive(Debug)]
+pub enum QuoteError {
+    /// Too many recent failures; Nasdaq isn't called until the breaker half-opens.
+    CircuitOpen,
+    Timeout,
+    Transport(String),
+    Status(u32),
+    Parse(String),
+}
+
+impl QuoteError {
+    /// Whether another attempt could succeed.
+    fn is_transient(&self) -> bool {
+        match self {
+            QuoteError::Timeout | QuoteError::Transport(_) => true,
+            QuoteError::Status(status) => *status == 429 || *status >= 500,
+            QuoteError::CircuitOpen | QuoteError::Parse(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for QuoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuoteError::CircuitOpen => write!(f, "Quote provider unavailable, try again later"),
+            QuoteError::Timeout => write!(f, "Quote provider timed out"),
+            QuoteError::Transport(e) => write!(f, "Quote provider unreachable: {}", e),
+            QuoteError::Status(status) => write!(f, "Quote provider answered with status {}", status),
+            QuoteError::Parse(e) => write!(f, "Invalid quote: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for QuoteError {}
+
+#[derive(Clone, Debug)]
+pub struct QuoteClientConfig {
+    /// Where the Nasdaq quote API is served, without a trailing slash.
+    pub base_url: String,
+    pub connect_timeout: Duration,
+    /// Limit on the whole request once connected.
+    pub request_timeout: Duration,
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Consecutive failed requests that open the circuit. A request only counts as failed once
+    /// its last attempt has.
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects requests before letting one through.
+    pub open_duration: Duration,
+}
+
+impl QuoteClientConfig {
+    /// Settings from the `QUOTE_*` environment variables, with defaults suited to Nasdaq.
+    pub fn from_env() -> Self {
+        QuoteClientConfig {
+            base_url: env::var("QUOTE_BASE_URL").unwrap_or_else(|_| "https://api.nasdaq.com".to_string()),
+            connect_timeout: Duration::from_millis(env_number("QUOTE_CONNECT_TIMEOUT_MS", 2_000)),
+            request_timeout: Duration::from_millis(env_number("QUOTE_REQUEST_TIMEOUT_MS", 5_000)),
+            max_attempts: env_number("QUOTE_MAX_ATTEMPTS", 3) as u32,
+            initial_backoff: Duration::from_millis(env_number("QUOTE_INITIAL_BACKOFF_MS", 200)),
+            max_backoff: Duration::from_millis(env_number("QUOTE_MAX_BACKOFF_MS", 2_000)),
+            failure_threshold: env_number("QUOTE_CIRCUIT_FAILURE_THRESHOLD", 5) as u32,
+            open_duration: Duration::from_millis(env_number("QUOTE_CIRCUIT_OPEN_MS", 30_000)),
+        }
+    }
+
+    /// Delay before retry number `attempt` (1 for the first retry): exponential growth capped at
+    /// `max_backoff`, with full jitter so that clients don't retry in lockstep.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponential = self.initial_backoff.saturating_mul(2u32.saturating_pow(attempt - 1));
+        let capped = exponential.min(self.max_backoff);
+        capped.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+fn env_number(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+enum CircuitState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    /// A trial request is in flight after the open period ended. Another one is let through
+    /// at `until` in case it never reports back.
+    HalfOpen { until: Instant },
+}
+
+struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        CircuitBreaker {
+            failure_threshold,
+            open_duration,
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    fn allow_request(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } => true,
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if Instant::now() >= until => {
+                *state = CircuitState::HalfOpen { until: Instant::now() + self.open_duration };
+                true
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => false,
+        }
+    }
+
+    fn record_success(&self) {
+        *self.state.lock().unwrap() = CircuitState::Closed { failures: 0 };
+    }
+
+    fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            CircuitState::Closed { failures } => failures + 1,
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => self.failure_threshold,
+        };
+        *state = if failures >= self.failure_threshold {
+            CircuitState::Open { until: Instant::now() + self.open_duration }
+        } else {
+            CircuitState::Closed { failures }
+        };
+    }
+}
+
+/// Nasdaq quote client with timeouts, retries with backoff and a circuit breaker shared by its
+/// async and blocking requests.
+pub struct QuoteClient {
+    config: QuoteClientConfig,
+    http: reqwest::Client,
+    breaker: CircuitBreaker,
+}
+
+impl QuoteClient {
+    pub fn new(config: QuoteClientConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .timeout(config.request_timeout)
+            .build()
+            .expect("Can't build the quote HTTP client");
+        QuoteClient {
+            breaker: CircuitBreaker::new(config.failure_threshold, config.open_duration),
+            config,
+            http,
+        }
+    }
+
+    pub async fn get_quote(&self, symbol: &str, asset_class: &str) -> Result<CustomResult, QuoteError> {
+        let url = self.quote_url(symbol, asset_class)?;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            self.begin_request()?;
+            let result = self.request(&url).await;
+            match self.retry_delay(&result, attempt) {
+                Some(backoff) => tokio::time::sleep(backoff).await,
+                None => return result,
+            }
+        }
+    }
+
+    /// Same as [`QuoteClient::get_quote`] for callers outside an async runtime.
+    pub fn get_quote_blocking(&self, symbol: &str, asset_class: &str) -> Result<CustomResult, QuoteError> {
+        let url = self.quote_url(symbol, asset_class)?;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            self.begin_request()?;
+            let result = self.request_blocking(&url);
+            match self.retry_delay(&result, attempt) {
+                Some(backoff) => thread::sleep(backoff),
+                None => return result,
+            }
+        }
+    }
+
+    fn begin_request(&self) -> Result<(), QuoteError> {
+        if self.breaker.allow_request() {
+            Ok(())
+        } else {
+            Err(QuoteError::CircuitOpen)
+        }
+    }
+
+    /// Returns how long to wait before the next attempt when it's worth retrying. Otherwise the
+    /// request is over and its outcome is recorded in the circuit breaker.
+    fn retry_delay(&self, result: &Result<CustomResult, QuoteError>, attempt: u32) -> Option<Duration> {
+        match result {
+            Ok(_) => {
+                self.breaker.record_success();
+                None
+            }
+            Err(e) => {
+                if !e.is_transient() || attempt >= self.config.max_attempts {
+                    self.breaker.record_failure();
+                    return None;
+                }
+                println!("Quote request failed ({}), retrying", e);
+                Some(self.config.backoff(attempt))
+            }
+        }
+    }
+
+    /// The quote URL of `symbol`, with it and `asset_class` percent-encoded so that neither can
+    /// change the path or add query parameters.
+    fn quote_url(&self, symbol: &str, asset_class: &str) -> Result<String, QuoteError> {
+        let invalid_base_url = |e: String| QuoteError::Transport(format!("Invalid QUOTE_BASE_URL: {}", e));
+        let mut url = reqwest::Url::parse(&self.config.base_url).map_err(|e| invalid_base_url(e.to_string()))?;
+        url.path_segments_mut()
+            .map_err(|_e| invalid_base_url(self.config.base_url.to_string()))?
+            .pop_if_empty()
+            .extend(["api", "quote", symbol, "info"]);
+        url.query_pairs_mut().append_pair("assetclass", asset_class);
+        Ok(url.into())
+    }
+
+    async fn request(&self, url: &str) -> Result<CustomResult, QuoteError> {
+        let response = self.http
+            .get(url)
+            .send()
+            .await
+            .map_err(request_error)?;
+        let status = response.status().as_u16();
+        if status != 200 {
+            return Err(QuoteError::Status(u32::from(status)));
+        }
+        let body = response.text().await.map_err(request_error)?;
+        parse_quote(&body)
+    }
+
+    fn request_blocking(&self, url: &str) -> Result<CustomResult, QuoteError> {
+        let mut easy = Easy2::new(Collector(Vec::new()));
+        let transport_error = |e: curl::Error| {
+            if e.is_operation_timedout() {
+                QuoteError::Timeout
+            } else {
+                QuoteError::Transport(e.to_string())
+            }
+        };
+        easy.get(true).map_err(transport_error)?;
+        easy.url(url).map_err(transport_error)?;
+        easy.connect_timeout(self.config.connect_timeout).map_err(transport_error)?;
+        easy.timeout(self.config.request_timeout).map_err(transport_error)?;
+        easy.perform().map_err(transport_error)?;
+        let status = easy.response_code().map_err(transport_error)?;
+        if status != 200 {
+            return Err(QuoteError::Status(status));
+        }
+        let body = std::str::from_utf8(&easy.get_ref().0)
+            .map_err(|e| QuoteError::Parse(e.to_string()))?;
+        parse_quote(body)
+    }
+}
+
+fn request_error(e: reqwest::Error) -> QuoteError {
+    if e.is_timeout() {
+        QuoteError::Timeout
+    } else {
+        QuoteError::Transport(e.to_string())
+    }
+}
+
+
+static QUOTE_CLIENT: OnceLock<QuoteClient> = OnceLock::new();
+
+/// The process-wide client configured with [`QuoteClientConfig::from_env`].
+pub fn quote_client() -> &'static QuoteClient {
+    QUOTE_CLIENT.get_or_init(|| QuoteClient::new(QuoteClientConfig::from_env()))
+}
@@ -0,0 +1,86 @@
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+
+use crate::event_bus::{event_bus, trade_events_topic};
+use crate::order_key;
+
+/// An order as it was when the event about it was published.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct OrderSnapshot {
+    pub id: i32,
+    pub user_id: i32,
+    pub symbol: String,
+    pub shares: Option<BigDecimal>,
+    pub amount: Option<BigDecimal>,
+    pub action_type: String,
+    pub order_type: String,
+    pub stop_price: Option<String>,
+    pub limit_price: Option<String>,
+    pub status: String,
+    pub parent_id: Option<i32>,
+}
+
+/// A recorded trade.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TradeSnapshot {
+    pub id: i32,
+    pub user_id: i32,
+    pub symbol: String,
+    pub shares: BigDecimal,
+    pub price: String,
+    pub percentage_change: String,
+    pub action_type: String,
+    pub fee: BigDecimal,
+    pub currency: String,
+}
+
+/// What happens to the orders, trades and portfolio of a user, published on the trade events
+/// topic once it's saved.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TradeEvent {
+    /// An order was placed, amended or moved to another status.
+    OrderUpdated { order: OrderSnapshot },
+    TradeExecuted { trade: TradeSnapshot },
+    /// The position of the user in `symbol` changed, or their cash when there's no symbol.
+    PortfolioChanged { user_id: i32, symbol: Option<String> },
+}
+
+impl TradeEvent {
+    pub fn user_id(&self) -> i32 {
+        match self {
+            TradeEvent::OrderUpdated { order } => order.user_id,
+            TradeEvent::TradeExecuted { trade } => trade.user_id,
+            TradeEvent::PortfolioChanged { user_id, .. } => *user_id,
+        }
+    }
+
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            TradeEvent::OrderUpdated { order } => Some(&order.symbol),
+            TradeEvent::TradeExecuted { trade } => Some(&trade.symbol),
+            TradeEvent::PortfolioChanged { symbol, .. } => symbol.as_deref(),
+        }
+    }
+
+    /// Whether the event is about `user_id` and, when one is given, `symbol`.
+    pub fn concerns(&self, user_id: i32, symbol: Option<&str>) -> bool {
+        self.user_id() == user_id && symbol.is_none_or(|symbol| self.symbol() == Some(symbol))
+    }
+}
+
+/// Publishes `event` on the [`event_bus`], keyed like the orders of its user and symbol so that
+/// the events of a position are read in order. The change is already saved by then, so failures
+/// are logged rather than returned.
+pub fn publish_trade_event(event: &TradeEvent) {
+    let key = match event.symbol() {
+        Some(symbol) => order_key(event.user_id(), symbol),
+        None => event.user_id().to_string(),
+    };
+    let published = serde_json::to_vec(event)
+        .map_err(|e| e.to_string())
+        .and_then(|payload| event_bus().publish(trade_events_topic(), Some(&key), &payload));
+    if let Err(e) = published {
+        println!("Can't publish {:?}: {}", event, e);
+    }
+}
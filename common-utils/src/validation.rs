@@ -0,0 +1,237 @@
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use serde::Serialize;
+
+/// Longest symbol accepted, enough for OCC option symbols without spaces.
+pub const MAX_SYMBOL_LENGTH: usize = 21;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidationErrorCode {
+    InvalidSymbol,
+    InvalidPrice,
+    NotPositive,
+    TooLarge,
+}
+
+impl ValidationErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidationErrorCode::InvalidSymbol => "invalid_symbol",
+            ValidationErrorCode::InvalidPrice => "invalid_price",
+            ValidationErrorCode::NotPositive => "not_positive",
+            ValidationErrorCode::TooLarge => "too_large",
+        }
+    }
+}
+
+/// Why an input was rejected, naming the field so that clients can point at it.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct ValidationError {
+    pub field: String,
+    pub code: ValidationErrorCode,
+    pub message: String,
+}
+
+impl ValidationError {
+    fn new(field: &str, code: ValidationErrorCode, message: String) -> Self {
+        ValidationError {
+            field: field.to_string(),
+            code,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Trims and upper-cases `symbol`, then checks it's 1 to [`MAX_SYMBOL_LENGTH`] ASCII letters,
+/// digits, dots or dashes starting with a letter or digit.
+pub fn normalize_symbol(symbol: &str) -> Result<String, ValidationError> {
+    let symbol = symbol.trim().to_ascii_uppercase();
+    let valid = symbol.len() <= MAX_SYMBOL_LENGTH
+        && symbol.starts_with(|c: char| c.is_ascii_alphanumeric())
+        && symbol.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !valid {
+        return Err(ValidationError::new(
+            "symbol",
+            ValidationErrorCode::InvalidSymbol,
+            format!(
+                "symbol must be 1 to {} letters, digits, dots or dashes, got {:?}",
+                MAX_SYMBOL_LENGTH,
+                symbol.chars().take(MAX_SYMBOL_LENGTH * 2).collect::<String>()
+            ),
+        ));
+    }
+    Ok(symbol)
+}
+
+/// Parses the stop or limit price of an order, which must be a positive decimal.
+pub fn parse_price(field: &str, price: &str) -> Result<BigDecimal, ValidationError> {
+    let parsed = BigDecimal::from_str(price.trim()).map_err(|_e| {
+        ValidationError::new(field, ValidationErrorCode::InvalidPrice, format!("{} is not a valid price", field))
+    })?;
+    if parsed <= BigDecimal::from(0) {
+        return Err(ValidationError::new(field, ValidationErrorCode::NotPositive, format!("{} must be positive", field)));
+    }
+    Ok(parsed)
+}
+
+/// Bounds on the size of a single order.
+#[derive(Clone, Debug)]
+pub struct OrderLimits {
+    pub max_shares: BigDecimal,
+    /// Largest value of an order, in the currency of the instrument.
+    pub max_notional: BigDecimal,
+}
+
+impl OrderLimits {
+    /// Limits from `ORDER_MAX_SHARES` and `ORDER_MAX_NOTIONAL`, a million each by default.
+    pub fn from_env() -> Self {
+        OrderLimits {
+            max_shares: env_decimal("ORDER_MAX_SHARES", 1_000_000),
+            max_notional: env_decimal("ORDER_MAX_NOTIONAL", 1_000_000),
+        }
+    }
+
+    pub fn check_shares(&self, field: &str, shares: &BigDecimal) -> Result<(), ValidationError> {
+        check_bounded(field, shares, &self.max_shares)
+    }
+
+    /// Checks a dollar amount to spend.
+    pub fn check_amount(&self, field: &str, amount: &BigDecimal) -> Result<(), ValidationError> {
+        check_bounded(field, amount, &self.max_notional)
+    }
+
+    /// Checks the value of an order once its price is known.
+    pub fn check_notional(&self, notional: &BigDecimal) -> Result<(), ValidationError> {
+        if notional > &self.max_notional {
+            return Err(ValidationError::new(
+                "notional",
+                ValidationErrorCode::TooLarge,
+                format!("order value {} exceeds the limit of {}", notional, self.max_notional),
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn check_bounded(field: &str, value: &BigDecimal, max: &BigDecimal) -> Result<(), ValidationError> {
+    if value <= &BigDecimal::from(0) {
+        return Err(ValidationError::new(field, ValidationErrorCode::NotPositive, format!("{} must be positive", field)));
+    }
+    if value > max {
+        return Err(ValidationError::new(
+            field,
+            ValidationErrorCode::TooLarge,
+            format!("{} can't be greater than {}", field, max),
+        ));
+    }
+    Ok(())
+}
+
+fn env_decimal(name: &str, default: u32) -> BigDecimal {
+    env::var(name)
+        .ok()
+        .and_then(|value| BigDecimal::from_str(&value).ok())
+        .unwrap_or_else(|| BigDecimal::from(default))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn limits() -> OrderLimits {
+        OrderLimits { max_shares: decimal("1000"), max_notional: decimal("50000") }
+    }
+
+    #[test]
+    fn symbols_are_trimmed_and_upper_cased() {
+        assert_eq!("AAPL", normalize_symbol(" aapl ").unwrap());
+        assert_eq!("BRK.B", normalize_symbol("brk.b").unwrap());
+        assert_eq!("BTC-USD", normalize_symbol("BTC-USD").unwrap());
+        assert_eq!("AAPL240119C00150000", normalize_symbol("AAPL240119C00150000").unwrap());
+    }
+
+    #[test]
+    fn symbols_are_limited_in_length() {
+        assert_eq!("A", normalize_symbol("a").unwrap());
+        let longest = "A".repeat(MAX_SYMBOL_LENGTH);
+        assert_eq!(longest, normalize_symbol(&longest).unwrap());
+        let error = normalize_symbol(&"A".repeat(MAX_SYMBOL_LENGTH + 1)).unwrap_err();
+        assert_eq!(("symbol", ValidationErrorCode::InvalidSymbol), (error.field.as_str(), error.code));
+    }
+
+    #[test]
+    fn symbols_with_other_characters_are_rejected() {
+        for symbol in ["", "   ", ".AAPL", "-AAPL", "AA PL", "AAPL'", "AAPL;--", "ÄAPL", "AAPL%"] {
+            let error = normalize_symbol(symbol).unwrap_err();
+            assert_eq!(ValidationErrorCode::InvalidSymbol, error.code, "{:?}", symbol);
+        }
+    }
+
+    #[test]
+    fn prices_are_positive_decimals() {
+        assert_eq!(decimal("101.25"), parse_price("limitPrice", " 101.25 ").unwrap());
+        assert_eq!(decimal("0.0001"), parse_price("limitPrice", "0.0001").unwrap());
+        assert_eq!(decimal("30"), parse_price("stopPrice", "30").unwrap());
+    }
+
+    #[test]
+    fn invalid_prices_are_rejected_naming_the_field() {
+        for price in ["", "lots", "1e", "$30", "30.00.1"] {
+            let error = parse_price("stopPrice", price).unwrap_err();
+            assert_eq!(("stopPrice", ValidationErrorCode::InvalidPrice), (error.field.as_str(), error.code), "{:?}", price);
+        }
+        for price in ["0", "0.00", "-5.00"] {
+            let error = parse_price("limitPrice", price).unwrap_err();
+            assert_eq!(("limitPrice", ValidationErrorCode::NotPositive), (error.field.as_str(), error.code), "{:?}", price);
+        }
+    }
+
+    #[test]
+    fn shares_and_amounts_are_positive_up_to_their_limit() {
+        let limits = limits();
+        assert!(limits.check_shares("shares", &decimal("0.0001")).is_ok());
+        assert!(limits.check_shares("shares", &decimal("1000")).is_ok());
+        assert!(limits.check_amount("amount", &decimal("50000")).is_ok());
+
+        assert_eq!(ValidationErrorCode::NotPositive, limits.check_shares("shares", &decimal("0")).unwrap_err().code);
+        assert_eq!(ValidationErrorCode::NotPositive, limits.check_amount("amount", &decimal("-1")).unwrap_err().code);
+        let error = limits.check_shares("shares", &decimal("1000.0001")).unwrap_err();
+        assert_eq!(("shares", ValidationErrorCode::TooLarge), (error.field.as_str(), error.code));
+        let error = limits.check_amount("amount", &decimal("50000.01")).unwrap_err();
+        assert_eq!(("amount", ValidationErrorCode::TooLarge), (error.field.as_str(), error.code));
+    }
+
+    #[test]
+    fn notionals_are_limited() {
+        let limits = limits();
+        assert!(limits.check_notional(&decimal("50000")).is_ok());
+        let error = limits.check_notional(&decimal("50000.01")).unwrap_err();
+        assert_eq!(("notional", ValidationErrorCode::TooLarge), (error.field.as_str(), error.code));
+    }
+
+    #[test]
+    fn errors_serialize_with_snake_case_codes() {
+        let error = parse_price("limitPrice", "0").unwrap_err();
+        assert_eq!("not_positive", error.code.as_str());
+        let json = serde_json::to_value(&error).unwrap();
+        assert_eq!("not_positive", json["code"]);
+        assert_eq!("limitPrice", json["field"]);
+        assert_eq!("limitPrice must be positive", error.to_string());
+    }
+}
@@ -0,0 +1,51 @@
+//! Inspects and re-drives the orders the consumer gave up on.
+//!
+//! ```text
+//! dlq [list]   print every dead-lettered order with the error it failed with
+//! dlq redrive  queue the orders not re-driven yet again, for the consumer to retry
+//! ```
+
+use std::env;
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use common_utils::event_bus::event_bus;
+use consumer_stocks_service::dead_letter::{read_dead_letters, redrive_dead_letters};
+use dotenv::dotenv;
+
+fn main() {
+    dotenv().ok();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = match args.as_slice() {
+        [] | ["list"] => list(),
+        ["redrive"] => redrive_dead_letters(event_bus())
+            .map(|redriven| println!("Re-drove {} orders", redriven)),
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("Dead-letter command failed: {}", e);
+        process::exit(1);
+    }
+}
+
+fn list() -> Result<(), String> {
+    // A group of its own reads the whole topic without moving the offsets of other groups.
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let group = format!("consumer-stocks-service-inspect-{}", started);
+    for dead_letter in read_dead_letters(event_bus(), &group)? {
+        match dead_letter {
+            Ok(dead_letter) => println!(
+                "{} {} after {} attempts: {:?} ({})",
+                dead_letter.failed_at, dead_letter.topic, dead_letter.attempts, dead_letter.payload, dead_letter.error
+            ),
+            Err(message) => println!("invalid dead letter: {}", message),
+        }
+    }
+    Ok(())
+}
+
+fn usage() -> Result<(), String> {
+    eprintln!("Usage: dlq [list | redrive]");
+    process::exit(2);
+}
@@ -0,0 +1,158 @@
+use std::env;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread;
+use std::time::Duration;
+
+use chrono::Utc;
+use common_utils::event_bus::{dead_letter_topic, orders_topic, Commit, Event, EventBus, StartFrom, Subscriber};
+use serde::{Deserialize, Serialize};
+
+use crate::persistence::connection::PgPool;
+use crate::process_order_message;
+use crate::stock_functions::TradeError;
+
+/// Consumer group that re-drives dead letters, so each one is only re-driven once.
+pub const REDRIVE_GROUP: &str = "consumer-stocks-service-redrive";
+
+/// Empty polls in a row after which a dead-letter topic is considered fully read. The first polls
+/// of a new Kafka consumer can come back empty while it joins its group.
+const IDLE_POLLS: usize = 3;
+
+/// How many times an order is attempted, and how long to wait between attempts.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Policy from `ORDER_MAX_ATTEMPTS` (3 by default), `ORDER_RETRY_INITIAL_BACKOFF_MS` (500) and
+    /// `ORDER_RETRY_MAX_BACKOFF_MS` (10000).
+    pub fn from_env() -> Self {
+        RetryPolicy {
+            max_attempts: env_number("ORDER_MAX_ATTEMPTS", 3).max(1) as u32,
+            initial_backoff: Duration::from_millis(env_number("ORDER_RETRY_INITIAL_BACKOFF_MS", 500)),
+            max_backoff: Duration::from_millis(env_number("ORDER_RETRY_MAX_BACKOFF_MS", 10_000)),
+        }
+    }
+
+    /// Wait after the failed `attempt`, doubling from `initial_backoff` up to `max_backoff`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_backoff)
+    }
+}
+
+fn env_number(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// An order message the consumer gave up on, as published on the dead-letter topic.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// Topic the message was read from, and is re-driven to.
+    pub topic: String,
+    /// Key the message was published with, and is re-driven with.
+    #[serde(default)]
+    pub key: Option<String>,
+    pub payload: String,
+    /// Why the last attempt failed.
+    pub error: String,
+    pub attempts: u32,
+    /// RFC 3339 time of the last attempt.
+    pub failed_at: String,
+}
+
+/// Processes an order read from the orders topic, retrying it as `policy` says while the
+/// database or Nasdaq is unavailable, then moves the order to the dead-letter topic of `bus`.
+/// Orders that can't be processed as they are, or whose processing panicked, are dead-lettered
+/// without retrying. Fails when the order could be neither processed nor dead-lettered.
+pub fn process_with_retries(bus: &dyn EventBus, event: &Event, policy: &RetryPolicy, pool: &PgPool) -> Result<(), String> {
+    let mut attempt = 1;
+    loop {
+        let error = match panic::catch_unwind(AssertUnwindSafe(|| process_order_message(&event.payload, pool))) {
+            Ok(Ok(())) => return Ok(()),
+            Ok(Err(TradeError::Unavailable(e))) if attempt < policy.max_attempts => {
+                println!("Attempt {} of {} failed: {}", attempt, policy.max_attempts, e);
+                thread::sleep(policy.backoff(attempt));
+                attempt += 1;
+                continue;
+            }
+            Ok(Err(e)) => e.to_string(),
+            Err(panic) => panic_message(panic),
+        };
+        return dead_letter(bus, event, error, attempt);
+    }
+}
+
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    let message = panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string());
+    format!("panicked: {}", message)
+}
+
+fn dead_letter(bus: &dyn EventBus, event: &Event, error: String, attempts: u32) -> Result<(), String> {
+    let dead_letter = DeadLetter {
+        topic: orders_topic().to_string(),
+        key: event.key.clone(),
+        payload: String::from_utf8_lossy(&event.payload).to_string(),
+        error,
+        attempts,
+        failed_at: Utc::now().to_rfc3339(),
+    };
+    println!("Giving up on {:?} after {} attempts: {}", dead_letter.payload, attempts, dead_letter.error);
+    serde_json::to_vec(&dead_letter)
+        .map_err(|e| e.to_string())
+        .and_then(|message| bus.publish(dead_letter_topic(), event.key.as_deref(), &message))
+        .map_err(|e| format!("Can't dead-letter {:?}: {}", dead_letter.payload, e))
+}
+
+/// Every message on the dead-letter topic, read as `group` from the start of the topic.
+/// Messages that aren't dead letters are returned as errors.
+pub fn read_dead_letters(bus: &dyn EventBus, group: &str) -> Result<Vec<Result<DeadLetter, String>>, String> {
+    let mut subscriber = bus.subscribe(dead_letter_topic(), group, StartFrom::Earliest, Commit::OnPoll)?;
+    Ok(drain(subscriber.as_mut())?.iter().map(parse_dead_letter).collect())
+}
+
+/// Publishes the dead letters not re-driven yet back to their topic, and returns how many were.
+/// Each one is only committed as re-driven once the brokers have it, so a failure part way
+/// leaves the rest to the next run.
+pub fn redrive_dead_letters(bus: &dyn EventBus) -> Result<usize, String> {
+    let mut subscriber = bus.subscribe(dead_letter_topic(), REDRIVE_GROUP, StartFrom::Earliest, Commit::Manual)?;
+    let mut redriven = 0;
+    for event in drain(subscriber.as_mut())? {
+        match parse_dead_letter(&event) {
+            Ok(dead_letter) => {
+                bus.publish(&dead_letter.topic, dead_letter.key.as_deref(), dead_letter.payload.as_bytes())?;
+                bus.flush(Duration::from_secs(10))?;
+                redriven += 1;
+            }
+            Err(message) => println!("Skipping invalid dead letter: {}", message),
+        }
+        subscriber.commit(event.partition, event.offset + 1)?;
+    }
+    Ok(redriven)
+}
+
+fn parse_dead_letter(event: &Event) -> Result<DeadLetter, String> {
+    serde_json::from_slice(&event.payload).map_err(|_e| String::from_utf8_lossy(&event.payload).to_string())
+}
+
+fn drain(subscriber: &mut dyn Subscriber) -> Result<Vec<Event>, String> {
+    let mut messages = Vec::new();
+    let mut idle_polls = 0;
+    while idle_polls < IDLE_POLLS {
+        let events = subscriber.poll()?;
+        idle_polls = if events.is_empty() { idle_polls + 1 } else { 0 };
+        messages.extend(events);
+    }
+    Ok(messages)
+}
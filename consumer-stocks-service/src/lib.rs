@@ -1,35 +1,182 @@
-use crate::stock_functions::{save_stock, calculate_stock_summary};
-pub mod stock_functions;
-pub mod persistence;
-
-pub fn buy_stocks(symbol: String, shares: String, action: String) {
-    let result = common_utils::get_stock_from_nasdaq(symbol.to_string());
-    if result.success == false {
-        return;
+use std::env;
+use std::str;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bigdecimal::BigDecimal;
+use common_utils::event_bus::{orders_topic, Commit, EventBus, StartFrom, Subscriber};
+use common_utils::validation::{normalize_symbol, OrderLimits};
+use crate::stock_functions::{
+    save_stock, get_quote, lookup_instrument, trade_notional,
+    evaluate_pending_orders, execute_queued_order, refresh_stale_instruments, sample_prices, OrderAction, TradeError,
+};
+use crate::dead_letter::RetryPolicy;
+use crate::persistence::connection::PgPool;
+use crate::processor::OrderProcessor;
+use signal_hook::consts::{SIGINT, SIGTERM};
+pub use stocks_domain::{check_schema_version, persistence, stock_functions};
+use tokio::task;
+
+pub mod dead_letter;
+pub mod processor;
+
+/// Consumer group shared by every consumer of the queued orders.
+pub const CONSUMER_GROUP: &str = "consumer-stocks-service";
+
+/// How long to wait before polling again when polling the orders failed.
+const POLL_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Executes the orders queued on `bus` and runs the periodic jobs until SIGINT or SIGTERM. Up to
+/// `ORDER_CONCURRENCY` orders (8 by default) are executed at once, in order per key, sharing the
+/// connections of `pool`, and orders that keep failing are moved to the dead-letter topic.
+///
+/// Offsets are committed as orders are handled, so on a signal, polling stops, the orders being
+/// executed finish and exactly their offsets are committed. The orders read and not started are
+/// read again by the next consumer. The consumer stops the same way, and fails, when an order
+/// can be neither executed nor dead-lettered.
+pub fn run_consumer(bus: &'static dyn EventBus, pool: PgPool) -> Result<(), String> {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("Can't start the consumer runtime")
+        .block_on(consume(bus, pool))
+}
+
+async fn consume(bus: &'static dyn EventBus, pool: PgPool) -> Result<(), String> {
+    let concurrency = env::var("ORDER_CONCURRENCY")
+        .ok()
+        .and_then(|concurrency| concurrency.parse().ok())
+        .unwrap_or(8);
+    let processor = OrderProcessor::new(bus, pool.clone(), RetryPolicy::from_env(), concurrency);
+    let stopping = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register(signal, stopping.clone()).expect("Can't listen for termination signals");
     }
-    let stock_from_nasdaq = result.stock.unwrap();
-    let stock_data = stock_from_nasdaq.data;
-    let last_sale_price = stock_data.primaryData.lastSalePrice.replace("$", "");
-    let bid_price = stock_data.primaryData.bidPrice.replace("$", "");
-    let price = if bid_price != "N/A" {
-        bid_price
+    let mut orders = bus
+        .subscribe(orders_topic(), CONSUMER_GROUP, StartFrom::Earliest, Commit::Manual)
+        .expect("Can't subscribe to the orders");
+    let orders_evaluation_interval = env_interval("ORDERS_EVALUATION_INTERVAL_SECS", 30);
+    let mut last_orders_evaluation = Instant::now();
+    let instruments_refresh_interval = env_interval("INSTRUMENTS_REFRESH_INTERVAL_SECS", 24 * 60 * 60);
+    let mut last_instruments_refresh = Instant::now();
+    let price_sampling_interval = env_interval("PRICE_SAMPLING_INTERVAL_SECS", 60);
+    let mut last_price_sampling = Instant::now();
+    while !stopping.load(Ordering::SeqCst) && processor.failure().is_none() {
+        // Polling, committing and the periodic jobs block, while dispatched orders keep executing.
+        let events = task::block_in_place(|| {
+            orders.poll().unwrap_or_else(|e| {
+                // The brokers may be unreachable for a while, so back off and poll again.
+                println!("Can't poll the orders: {}", e);
+                thread::sleep(POLL_BACKOFF);
+                Vec::new()
+            })
+        });
+        for event in events {
+            processor.dispatch(event).await;
+        }
+        commit_handled(&processor, orders.as_mut());
+        task::block_in_place(|| {
+            // Jobs that fail, e.g. while the database is down, run again at their next interval.
+            if last_orders_evaluation.elapsed() >= orders_evaluation_interval {
+                if let Err(e) = evaluate_pending_orders(&pool) {
+                    println!("Can't evaluate the pending orders: {}", e);
+                }
+                last_orders_evaluation = Instant::now();
+            }
+            if last_instruments_refresh.elapsed() >= instruments_refresh_interval {
+                let max_age = chrono::Duration::from_std(instruments_refresh_interval).unwrap_or(chrono::Duration::days(1));
+                if let Err(e) = refresh_stale_instruments(max_age, &pool) {
+                    println!("Can't refresh the instruments: {}", e);
+                }
+                last_instruments_refresh = Instant::now();
+            }
+            if last_price_sampling.elapsed() >= price_sampling_interval {
+                if let Err(e) = sample_prices(&pool) {
+                    println!("Can't sample prices: {}", e);
+                }
+                last_price_sampling = Instant::now();
+            }
+        });
+    }
+    println!("Stopping, finishing the orders being executed");
+    processor.stop();
+    processor.idle().await;
+    commit_handled(&processor, orders.as_mut());
+    processor.failure().map_or(Ok(()), Err)
+}
+
+fn commit_handled(processor: &OrderProcessor, orders: &mut dyn Subscriber) {
+    task::block_in_place(|| {
+        for (partition, next_offset) in processor.take_commits() {
+            if let Err(e) = orders.commit(partition, next_offset) {
+                println!("Can't commit offset {} of partition {}: {}", next_offset, partition, e);
+            }
+        }
+    });
+}
+
+/// Handles one queued order: `symbol,quantity,action,order_id` executes the order row, while the
+/// older `symbol,shares,action` trades straight away. Only [`TradeError::Unavailable`] failures
+/// are worth retrying.
+pub fn process_order_message(payload: &[u8], pool: &PgPool) -> Result<(), TradeError> {
+    let message = str::from_utf8(payload)
+        .map_err(|_e| TradeError::Rejected(format!("Invalid message: {:?}", payload)))?;
+    let collection = message.split(",").collect::<Vec<&str>>();
+    if collection.len() == 4 {
+        match collection[3].parse::<i32>() {
+            Ok(order_id) => execute_queued_order(order_id, pool),
+            Err(_e) => Err(TradeError::Rejected(format!("Invalid order id: {}", collection[3]))),
+        }
+    } else if collection.len() == 3 {
+        let symbol = collection[0];
+        let shares = collection[1];
+        let action = collection[2];
+        buy_stocks(symbol.to_string(), shares.to_string(), action.to_string(), pool)
     } else {
-        last_sale_price
+        Err(TradeError::Rejected(format!("Invalid message: {}", message)))
+    }
+}
+
+fn env_interval(name: &str, default_secs: u64) -> Duration {
+    Duration::from_secs(
+        env::var(name)
+            .ok()
+            .and_then(|secs| secs.parse().ok())
+            .unwrap_or(default_secs)
+    )
+}
+
+pub fn buy_stocks(symbol: String, shares: String, action: String, pool: &PgPool) -> Result<(), TradeError> {
+    let symbol = normalize_symbol(&symbol).map_err(|e| TradeError::Rejected(format!("Invalid order: {}", e)))?;
+    OrderAction::parse(&action)?;
+    let Ok(shares) = shares.parse::<BigDecimal>() else {
+        return Err(TradeError::Rejected(format!("Invalid shares: {}", shares)));
+    };
+    let limits = OrderLimits::from_env();
+    limits.check_shares("shares", &shares)
+        .map_err(|e| TradeError::Rejected(format!("Invalid order for {}: {}", symbol, e)))?;
+    let instrument = lookup_instrument(&symbol, pool).map_err(|e| cant_trade(&symbol, e))?;
+    let result = get_quote(&symbol, pool)?;
+    let Some(stock_from_nasdaq) = result.stock else {
+        return Err(TradeError::Unavailable(format!("Can't get a quote for {}: {}", symbol, result.message)));
     };
-    let percentage_change = stock_data.primaryData.percentageChange
-        .replace("%", "")
-        .replace("+", "");
-    save_stock(
-        symbol.to_string(), 
-        shares.parse::<i32>().unwrap(), 
-        price.to_string(), 
-        percentage_change.to_string(),
-        action.to_string());
-    calculate_stock_summary(
-        symbol.to_string(),
-        shares.parse::<i32>().unwrap(),
-        price.to_string(),
-        percentage_change.to_string()
-    );
+    let stock_data = stock_from_nasdaq.data;
+    let price = stock_data.price();
+    let percentage_change = stock_data.percentage_change();
+    limits.check_notional(&trade_notional(&price, &shares, instrument.contract_multiplier))
+        .map_err(|e| TradeError::Rejected(format!("Invalid order for {}: {}", symbol, e)))?;
+    // Messages of this format predate orders and users, so they trade for the seeded user.
+    save_stock(1, symbol.to_string(), shares, price, percentage_change, action, pool)
+        .map_err(|e| cant_trade(&symbol, e))?;
+    Ok(())
 }
 
+/// Names the symbol in the error, keeping whether it's worth retrying.
+fn cant_trade(symbol: &str, e: TradeError) -> TradeError {
+    match e {
+        TradeError::Rejected(reason) => TradeError::Rejected(format!("Can't trade {}: {}", symbol, reason)),
+        TradeError::Unavailable(e) => TradeError::Unavailable(format!("Can't trade {}: {}", symbol, e)),
+    }
+}
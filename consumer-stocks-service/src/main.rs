@@ -1,42 +1,22 @@
 extern crate consumer_stocks_service;
 
-use kafka::consumer::{Consumer, FetchOffset};
-use std::{str, env};
-use consumer_stocks_service::buy_stocks;
+use std::process;
+use common_utils::event_bus::{event_bus, flush_event_bus};
+use consumer_stocks_service::{check_schema_version, run_consumer};
+use consumer_stocks_service::persistence::connection::create_connection_pool;
 
 fn main() {
-    #[allow(unused_assignments)]
-    let mut url_kafka = "".to_string();
-    match env::var("KAFKA_BROKER") {
-        Ok(stream) => {
-            url_kafka = format!("{}", stream);
-        }
-        Err(_e) => {
-            url_kafka = "localhost:9092".to_string();
-        }
-    };
-    let hosts = vec![url_kafka];
-    let mut consumer =
-       Consumer::from_hosts(hosts)
-          .with_topic("topic-stocks".to_owned())
-          .with_fallback_offset(FetchOffset::Earliest)
-          .create()
-          .unwrap();
-    loop {
-      for ms in consumer.poll().unwrap().iter() {
-        for m in ms.messages() {
-          println!("{:?}", str::from_utf8(m.value).unwrap());
-          let parts = str::from_utf8(m.value).unwrap().split(",");
-          let collection = parts.collect::<Vec<&str>>();
-          if collection.len() == 3 {
-            let symbol = collection[0];
-            let shares = collection[1];
-            let action = collection[2];
-            buy_stocks(symbol.to_string(), shares.to_string(), action.to_string());
-          }
-        }
-        let _ = consumer.consume_messageset(ms);
-      }
-      consumer.commit_consumed().unwrap();
+    // The consumer doesn't migrate the database, so it refuses to run against an outdated schema.
+    let pool = create_connection_pool();
+    if let Err(e) = check_schema_version(&mut pool.get().expect("Can't get DB connection")) {
+        eprintln!("{}", e);
+        process::exit(1);
     }
-}
\ No newline at end of file
+    let result = run_consumer(event_bus(), pool);
+    // Delivers the orders dead-lettered while stopping.
+    flush_event_bus();
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use common_utils::event_bus::{Event, EventBus};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::task;
+
+use crate::dead_letter::{process_with_retries, RetryPolicy};
+use crate::persistence::connection::PgPool;
+
+/// Orders read ahead per order processed at once, before reading more waits for room.
+const QUEUED_PER_WORKER: usize = 64;
+
+/// Orders waiting their turn, with the permits they hold while queued.
+type Lane = VecDeque<(Event, OwnedSemaphorePermit)>;
+
+/// Executes orders concurrently, up to a limit, while the orders with the same key are executed
+/// one at a time in the order they were read.
+///
+/// Orders are keyed by user and symbol, so the orders touching one position never race, and as
+/// every key lives on a single Kafka partition, partitions are processed in parallel without
+/// reordering what matters. Unkeyed orders share a key, so they're executed one at a time.
+///
+/// The offset of an order is only committed once it's executed, or dead-lettered, along with
+/// every order read before it from its partition. An order that can be neither stops the
+/// processor, as every order after it on its partition would stay uncommitted.
+#[derive(Clone)]
+pub struct OrderProcessor {
+    bus: &'static dyn EventBus,
+    /// Shared by every order, so concurrent orders wait for a connection rather than opening more.
+    pool: PgPool,
+    policy: RetryPolicy,
+    /// Permits for the orders being executed.
+    running: Arc<Semaphore>,
+    /// Permits for the orders read and not executed yet, running ones included.
+    queued: Arc<Semaphore>,
+    capacity: usize,
+    /// Orders waiting behind the one being executed, by key. A key is present while a task is
+    /// executing its orders.
+    lanes: Arc<Mutex<HashMap<String, Lane>>>,
+    offsets: Arc<Mutex<Offsets>>,
+    stopping: Arc<AtomicBool>,
+    /// Why the processor stopped on its own.
+    failure: Arc<Mutex<Option<String>>>,
+}
+
+impl OrderProcessor {
+    /// Executes up to `concurrency` orders at once with the connections of `pool`, retrying and
+    /// dead-lettering them on `bus` as `policy` says.
+    pub fn new(bus: &'static dyn EventBus, pool: PgPool, policy: RetryPolicy, concurrency: usize) -> Self {
+        let concurrency = concurrency.max(1);
+        let capacity = concurrency * QUEUED_PER_WORKER;
+        OrderProcessor {
+            bus,
+            pool,
+            policy,
+            running: Arc::new(Semaphore::new(concurrency)),
+            queued: Arc::new(Semaphore::new(capacity)),
+            capacity,
+            lanes: Arc::new(Mutex::new(HashMap::new())),
+            offsets: Arc::new(Mutex::new(Offsets::default())),
+            stopping: Arc::new(AtomicBool::new(false)),
+            failure: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Queues `event` behind the orders with the same key, waiting while too many are queued.
+    pub async fn dispatch(&self, event: Event) {
+        let permit = self.queued.clone().acquire_owned().await.expect("Order processor closed");
+        let key = event.key.clone().unwrap_or_default();
+        self.offsets.lock().unwrap().read(&event);
+        {
+            let mut lanes = self.lanes.lock().unwrap();
+            if let Some(lane) = lanes.get_mut(&key) {
+                lane.push_back((event, permit));
+                return;
+            }
+            lanes.insert(key.clone(), VecDeque::from([(event, permit)]));
+        }
+        tokio::spawn(self.clone().execute_lane(key));
+    }
+
+    /// Drops the orders that haven't started executing, leaving their offsets uncommitted, while
+    /// the ones being executed finish.
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::SeqCst);
+    }
+
+    /// Why the processor stopped, if an order could be neither executed nor dead-lettered.
+    pub fn failure(&self) -> Option<String> {
+        self.failure.lock().unwrap().clone()
+    }
+
+    /// Waits until every order dispatched so far has been executed, or dropped by [`Self::stop`].
+    pub async fn idle(&self) {
+        let _all = self.queued
+            .acquire_many(self.capacity as u32)
+            .await
+            .expect("Order processor closed");
+    }
+
+    /// The offset to commit for each partition whose handled orders moved on since the last call.
+    pub fn take_commits(&self) -> Vec<(i32, i64)> {
+        self.offsets.lock().unwrap().take_commits()
+    }
+
+    async fn execute_lane(self, key: String) {
+        // Once the processor is stopping, the next call to `next` closes the lane.
+        while let Some((event, _queued)) = self.next(&key) {
+            let _running = self.running.acquire().await.expect("Order processor closed");
+            if self.stopping.load(Ordering::SeqCst) {
+                continue;
+            }
+            let (partition, offset) = (event.partition, event.offset);
+            let (bus, policy, pool) = (self.bus, self.policy.clone(), self.pool.clone());
+            // Quotes and the database are read with blocking calls.
+            let error = match task::spawn_blocking(move || process_with_retries(bus, &event, &policy, &pool)).await {
+                Ok(Ok(())) => {
+                    self.offsets.lock().unwrap().handled(partition, offset);
+                    continue;
+                }
+                Ok(Err(e)) => e,
+                Err(e) => format!("Can't execute an order for {:?}: {}", key, e),
+            };
+            // The order is read again once the consumer restarts.
+            self.fail(format!("Offset {} of partition {} can't be committed: {}", offset, partition, error));
+        }
+    }
+
+    fn fail(&self, error: String) {
+        println!("{}", error);
+        self.failure.lock().unwrap().get_or_insert(error);
+        self.stop();
+    }
+
+    /// The next order of `key`, or none once its lane is empty or the processor is stopping,
+    /// which then closes the lane.
+    fn next(&self, key: &str) -> Option<(Event, OwnedSemaphorePermit)> {
+        let mut lanes = self.lanes.lock().unwrap();
+        let next = if self.stopping.load(Ordering::SeqCst) {
+            None
+        } else {
+            lanes.get_mut(key)?.pop_front()
+        };
+        if next.is_none() {
+            lanes.remove(key);
+        }
+        next
+    }
+}
+
+/// Offsets of the orders read, by partition.
+#[derive(Default)]
+struct Offsets {
+    /// Orders read and not handled yet.
+    pending: HashMap<i32, BTreeSet<i64>>,
+    /// Offset after the last order read.
+    read: HashMap<i32, i64>,
+    committed: HashMap<i32, i64>,
+}
+
+impl Offsets {
+    fn read(&mut self, event: &Event) {
+        self.pending.entry(event.partition).or_default().insert(event.offset);
+        self.read.insert(event.partition, event.offset + 1);
+    }
+
+    fn handled(&mut self, partition: i32, offset: i64) {
+        if let Some(pending) = self.pending.get_mut(&partition) {
+            pending.remove(&offset);
+        }
+    }
+
+    /// A partition is committed up to its first pending order, or up to the orders read if none is.
+    fn take_commits(&mut self) -> Vec<(i32, i64)> {
+        let mut commits = Vec::new();
+        for (&partition, &read) in &self.read {
+            let next = self.pending
+                .get(&partition)
+                .and_then(|pending| pending.first().copied())
+                .unwrap_or(read);
+            if self.committed.insert(partition, next) != Some(next) {
+                commits.push((partition, next));
+            }
+        }
+        commits
+    }
+}
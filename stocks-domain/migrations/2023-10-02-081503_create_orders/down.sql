@@ -0,0 +1 @@
+drop table orders;
\ No newline at end of file
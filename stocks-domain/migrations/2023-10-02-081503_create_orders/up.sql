@@ -0,0 +1,16 @@
+create table orders (
+    id serial primary key,
+    symbol varchar not null,
+    shares integer not null,
+    action_type varchar(10) not null,
+    order_type varchar(20) not null,
+    stop_price varchar,
+    limit_price varchar,
+    status varchar(20) not null default 'pending',
+    parent_id integer references orders,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id integer references users not null
+);
+
+SELECT diesel_manage_updated_at('orders');
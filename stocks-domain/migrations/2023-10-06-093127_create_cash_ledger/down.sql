@@ -0,0 +1 @@
+drop table cash_ledger;
\ No newline at end of file
@@ -0,0 +1,9 @@
+create table cash_ledger (
+    id serial primary key,
+    entry_type varchar(20) not null,
+    amount numeric not null,
+    description varchar,
+    stock_id integer references stocks,
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    user_id integer references users not null
+);
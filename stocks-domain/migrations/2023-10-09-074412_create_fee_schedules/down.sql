@@ -0,0 +1,2 @@
+alter table stocks drop column fee;
+drop table fee_schedules;
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- percentage_fee is a percentage of the trade value (0.1 = 0.1%), sell_fee_rate a fraction of
+-- the sale proceeds charged outside the min/max caps, like the SEC and FINRA fees.
+create table fee_schedules (
+    id serial primary key,
+    flat_fee numeric not null default 0,
+    per_share_fee numeric not null default 0,
+    percentage_fee numeric not null default 0,
+    min_fee numeric,
+    max_fee numeric,
+    sell_fee_rate numeric not null default 0,
+    user_id integer references users not null unique
+);
+
+alter table stocks add column fee numeric not null default 0;
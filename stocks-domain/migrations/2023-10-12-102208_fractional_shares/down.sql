@@ -0,0 +1,8 @@
+drop table instruments;
+
+alter table orders drop column amount;
+delete from orders where shares is null;
+alter table orders alter column shares set not null;
+alter table orders alter column shares type integer using trunc(shares);
+alter table stocks_summary alter column shares type integer using trunc(shares);
+alter table stocks alter column shares type integer using trunc(shares);
@@ -0,0 +1,11 @@
+alter table stocks alter column shares type numeric;
+alter table stocks_summary alter column shares type numeric;
+alter table orders alter column shares type numeric;
+-- Dollar-amount orders only know their quantity once they fill.
+alter table orders alter column shares drop not null;
+alter table orders add column amount numeric;
+
+create table instruments (
+    symbol varchar primary key,
+    share_precision integer not null default 4
+);
@@ -0,0 +1,4 @@
+alter table stocks drop column fx_rate;
+alter table stocks drop column currency;
+alter table cash_ledger drop column currency;
+alter table instruments drop column currency;
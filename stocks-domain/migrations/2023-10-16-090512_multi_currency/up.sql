@@ -0,0 +1,6 @@
+alter table instruments add column currency varchar not null default 'USD';
+alter table cash_ledger add column currency varchar not null default 'USD';
+-- fx_rate is the USD value of one unit of the trade currency when the trade was made, so
+-- positions can split their P&L between price moves and currency moves.
+alter table stocks add column currency varchar not null default 'USD';
+alter table stocks add column fx_rate numeric not null default 1;
@@ -0,0 +1,3 @@
+alter table stocks drop column multiplier;
+alter table instruments drop column contract_multiplier;
+alter table instruments drop column asset_class;
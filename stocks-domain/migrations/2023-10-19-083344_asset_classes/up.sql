@@ -0,0 +1,4 @@
+alter table instruments add column asset_class varchar not null default 'stocks';
+-- Units of the underlying a contract controls, e.g. 100 for standard equity options.
+alter table instruments add column contract_multiplier integer not null default 1;
+alter table stocks add column multiplier integer not null default 1;
@@ -0,0 +1,7 @@
+alter table instruments drop column refreshed_at;
+alter table instruments drop column is_listed;
+alter table instruments drop column is_nasdaq100;
+alter table instruments drop column is_nasdaq_listed;
+alter table instruments drop column stock_type;
+alter table instruments drop column exchange;
+alter table instruments drop column company_name;
@@ -0,0 +1,9 @@
+alter table instruments add column company_name varchar;
+alter table instruments add column exchange varchar;
+alter table instruments add column stock_type varchar;
+alter table instruments add column is_nasdaq_listed boolean not null default false;
+alter table instruments add column is_nasdaq100 boolean not null default false;
+-- False once Nasdaq stops knowing the symbol; orders for it are rejected.
+alter table instruments add column is_listed boolean not null default true;
+-- Null until the metadata has been fetched from Nasdaq.
+alter table instruments add column refreshed_at timestamp;
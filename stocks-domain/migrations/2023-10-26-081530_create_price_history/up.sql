@@ -0,0 +1,9 @@
+-- Quotes sampled periodically by the consumer, to chart prices between trades.
+create table price_history (
+    id serial primary key,
+    symbol varchar not null,
+    price numeric not null,
+    sampled_at timestamp not null default current_timestamp
+);
+
+create index price_history_symbol_sampled_at on price_history (symbol, sampled_at);
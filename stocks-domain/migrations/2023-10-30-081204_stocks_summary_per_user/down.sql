@@ -0,0 +1,2 @@
+alter table stocks_summary drop constraint stocks_summary_user_id_symbol_key;
+alter table stocks_summary add constraint stocks_summary_symbol_key unique (symbol);
@@ -0,0 +1,3 @@
+-- Every user has their own summary of a symbol.
+alter table stocks_summary drop constraint stocks_summary_symbol_key;
+alter table stocks_summary add constraint stocks_summary_user_id_symbol_key unique (user_id, symbol);
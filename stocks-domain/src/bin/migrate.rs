@@ -0,0 +1,70 @@
+//! Applies or reverts the migrations of the database shared by the stocks services.
+//!
+//! ```text
+//! migrate [up]          apply the pending migrations
+//! migrate down [COUNT]  revert the last COUNT migrations, 1 by default
+//! migrate redo          revert and reapply the last migration
+//! migrate status        list the applied and pending migrations
+//! ```
+
+use std::env;
+use std::process;
+
+use diesel::migration::{MigrationSource, Result};
+use diesel::pg::Pg;
+use diesel::PgConnection;
+use diesel_migrations::{HarnessWithOutput, MigrationHarness};
+use dotenv::dotenv;
+
+use stocks_domain::persistence::connection::create_connection_pool;
+use stocks_domain::MIGRATIONS;
+
+fn main() {
+    dotenv().ok();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let pool = create_connection_pool();
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let result = match args.as_slice() {
+        [] | ["up"] => up(&mut conn),
+        ["down"] => down(&mut conn, 1),
+        ["down", count] => match count.parse() {
+            Ok(count) => down(&mut conn, count),
+            Err(_e) => usage(),
+        },
+        ["redo"] => down(&mut conn, 1).and_then(|_| up(&mut conn)),
+        ["status"] => status(&mut conn),
+        _ => usage(),
+    };
+    if let Err(e) = result {
+        eprintln!("Migration failed: {}", e);
+        process::exit(1);
+    }
+}
+
+fn up(conn: &mut PgConnection) -> Result<()> {
+    HarnessWithOutput::write_to_stdout(conn).run_pending_migrations(MIGRATIONS)?;
+    Ok(())
+}
+
+fn down(conn: &mut PgConnection, count: usize) -> Result<()> {
+    let mut harness = HarnessWithOutput::write_to_stdout(conn);
+    for _ in 0..count {
+        harness.revert_last_migration(MIGRATIONS)?;
+    }
+    Ok(())
+}
+
+fn status(conn: &mut PgConnection) -> Result<()> {
+    let applied = conn.applied_migrations()?;
+    for migration in MigrationSource::<Pg>::migrations(&MIGRATIONS)? {
+        let state = if applied.contains(&migration.name().version()) { "applied" } else { "pending" };
+        println!("{:8} {}", state, migration.name());
+    }
+    Ok(())
+}
+
+fn usage() -> Result<()> {
+    eprintln!("Usage: migrate [up | down [COUNT] | redo | status]");
+    process::exit(2);
+}
@@ -0,0 +1,33 @@
+//! Schema, models, repository and trading calculations shared by the stocks services, with the
+//! migrations that create the tables they use.
+
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::PgConnection;
+use diesel_migrations::MigrationHarness;
+
+pub mod persistence;
+pub mod stock_functions;
+
+pub const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
+    diesel_migrations::embed_migrations!("./migrations");
+
+pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
+    conn.run_pending_migrations(MIGRATIONS)
+        .expect("Failed to run database migrations");
+}
+
+/// Fails with the pending migrations when the database is behind this build, so that services
+/// which don't migrate it can refuse to start instead of writing to missing tables.
+pub fn check_schema_version(conn: &mut PgConnection) -> Result<(), String> {
+    let pending = conn.pending_migrations(MIGRATIONS)
+        .map_err(|e| format!("Can't read the applied migrations: {}", e))?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let names: Vec<String> = pending.iter().map(|migration| migration.name().to_string()).collect();
+    Err(format!(
+        "The database schema is missing {} migration(s): {}. Run `cargo run -p stocks-domain --bin migrate` first.",
+        names.len(),
+        names.join(", ")
+    ))
+}
@@ -0,0 +1,238 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use diesel::prelude::*;
+
+use crate::persistence::schema::{cash_ledger, fee_schedules, instruments, orders, price_history, stocks, users, stocks_summary};
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = users)]
+pub struct UserEntity {
+    pub id: i32,
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[diesel(table_name = stocks)]
+#[diesel(belongs_to(StocksEntity, foreign_key = user_id))]
+pub struct StocksEntity {
+    pub id: i32,
+    pub symbol: String,
+    pub shares: BigDecimal,
+    pub price: String,
+    pub percentage_change: String,
+    pub action_type: String,
+    pub user_id: i32,
+    pub fee: BigDecimal,
+    pub currency: String,
+    pub fx_rate: BigDecimal,
+    pub multiplier: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = users)]
+pub struct NewUserEntity {
+    pub name: String,
+    pub email: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = stocks)]
+pub struct NewStocksEntity {
+    pub symbol: String,
+    pub shares: BigDecimal,
+    pub price: String,
+    pub percentage_change: String,
+    pub action_type: String,
+    pub user_id: i32,
+    pub fee: BigDecimal,
+    pub currency: String,
+    pub fx_rate: BigDecimal,
+    pub multiplier: i32,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[diesel(table_name = stocks_summary)]
+#[diesel(belongs_to(StocksSummaryEntity, foreign_key = user_id))]
+pub struct StocksSummaryEntity {
+    pub id: i32,
+    pub symbol: String,
+    pub shares: BigDecimal,
+    pub total_value: String,
+    pub lowest_price: String,
+    pub highest_price: String,
+    pub average_price: String,
+    pub price_by_hours: String,
+    pub profit_loss: String,
+    pub user_id: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = stocks_summary)]
+pub struct NewStocksSummaryEntity {
+    pub symbol: String,
+    pub shares: BigDecimal,
+    pub total_value: String,
+    pub lowest_price: String,
+    pub highest_price: String,
+    pub average_price: String,
+    pub price_by_hours: String,
+    pub profit_loss: String,
+    pub user_id: i32,
+}
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = orders)]
+pub struct OrderEntity {
+    pub id: i32,
+    pub symbol: String,
+    pub shares: Option<BigDecimal>,
+    pub amount: Option<BigDecimal>,
+    pub action_type: String,
+    pub order_type: String,
+    pub stop_price: Option<String>,
+    pub limit_price: Option<String>,
+    pub status: String,
+    pub parent_id: Option<i32>,
+    pub user_id: i32,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = orders)]
+pub struct NewOrderEntity {
+    pub symbol: String,
+    pub shares: Option<BigDecimal>,
+    pub amount: Option<BigDecimal>,
+    pub action_type: String,
+    pub order_type: String,
+    pub stop_price: Option<String>,
+    pub limit_price: Option<String>,
+    pub status: String,
+    pub parent_id: Option<i32>,
+    pub user_id: i32,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = orders)]
+pub struct OrderChangeset {
+    pub shares: Option<BigDecimal>,
+    pub stop_price: Option<String>,
+    pub limit_price: Option<String>,
+}
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = cash_ledger)]
+pub struct CashLedgerEntity {
+    pub id: i32,
+    pub entry_type: String,
+    pub amount: BigDecimal,
+    pub description: Option<String>,
+    pub stock_id: Option<i32>,
+    pub user_id: i32,
+    pub currency: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = cash_ledger)]
+pub struct NewCashLedgerEntity {
+    pub entry_type: String,
+    pub amount: BigDecimal,
+    pub description: Option<String>,
+    pub stock_id: Option<i32>,
+    pub user_id: i32,
+    pub currency: String,
+}
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = fee_schedules)]
+pub struct FeeScheduleEntity {
+    pub id: i32,
+    pub flat_fee: BigDecimal,
+    pub per_share_fee: BigDecimal,
+    pub percentage_fee: BigDecimal,
+    pub min_fee: Option<BigDecimal>,
+    pub max_fee: Option<BigDecimal>,
+    pub sell_fee_rate: BigDecimal,
+    pub user_id: i32,
+}
+
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = fee_schedules)]
+#[diesel(treat_none_as_null = true)]
+pub struct NewFeeScheduleEntity {
+    pub flat_fee: BigDecimal,
+    pub per_share_fee: BigDecimal,
+    pub percentage_fee: BigDecimal,
+    pub min_fee: Option<BigDecimal>,
+    pub max_fee: Option<BigDecimal>,
+    pub sell_fee_rate: BigDecimal,
+    pub user_id: i32,
+}
+
+#[derive(Queryable, Insertable, AsChangeset)]
+#[diesel(table_name = instruments)]
+pub struct InstrumentEntity {
+    pub symbol: String,
+    pub share_precision: i32,
+    pub currency: String,
+    pub asset_class: String,
+    pub contract_multiplier: i32,
+    pub company_name: Option<String>,
+    pub exchange: Option<String>,
+    pub stock_type: Option<String>,
+    pub is_nasdaq_listed: bool,
+    pub is_nasdaq100: bool,
+    pub is_listed: bool,
+    pub refreshed_at: Option<NaiveDateTime>,
+}
+
+#[derive(Identifiable, Queryable)]
+#[diesel(table_name = price_history)]
+pub struct PriceSampleEntity {
+    pub id: i32,
+    pub symbol: String,
+    pub price: BigDecimal,
+    pub sampled_at: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = price_history)]
+pub struct NewPriceSampleEntity {
+    pub symbol: String,
+    pub price: BigDecimal,
+}
+
+/// Prices sampled within one bucket of a candle query.
+#[derive(QueryableByName)]
+pub struct CandleEntity {
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub bucket_start: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub open: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub high: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub low: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub close: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub samples: i64,
+}
+
+/// Trades of one time bucket of a price series query.
+#[derive(QueryableByName)]
+pub struct PriceBucketEntity {
+    /// Start of the bucket in the time zone of the query.
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub local_start: NaiveDateTime,
+    #[diesel(sql_type = diesel::sql_types::Timestamptz)]
+    pub bucket_start: DateTime<Utc>,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub avg_price: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub min_price: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub max_price: BigDecimal,
+    #[diesel(sql_type = diesel::sql_types::Numeric)]
+    pub volume: BigDecimal,
+}
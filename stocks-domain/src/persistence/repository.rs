@@ -0,0 +1,442 @@
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::prelude::*;
+
+use crate::persistence::model::{StocksEntity, NewStocksEntity, OrderEntity, NewOrderEntity, OrderChangeset, CashLedgerEntity, NewCashLedgerEntity, FeeScheduleEntity, NewFeeScheduleEntity, InstrumentEntity, PriceSampleEntity, NewPriceSampleEntity, CandleEntity, PriceBucketEntity, UserEntity, StocksSummaryEntity, NewStocksSummaryEntity};
+use crate::persistence::schema::{stocks, users};
+
+pub fn get_all(conn: &mut PgConnection) -> QueryResult<Vec<UserEntity>> {
+    use crate::persistence::schema::users::dsl::*;
+
+    users.load(conn)
+}
+
+pub fn get(id: i32, conn: &mut PgConnection) -> QueryResult<UserEntity> {
+    users::table.find(id).get_result(conn)
+}
+
+/// Locks the row of the user until the end of the transaction, so that the trades and
+/// withdrawals of one user check their cash and shares one at a time.
+pub fn lock_user(user: i32, conn: &mut PgConnection) -> QueryResult<UserEntity> {
+    users::table.find(user).for_update().get_result(conn)
+}
+
+pub fn get_stock(symbol: String, conn: &mut PgConnection) -> QueryResult<StocksEntity> {
+    stocks::table.filter(stocks::symbol.eq(symbol)).get_result(conn)
+}
+
+pub fn get_stocks_summary(user: i32, conn: &mut PgConnection) -> QueryResult<Vec<StocksSummaryEntity>> {
+    use crate::persistence::schema::stocks_summary::dsl::*;
+
+    stocks_summary.filter(user_id.eq(user)).order(id).load(conn)
+}
+
+pub fn create_stock(
+    new_stocks: NewStocksEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<StocksEntity> {
+    use crate::persistence::schema::{stocks::dsl::*};
+
+    let created_stock: StocksEntity = diesel::insert_into(stocks)
+        .values(new_stocks)
+        .get_result(conn)?;
+
+    Ok(created_stock)
+}
+
+pub fn get_stocks_by_symbol(symbol_data: String, conn: &mut PgConnection) -> Vec<StocksEntity> {
+    use crate::persistence::schema::{stocks::dsl::*};
+    let result = stocks
+        .filter(symbol.eq(symbol_data))
+        .order(id)
+        .load::<StocksEntity>(conn)
+        .expect("Error loading students");
+    result
+}
+
+pub fn get_user_stocks_by_symbol(user: i32, symbol_data: String, conn: &mut PgConnection) -> QueryResult<Vec<StocksEntity>> {
+    use crate::persistence::schema::{stocks::dsl::*};
+
+    stocks
+        .filter(user_id.eq(user))
+        .filter(symbol.eq(symbol_data))
+        .order(id)
+        .load(conn)
+}
+
+pub fn get_stock_summary(user: i32, symbol_data: String, conn: &mut PgConnection) -> QueryResult<Option<StocksSummaryEntity>> {
+    use crate::persistence::schema::{stocks_summary::dsl::*};
+
+    stocks_summary
+        .filter(user_id.eq(user))
+        .filter(symbol.eq(symbol_data))
+        .first(conn)
+        .optional()
+}
+
+pub fn create_stock_summary(
+    new_stocks_summary: NewStocksSummaryEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<StocksSummaryEntity> {
+    use crate::persistence::schema::{stocks_summary::dsl::*};
+
+    let created_stock_summary: StocksSummaryEntity = diesel::insert_into(stocks_summary)
+        .values(new_stocks_summary)
+        .get_result(conn)?;
+
+    Ok(created_stock_summary)
+}
+
+pub fn update_stock_summary(
+    new_stocks_summary: NewStocksSummaryEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<StocksSummaryEntity> {
+    use crate::persistence::schema::{stocks_summary::dsl::*};
+
+    let created_stock_summary: StocksSummaryEntity = diesel::update(stocks_summary
+            .filter(user_id.eq(new_stocks_summary.user_id))
+            .filter(symbol.eq(new_stocks_summary.symbol)))
+        .set((
+            shares.eq(new_stocks_summary.shares),
+            total_value.eq(new_stocks_summary.total_value),
+            lowest_price.eq(new_stocks_summary.lowest_price),
+            highest_price.eq(new_stocks_summary.highest_price),
+            average_price.eq(new_stocks_summary.average_price),
+            price_by_hours.eq(new_stocks_summary.price_by_hours),
+            profit_loss.eq(new_stocks_summary.profit_loss),
+        ))
+        .get_result(conn)?;
+
+    Ok(created_stock_summary)
+}
+
+pub fn create_order(
+    new_order: NewOrderEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<OrderEntity> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    diesel::insert_into(orders)
+        .values(new_order)
+        .get_result(conn)
+}
+
+pub fn get_order(order_id: i32, conn: &mut PgConnection) -> QueryResult<OrderEntity> {
+    use crate::persistence::schema::orders::dsl::*;
+
+    orders.find(order_id).get_result(conn)
+}
+
+pub fn get_user_order(order_id: i32, user: i32, conn: &mut PgConnection) -> QueryResult<OrderEntity> {
+    use crate::persistence::schema::orders::dsl::*;
+
+    orders.find(order_id).filter(user_id.eq(user)).get_result(conn)
+}
+
+pub fn get_orders(conn: &mut PgConnection) -> QueryResult<Vec<OrderEntity>> {
+    use crate::persistence::schema::orders::dsl::*;
+
+    orders.order(id).load(conn)
+}
+
+pub fn get_orders_by_status(statuses: Vec<String>, conn: &mut PgConnection) -> QueryResult<Vec<OrderEntity>> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    orders
+        .filter(status.eq_any(statuses))
+        .order(id)
+        .load(conn)
+}
+
+pub fn update_order_status(
+    order_id: i32,
+    new_status: String,
+    conn: &mut PgConnection,
+) -> QueryResult<OrderEntity> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    diesel::update(orders.find(order_id))
+        .set(status.eq(new_status))
+        .get_result(conn)
+}
+
+pub fn cancel_sibling_orders(
+    order_id: i32,
+    parent_order_id: i32,
+    open_statuses: Vec<String>,
+    new_status: String,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<OrderEntity>> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    diesel::update(
+        orders
+            .filter(parent_id.eq(parent_order_id))
+            .filter(id.ne(order_id))
+            .filter(status.eq_any(open_statuses)),
+    )
+    .set(status.eq(new_status))
+    .get_results(conn)
+}
+
+/// Moves an order to `new_status` only while it is still in one of `open_statuses`,
+/// returning `None` when it was already executed or cancelled.
+pub fn update_open_order_status(
+    order_id: i32,
+    open_statuses: Vec<String>,
+    new_status: String,
+    conn: &mut PgConnection,
+) -> QueryResult<Option<OrderEntity>> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    diesel::update(orders.find(order_id).filter(status.eq_any(open_statuses)))
+        .set(status.eq(new_status))
+        .get_result(conn)
+        .optional()
+}
+
+/// Like `update_open_order_status`, but only for an order placed by `user`.
+pub fn update_open_user_order_status(
+    order_id: i32,
+    user: i32,
+    open_statuses: Vec<String>,
+    new_status: String,
+    conn: &mut PgConnection,
+) -> QueryResult<Option<OrderEntity>> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    diesel::update(orders.find(order_id).filter(user_id.eq(user)).filter(status.eq_any(open_statuses)))
+        .set(status.eq(new_status))
+        .get_result(conn)
+        .optional()
+}
+
+pub fn amend_open_order(
+    order_id: i32,
+    user: i32,
+    open_statuses: Vec<String>,
+    changes: OrderChangeset,
+    conn: &mut PgConnection,
+) -> QueryResult<Option<OrderEntity>> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    diesel::update(orders.find(order_id).filter(user_id.eq(user)).filter(status.eq_any(open_statuses)))
+        .set(changes)
+        .get_result(conn)
+        .optional()
+}
+
+pub fn create_cash_ledger_entry(
+    new_entry: NewCashLedgerEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<CashLedgerEntity> {
+    use crate::persistence::schema::{cash_ledger::dsl::*};
+
+    diesel::insert_into(cash_ledger)
+        .values(new_entry)
+        .get_result(conn)
+}
+
+pub fn get_cash_ledger(user: i32, conn: &mut PgConnection) -> QueryResult<Vec<CashLedgerEntity>> {
+    use crate::persistence::schema::{cash_ledger::dsl::*};
+
+    cash_ledger
+        .filter(user_id.eq(user))
+        .order(id.desc())
+        .load(conn)
+}
+
+pub fn get_cash_balance(user: i32, currency_code: String, conn: &mut PgConnection) -> QueryResult<BigDecimal> {
+    use crate::persistence::schema::{cash_ledger::dsl::*};
+
+    let balance: Option<BigDecimal> = cash_ledger
+        .filter(user_id.eq(user))
+        .filter(currency.eq(currency_code))
+        .select(diesel::dsl::sum(amount))
+        .first(conn)?;
+    Ok(balance.unwrap_or_default())
+}
+
+/// Cash balance of the user in each currency it has ledger entries in.
+pub fn get_cash_balances(user: i32, conn: &mut PgConnection) -> QueryResult<Vec<(String, BigDecimal)>> {
+    use crate::persistence::schema::{cash_ledger::dsl::*};
+
+    let balances: Vec<(String, Option<BigDecimal>)> = cash_ledger
+        .filter(user_id.eq(user))
+        .group_by(currency)
+        .select((currency, diesel::dsl::sum(amount)))
+        .order(currency)
+        .load(conn)?;
+    Ok(balances
+        .into_iter()
+        .map(|(code, balance)| (code, balance.unwrap_or_default()))
+        .collect())
+}
+
+pub fn get_fee_schedule(user: i32, conn: &mut PgConnection) -> QueryResult<Option<FeeScheduleEntity>> {
+    use crate::persistence::schema::{fee_schedules::dsl::*};
+
+    fee_schedules
+        .filter(user_id.eq(user))
+        .first(conn)
+        .optional()
+}
+
+pub fn save_fee_schedule(
+    new_schedule: NewFeeScheduleEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<FeeScheduleEntity> {
+    use crate::persistence::schema::{fee_schedules::dsl::*};
+
+    diesel::insert_into(fee_schedules)
+        .values(&new_schedule)
+        .on_conflict(user_id)
+        .do_update()
+        .set(&new_schedule)
+        .get_result(conn)
+}
+
+/// Moves an open order to `new_status` recording the quantity it filled with.
+pub fn fill_open_order(
+    order_id: i32,
+    open_statuses: Vec<String>,
+    new_status: String,
+    filled_shares: BigDecimal,
+    conn: &mut PgConnection,
+) -> QueryResult<Option<OrderEntity>> {
+    use crate::persistence::schema::{orders::dsl::*};
+
+    diesel::update(orders.find(order_id).filter(status.eq_any(open_statuses)))
+        .set((status.eq(new_status), shares.eq(filled_shares)))
+        .get_result(conn)
+        .optional()
+}
+
+pub fn get_instrument(symbol_data: String, conn: &mut PgConnection) -> QueryResult<Option<InstrumentEntity>> {
+    use crate::persistence::schema::{instruments::dsl::*};
+
+    instruments.find(symbol_data).first(conn).optional()
+}
+
+/// Listed instruments whose symbol or company name starts with `prefix`, case-insensitively.
+pub fn search_instruments(prefix: String, limit: i64, conn: &mut PgConnection) -> QueryResult<Vec<InstrumentEntity>> {
+    use crate::persistence::schema::{instruments::dsl::*};
+
+    let pattern = format!("{}%", prefix.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+    instruments
+        .filter(is_listed.eq(true))
+        .filter(symbol.ilike(&pattern).or(company_name.ilike(&pattern)))
+        .order(symbol)
+        .limit(limit)
+        .load(conn)
+}
+
+/// Instruments never fetched from Nasdaq or last refreshed before `cutoff`.
+pub fn get_instruments_refreshed_before(cutoff: NaiveDateTime, conn: &mut PgConnection) -> QueryResult<Vec<InstrumentEntity>> {
+    use crate::persistence::schema::{instruments::dsl::*};
+
+    instruments
+        .filter(refreshed_at.is_null().or(refreshed_at.lt(cutoff)))
+        .order(symbol)
+        .load(conn)
+}
+
+pub fn save_instrument(
+    instrument: InstrumentEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<InstrumentEntity> {
+    use crate::persistence::schema::{instruments::dsl::*};
+
+    diesel::insert_into(instruments)
+        .values(&instrument)
+        .on_conflict(symbol)
+        .do_update()
+        .set(&instrument)
+        .get_result(conn)
+}
+
+/// Instruments Nasdaq still lists, in symbol order.
+pub fn get_listed_instruments(conn: &mut PgConnection) -> QueryResult<Vec<InstrumentEntity>> {
+    use crate::persistence::schema::{instruments::dsl::*};
+
+    instruments
+        .filter(is_listed.eq(true))
+        .order(symbol)
+        .load(conn)
+}
+
+pub fn create_price_sample(
+    new_sample: NewPriceSampleEntity,
+    conn: &mut PgConnection,
+) -> QueryResult<PriceSampleEntity> {
+    use crate::persistence::schema::{price_history::dsl::*};
+
+    diesel::insert_into(price_history)
+        .values(new_sample)
+        .get_result(conn)
+}
+
+/// OHLC candles of the prices of `symbol_data` sampled in `[from, to)`, in buckets of
+/// `bucket_secs` seconds aligned on the Unix epoch. Buckets without samples are left out.
+pub fn get_candles(
+    symbol_data: String,
+    bucket_secs: i64,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<CandleEntity>> {
+    use diesel::sql_types::{BigInt, Timestamp, Varchar};
+
+    diesel::sql_query(
+        "SELECT to_timestamp(floor(extract(epoch FROM sampled_at) / $2) * $2) AT TIME ZONE 'UTC' AS bucket_start,
+            (array_agg(price ORDER BY sampled_at, id))[1] AS open,
+            MAX(price) AS high,
+            MIN(price) AS low,
+            (array_agg(price ORDER BY sampled_at DESC, id DESC))[1] AS close,
+            COUNT(*) AS samples
+        FROM price_history
+        WHERE symbol = $1 AND sampled_at >= $3 AND sampled_at < $4
+        GROUP BY 1
+        ORDER BY 1"
+    )
+    .bind::<Varchar, _>(symbol_data)
+    .bind::<BigInt, _>(bucket_secs)
+    .bind::<Timestamp, _>(from)
+    .bind::<Timestamp, _>(to)
+    .load(conn)
+}
+
+/// Prices and volume `user` traded in `symbol_data`, grouped by `date_trunc` buckets of `unit`
+/// (`minute`, `hour`, `day`...) in `time_zone`. Trade timestamps are stored in UTC.
+pub fn get_price_series(
+    symbol_data: String,
+    user: i32,
+    unit: String,
+    time_zone: String,
+    conn: &mut PgConnection,
+) -> QueryResult<Vec<PriceBucketEntity>> {
+    use diesel::sql_types::{Int4, Varchar};
+
+    diesel::sql_query(
+        "SELECT local_start,
+            local_start AT TIME ZONE $3 AS bucket_start,
+            ROUND(AVG(price), 2) AS avg_price,
+            MIN(price) AS min_price,
+            MAX(price) AS max_price,
+            SUM(shares) AS volume
+        FROM (
+            SELECT date_trunc($2, created_at AT TIME ZONE 'UTC' AT TIME ZONE $3) AS local_start,
+                CAST(price AS DECIMAL) AS price,
+                shares
+            FROM stocks
+            WHERE symbol = $1 AND user_id = $4
+        ) trades
+        GROUP BY local_start
+        ORDER BY local_start"
+    )
+    .bind::<Varchar, _>(symbol_data)
+    .bind::<Varchar, _>(unit)
+    .bind::<Varchar, _>(time_zone)
+    .bind::<Int4, _>(user)
+    .load(conn)
+}
@@ -0,0 +1,122 @@
+diesel::table! {
+    stocks (id) {
+        id -> Int4,
+        symbol -> Varchar,
+        shares -> Numeric,
+        price -> Varchar,
+        percentage_change -> Varchar,
+        action_type -> Varchar,
+        user_id -> Int4,
+        fee -> Numeric,
+        currency -> Varchar,
+        fx_rate -> Numeric,
+        multiplier -> Int4,
+    }
+}
+
+diesel::table! {
+    stocks_summary (id) {
+        id -> Int4,
+        symbol -> Varchar,
+        shares -> Numeric,
+        total_value -> Varchar,
+        lowest_price -> Varchar,
+        highest_price -> Varchar,
+        average_price -> Varchar,
+        price_by_hours -> Varchar,
+        profit_loss -> Varchar,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    cash_ledger (id) {
+        id -> Int4,
+        entry_type -> Varchar,
+        amount -> Numeric,
+        description -> Nullable<Varchar>,
+        stock_id -> Nullable<Int4>,
+        user_id -> Int4,
+        currency -> Varchar,
+    }
+}
+
+diesel::table! {
+    fee_schedules (id) {
+        id -> Int4,
+        flat_fee -> Numeric,
+        per_share_fee -> Numeric,
+        percentage_fee -> Numeric,
+        min_fee -> Nullable<Numeric>,
+        max_fee -> Nullable<Numeric>,
+        sell_fee_rate -> Numeric,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    instruments (symbol) {
+        symbol -> Varchar,
+        share_precision -> Int4,
+        currency -> Varchar,
+        asset_class -> Varchar,
+        contract_multiplier -> Int4,
+        company_name -> Nullable<Varchar>,
+        exchange -> Nullable<Varchar>,
+        stock_type -> Nullable<Varchar>,
+        is_nasdaq_listed -> Bool,
+        is_nasdaq100 -> Bool,
+        is_listed -> Bool,
+        refreshed_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    orders (id) {
+        id -> Int4,
+        symbol -> Varchar,
+        shares -> Nullable<Numeric>,
+        amount -> Nullable<Numeric>,
+        action_type -> Varchar,
+        order_type -> Varchar,
+        stop_price -> Nullable<Varchar>,
+        limit_price -> Nullable<Varchar>,
+        status -> Varchar,
+        parent_id -> Nullable<Int4>,
+        user_id -> Int4,
+    }
+}
+
+diesel::table! {
+    price_history (id) {
+        id -> Int4,
+        symbol -> Varchar,
+        price -> Numeric,
+        sampled_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    users (id) {
+        id -> Int4,
+        name -> Varchar,
+        email -> Varchar
+    }
+}
+
+diesel::joinable!(stocks -> users (user_id));
+diesel::joinable!(orders -> users (user_id));
+diesel::joinable!(cash_ledger -> users (user_id));
+diesel::joinable!(cash_ledger -> stocks (stock_id));
+diesel::joinable!(fee_schedules -> users (user_id));
+
+diesel::allow_tables_to_appear_in_same_query!(
+    cash_ledger,
+    fee_schedules,
+    instruments,
+    orders,
+    price_history,
+    stocks,
+    users,
+);
+
@@ -0,0 +1,1544 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use bigdecimal::{BigDecimal, RoundingMode};
+use chrono::{Duration, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use strum_macros::{Display, EnumString};
+use async_graphql::*;
+use common_utils::fx::{FxRateSource, StaticFxRates, DEFAULT_CURRENCY};
+use common_utils::trade_events::{publish_trade_event, OrderSnapshot, TradeEvent, TradeSnapshot};
+use crate::persistence::model::{StocksEntity, NewStocksEntity, NewStocksSummaryEntity, OrderEntity, NewOrderEntity, CashLedgerEntity, NewCashLedgerEntity, FeeScheduleEntity, InstrumentEntity, NewPriceSampleEntity, CandleEntity, PriceBucketEntity};
+use crate::persistence::repository;
+use crate::persistence::connection::PgPool;
+use diesel::r2d2::{ConnectionManager, PooledConnection};
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::*;
+
+/// Why a trade or a withdrawal wasn't booked.
+#[derive(Debug)]
+pub enum TradeError {
+    /// The user can't make it as asked, e.g. without the cash or the shares it needs.
+    Rejected(String),
+    /// The database or Nasdaq failed; trying again may succeed.
+    Unavailable(String),
+}
+
+impl fmt::Display for TradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradeError::Rejected(reason) => write!(f, "{}", reason),
+            TradeError::Unavailable(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for TradeError {}
+
+impl From<diesel::result::Error> for TradeError {
+    fn from(e: diesel::result::Error) -> Self {
+        TradeError::Unavailable(format!("Database unavailable: {}", e))
+    }
+}
+
+/// Runs `f` on the runtime's blocking thread pool, since diesel would stall the async tasks.
+async fn run_blocking<T, F>(pool: &PgPool, f: F) -> Result<T, TradeError>
+where
+    T: Send + 'static,
+    F: FnOnce(&PgPool) -> Result<T, TradeError> + Send + 'static,
+{
+    let pool = pool.clone();
+    tokio::task::spawn_blocking(move || f(&pool))
+        .await
+        .map_err(|e| TradeError::Unavailable(format!("Database task failed: {}", e)))?
+}
+
+fn get_connection(pool: &PgPool) -> Result<PooledConnection<ConnectionManager<PgConnection>>, TradeError> {
+    pool.get().map_err(|e| TradeError::Unavailable(format!("Database unavailable: {}", e)))
+}
+
+/// Records an executed trade of `user_id` together with the cash it moved and the summary of the
+/// position, failing without booking anything when the user lacks the cash or the shares for it,
+/// or when there's no rate to record the trade's currency against USD.
+pub fn save_stock(
+    user_id: i32,
+    symbol: String,
+    shares: BigDecimal,
+    price: String,
+    percentage_change: String,
+    action: String,
+    pool: &PgPool,
+) -> Result<StocksEntity, TradeError> {
+    let instrument = get_instrument(&symbol, pool)?;
+    let created_stock = get_connection(pool)?.transaction(|conn| {
+        execute_trade(user_id, &instrument, shares, price, percentage_change, action, conn)
+    })?;
+    publish_trade_executed(&created_stock);
+    Ok(created_stock)
+}
+
+/// Books a trade within the caller's transaction. The user's row stays locked until it commits,
+/// so concurrent trades and withdrawals see each other's cash and shares.
+fn execute_trade(
+    user_id: i32,
+    instrument: &InstrumentEntity,
+    shares: BigDecimal,
+    price: String,
+    percentage_change: String,
+    action: String,
+    conn: &mut PgConnection,
+) -> Result<StocksEntity, TradeError> {
+    let order_action = OrderAction::parse(&action)?;
+    lock_user(user_id, conn)?;
+    let symbol = instrument.symbol.to_string();
+    let schedule = repository::get_fee_schedule(user_id, conn)?;
+    let fee = calculate_fee(schedule.as_ref(), &action, &shares, &price, instrument.contract_multiplier);
+    let currency = instrument.currency.to_string();
+    let fx_rate = fx_rates().rate(&currency, DEFAULT_CURRENCY).ok_or_else(|| {
+        TradeError::Rejected(format!("No FX rate from {} to {} to book {}", currency, DEFAULT_CURRENCY, symbol))
+    })?;
+    if order_action == OrderAction::Buy {
+        let cost = trade_notional(&price, &shares, instrument.contract_multiplier) + &fee;
+        let balance = repository::get_cash_balance(user_id, currency.to_string(), conn)?;
+        if balance < cost {
+            return Err(TradeError::Rejected(format!(
+                "Insufficient buying power: {} {} needed, {} available", cost, currency, balance
+            )));
+        }
+    } else {
+        let held = position_shares(&repository::get_user_stocks_by_symbol(user_id, symbol.to_string(), conn)?);
+        if held < shares {
+            return Err(TradeError::Rejected(format!(
+                "Insufficient shares: {} {} to sell, {} held", shares.normalized(), symbol, held.normalized()
+            )));
+        }
+    }
+    let created_stock = repository::create_stock(NewStocksEntity {
+        symbol,
+        shares,
+        price,
+        percentage_change,
+        action_type: action,
+        user_id,
+        fee,
+        currency,
+        fx_rate,
+        multiplier: instrument.contract_multiplier,
+    }, conn)?;
+    book_trade(&created_stock, conn)?;
+    calculate_stock_summary(user_id, &created_stock.symbol, conn)?;
+    Ok(created_stock)
+}
+
+/// Locks the user for the rest of the transaction, rejecting users that don't exist.
+fn lock_user(user_id: i32, conn: &mut PgConnection) -> Result<(), TradeError> {
+    match repository::lock_user(user_id, conn) {
+        Ok(_) => Ok(()),
+        Err(diesel::result::Error::NotFound) => Err(TradeError::Rejected(format!("Unknown user {}", user_id))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Tells the subscribers about a committed trade and the position it changed.
+fn publish_trade_executed(stock: &StocksEntity) {
+    publish_trade_event(&TradeEvent::TradeExecuted { trade: TradeSnapshot::from(stock) });
+    publish_trade_event(&TradeEvent::PortfolioChanged { user_id: stock.user_id, symbol: Some(stock.symbol.to_string()) });
+}
+
+/// Shares the trades of a position add up to.
+fn position_shares(trades: &[StocksEntity]) -> BigDecimal {
+    trades.iter().fold(BigDecimal::from(0), |held, trade| {
+        if trade.action_type == "buy" { held + &trade.shares } else { held - &trade.shares }
+    })
+}
+
+/// Shares of `symbol` that `user_id` holds.
+pub fn held_shares(user_id: i32, symbol: &str, pool: &PgPool) -> Result<BigDecimal, TradeError> {
+    let trades = repository::get_user_stocks_by_symbol(user_id, symbol.to_string(), &mut *get_connection(pool)?)?;
+    Ok(position_shares(&trades))
+}
+
+impl From<&StocksEntity> for TradeSnapshot {
+    fn from(entity: &StocksEntity) -> Self {
+        TradeSnapshot {
+            id: entity.id,
+            user_id: entity.user_id,
+            symbol: entity.symbol.clone(),
+            shares: entity.shares.clone(),
+            price: entity.price.clone(),
+            percentage_change: entity.percentage_change.clone(),
+            action_type: entity.action_type.clone(),
+            fee: entity.fee.clone(),
+            currency: entity.currency.clone(),
+        }
+    }
+}
+
+impl From<&OrderEntity> for OrderSnapshot {
+    fn from(entity: &OrderEntity) -> Self {
+        OrderSnapshot {
+            id: entity.id,
+            user_id: entity.user_id,
+            symbol: entity.symbol.clone(),
+            shares: entity.shares.clone(),
+            amount: entity.amount.clone(),
+            action_type: entity.action_type.clone(),
+            order_type: entity.order_type.clone(),
+            stop_price: entity.stop_price.clone(),
+            limit_price: entity.limit_price.clone(),
+            status: entity.status.clone(),
+            parent_id: entity.parent_id,
+        }
+    }
+}
+
+/// Tells the subscribers to the orders of its user that `order` was placed or changed.
+pub fn publish_order_update(order: &OrderEntity) {
+    publish_trade_event(&TradeEvent::OrderUpdated { order: OrderSnapshot::from(order) });
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum LedgerEntryType {
+    Deposit,
+    Withdrawal,
+    TradeDebit,
+    TradeCredit,
+    Fee,
+}
+
+/// Cash moved by a trade, before fees. `multiplier` is the number of units a contract
+/// controls, 1 for anything but derivatives.
+pub fn trade_notional(price: &str, shares: &BigDecimal, multiplier: i32) -> BigDecimal {
+    price.parse::<BigDecimal>().unwrap_or_default() * shares * BigDecimal::from(multiplier)
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum AssetClass {
+    Stocks,
+    Etf,
+    Index,
+    Crypto,
+    Options,
+}
+
+impl AssetClass {
+    /// Options trade in whole contracts of `contract_multiplier` units of the underlying.
+    pub fn trades_in_contracts(&self) -> bool {
+        *self == AssetClass::Options
+    }
+
+    /// Decimals of quantity an instrument of this class starts with.
+    pub fn default_share_precision(&self) -> i32 {
+        match self {
+            AssetClass::Options => 0,
+            AssetClass::Crypto => 8,
+            _ => DEFAULT_SHARE_PRECISION,
+        }
+    }
+
+    /// Units a traded quantity of this class controls: `contract_multiplier` for options, 1 for
+    /// anything else.
+    pub fn multiplier(&self, contract_multiplier: i32) -> i32 {
+        if self.trades_in_contracts() { contract_multiplier } else { 1 }
+    }
+}
+
+/// Decimal places of shares an instrument can be traded in when it has no `instruments` row.
+pub const DEFAULT_SHARE_PRECISION: i32 = 4;
+
+/// The instrument's settings, or the defaults when it has no `instruments` row.
+pub fn get_instrument(symbol: &str, pool: &PgPool) -> Result<InstrumentEntity, TradeError> {
+    let instrument = repository::get_instrument(symbol.to_string(), &mut *get_connection(pool)?)?;
+    Ok(instrument.unwrap_or_else(|| InstrumentEntity {
+            symbol: symbol.to_string(),
+            share_precision: DEFAULT_SHARE_PRECISION,
+            currency: DEFAULT_CURRENCY.to_string(),
+            asset_class: AssetClass::Stocks.to_string(),
+            contract_multiplier: 1,
+            company_name: None,
+            exchange: None,
+            stock_type: None,
+            is_nasdaq_listed: false,
+            is_nasdaq100: false,
+            is_listed: true,
+            refreshed_at: None,
+        }))
+}
+
+/// The instrument for `symbol`, fetching its metadata from Nasdaq the first time the symbol is
+/// seen so that later orders are validated without another request. Symbols Nasdaq doesn't know
+/// or has delisted are rejected.
+pub fn lookup_instrument(symbol: &str, pool: &PgPool) -> Result<InstrumentEntity, TradeError> {
+    let instrument = get_instrument(symbol, pool)?;
+    if instrument.refreshed_at.is_some() {
+        return listed_instrument(instrument);
+    }
+    let asset_class = get_asset_class(&instrument).to_string();
+    update_instrument(instrument, common_utils::get_quote_from_nasdaq(symbol.to_string(), &asset_class), pool)
+}
+
+/// Same as [`lookup_instrument`] without blocking the async runtime on Nasdaq.
+pub async fn lookup_instrument_async(symbol: &str, pool: &PgPool) -> Result<InstrumentEntity, TradeError> {
+    let instrument = {
+        let symbol = symbol.to_string();
+        run_blocking(pool, move |pool| get_instrument(&symbol, pool)).await?
+    };
+    if instrument.refreshed_at.is_some() {
+        return listed_instrument(instrument);
+    }
+    let asset_class = get_asset_class(&instrument).to_string();
+    let result = common_utils::get_quote_from_nasdaq_async(symbol.to_string(), &asset_class).await;
+    run_blocking(pool, move |pool| update_instrument(instrument, result, pool)).await
+}
+
+/// Re-reads the metadata of `instrument` from Nasdaq, bypassing the quote cache. Failed requests
+/// leave it untouched.
+pub fn refresh_instrument(instrument: InstrumentEntity, pool: &PgPool) -> Result<InstrumentEntity, TradeError> {
+    let asset_class = get_asset_class(&instrument).to_string();
+    let result = common_utils::fetch_quote_from_nasdaq(&instrument.symbol, &asset_class)
+        .map_err(|e| TradeError::Unavailable(e.to_string()))?;
+    update_instrument(instrument, result, pool)
+}
+
+/// Stores the metadata of a Nasdaq quote for `instrument`, or marks it delisted when Nasdaq no
+/// longer knows it. Symbols that were never found aren't stored.
+fn update_instrument(
+    instrument: InstrumentEntity,
+    result: common_utils::CustomResult,
+    pool: &PgPool,
+) -> Result<InstrumentEntity, TradeError> {
+    let refreshed_at = Some(Utc::now().naive_utc());
+    let refreshed = match result.stock {
+        Some(stock) => InstrumentEntity {
+            asset_class: AssetClass::from_str(&stock.data.assetClass.to_lowercase())
+                .unwrap_or(get_asset_class(&instrument))
+                .to_string(),
+            company_name: Some(stock.data.companyName),
+            exchange: Some(stock.data.exchange),
+            stock_type: Some(stock.data.stockType),
+            is_nasdaq_listed: stock.data.isNasdaqListed,
+            is_nasdaq100: stock.data.isNasdaq100,
+            is_listed: true,
+            refreshed_at,
+            ..instrument
+        },
+        None if instrument.refreshed_at.is_none() => return Err(TradeError::Rejected(result.message)),
+        None => InstrumentEntity {
+            is_listed: false,
+            refreshed_at,
+            ..instrument
+        },
+    };
+    listed_instrument(save_instrument(refreshed, pool)?)
+}
+
+fn listed_instrument(instrument: InstrumentEntity) -> Result<InstrumentEntity, TradeError> {
+    if !instrument.is_listed {
+        return Err(TradeError::Rejected(format!("{} is no longer listed", instrument.symbol)));
+    }
+    Ok(instrument)
+}
+
+/// Refreshes the metadata of every instrument not fetched within `max_age`. Fails when the
+/// stale instruments can't be read.
+pub fn refresh_stale_instruments(max_age: Duration, pool: &PgPool) -> Result<(), TradeError> {
+    let stale_instruments = repository::get_instruments_refreshed_before(
+        Utc::now().naive_utc() - max_age, &mut *get_connection(pool)?
+    )?;
+    for instrument in stale_instruments {
+        let symbol = instrument.symbol.to_string();
+        if let Err(e) = refresh_instrument(instrument, pool) {
+            println!("Can't refresh instrument {}: {}", symbol, e);
+        }
+    }
+    Ok(())
+}
+
+pub fn get_asset_class(instrument: &InstrumentEntity) -> AssetClass {
+    AssetClass::from_str(&instrument.asset_class).unwrap_or(AssetClass::Stocks)
+}
+
+/// Quotes `symbol` from the Nasdaq listings of its asset class.
+pub fn get_quote(symbol: &str, pool: &PgPool) -> Result<common_utils::CustomResult, TradeError> {
+    let asset_class = get_asset_class(&get_instrument(symbol, pool)?);
+    Ok(common_utils::get_quote_from_nasdaq(symbol.to_string(), &asset_class.to_string()))
+}
+
+/// Same as [`get_quote`] without blocking the async runtime on Nasdaq.
+pub async fn get_quote_async(symbol: &str, pool: &PgPool) -> Result<common_utils::CustomResult, TradeError> {
+    let instrument = {
+        let symbol = symbol.to_string();
+        run_blocking(pool, move |pool| get_instrument(&symbol, pool)).await?
+    };
+    let asset_class = get_asset_class(&instrument);
+    Ok(common_utils::get_quote_from_nasdaq_async(symbol.to_string(), &asset_class.to_string()).await)
+}
+
+fn save_instrument(instrument: InstrumentEntity, pool: &PgPool) -> Result<InstrumentEntity, TradeError> {
+    Ok(repository::save_instrument(instrument, &mut *get_connection(pool)?)?)
+}
+
+pub fn get_share_precision(symbol: &str, pool: &PgPool) -> Result<i32, TradeError> {
+    Ok(get_instrument(symbol, pool)?.share_precision)
+}
+
+pub fn set_share_precision(symbol: &str, share_precision: i32, pool: &PgPool) -> Result<InstrumentEntity, TradeError> {
+    save_instrument(InstrumentEntity {
+        share_precision,
+        ..get_instrument(symbol, pool)?
+    }, pool)
+}
+
+/// Moves an instrument to `asset_class`, resetting its precision to the class default and its
+/// contract multiplier to `contract_multiplier` for options or 1 for anything else.
+pub fn set_asset_class(
+    symbol: &str,
+    asset_class: AssetClass,
+    contract_multiplier: i32,
+    pool: &PgPool,
+) -> Result<InstrumentEntity, TradeError> {
+    save_instrument(InstrumentEntity {
+        asset_class: asset_class.to_string(),
+        share_precision: asset_class.default_share_precision(),
+        contract_multiplier: asset_class.multiplier(contract_multiplier),
+        ..get_instrument(symbol, pool)?
+    }, pool)
+}
+
+pub fn set_instrument_currency(symbol: &str, currency: &str, pool: &PgPool) -> Result<InstrumentEntity, TradeError> {
+    save_instrument(InstrumentEntity {
+        currency: currency.to_string(),
+        ..get_instrument(symbol, pool)?
+    }, pool)
+}
+
+/// Exchange rates used to book trades and value portfolios across currencies.
+pub fn fx_rates() -> impl FxRateSource {
+    StaticFxRates::from_env()
+}
+
+/// Whether `shares` is a positive quantity with no more decimals than `share_precision`.
+pub fn is_valid_quantity(shares: &BigDecimal, share_precision: i32) -> bool {
+    let (_, scale) = shares.normalized().as_bigint_and_exponent();
+    shares > &BigDecimal::from(0) && scale <= i64::from(share_precision)
+}
+
+/// Rejects `shares` unless they're a quantity `symbol` trades in: whole contracts for options, and
+/// no more decimals than the instrument's precision otherwise.
+pub fn check_shares(symbol: &str, shares: &BigDecimal, pool: &PgPool) -> Result<(), TradeError> {
+    let instrument = get_instrument(symbol, pool)?;
+    if is_valid_quantity(shares, instrument.share_precision) {
+        return Ok(());
+    }
+    if get_asset_class(&instrument).trades_in_contracts() {
+        return Err(TradeError::Rejected(format!("shares must be a positive number of whole contracts for {}", symbol)));
+    }
+    Err(TradeError::Rejected(format!(
+        "shares must be positive with at most {} decimals for {}", instrument.share_precision, symbol
+    )))
+}
+
+/// Shares (or contracts) a dollar `amount` buys at `price`, rounded down to the instrument's precision.
+pub fn shares_for_amount(amount: &BigDecimal, price: &str, instrument: &InstrumentEntity) -> BigDecimal {
+    let unit_price = trade_notional(price, &BigDecimal::from(1), instrument.contract_multiplier);
+    if unit_price <= BigDecimal::from(0) {
+        return BigDecimal::from(0);
+    }
+    (amount / unit_price).with_scale_round(i64::from(instrument.share_precision), RoundingMode::Down)
+}
+
+/// Debits the cash ledger for a purchase or credits it with the proceeds of a sale, and
+/// debits the trade's fee as a separate entry.
+fn book_trade(stock: &StocksEntity, conn: &mut PgConnection) -> QueryResult<CashLedgerEntity> {
+    let notional = trade_notional(&stock.price, &stock.shares, stock.multiplier);
+    let (entry_type, amount) = if stock.action_type == "buy" {
+        (LedgerEntryType::TradeDebit, -notional)
+    } else {
+        (LedgerEntryType::TradeCredit, notional)
+    };
+    let entry = repository::create_cash_ledger_entry(NewCashLedgerEntity {
+        entry_type: entry_type.to_string(),
+        amount,
+        description: Some(format!("{} {} {} @ {}", stock.action_type, stock.shares.normalized(), stock.symbol, stock.price)),
+        stock_id: Some(stock.id),
+        user_id: stock.user_id,
+        currency: stock.currency.to_string(),
+    }, conn)?;
+    if stock.fee > BigDecimal::from(0) {
+        repository::create_cash_ledger_entry(NewCashLedgerEntity {
+            entry_type: LedgerEntryType::Fee.to_string(),
+            amount: -stock.fee.clone(),
+            description: Some(format!("Fee on {} {} {}", stock.action_type, stock.shares.normalized(), stock.symbol)),
+            stock_id: Some(stock.id),
+            user_id: stock.user_id,
+            currency: stock.currency.to_string(),
+        }, conn)?;
+    }
+    Ok(entry)
+}
+
+/// Fee charged by `schedule` on a trade: flat, per-share and percentage parts clamped to the
+/// schedule's min/max, plus the regulatory rate on sales, rounded half to even to the cent. No
+/// schedule means no fees. The per-share part is charged per contract for options.
+pub fn calculate_fee(
+    schedule: Option<&FeeScheduleEntity>,
+    action: &str,
+    shares: &BigDecimal,
+    price: &str,
+    multiplier: i32,
+) -> BigDecimal {
+    let Some(schedule) = schedule else {
+        return BigDecimal::from(0);
+    };
+    let notional = trade_notional(price, shares, multiplier);
+    let mut commission = &schedule.flat_fee
+        + &schedule.per_share_fee * shares
+        + &notional * &schedule.percentage_fee / BigDecimal::from(100);
+    if let Some(min_fee) = &schedule.min_fee {
+        if &commission < min_fee {
+            commission = min_fee.clone();
+        }
+    }
+    if let Some(max_fee) = &schedule.max_fee {
+        if &commission > max_fee {
+            commission = max_fee.clone();
+        }
+    }
+    if action != "buy" {
+        commission += &notional * &schedule.sell_fee_rate;
+    }
+    commission.with_scale_round(2, RoundingMode::HalfEven)
+}
+
+pub fn get_cash_balance(user_id: i32, currency: &str, pool: &PgPool) -> Result<BigDecimal, TradeError> {
+    Ok(repository::get_cash_balance(user_id, currency.to_string(), &mut *get_connection(pool)?)?)
+}
+
+/// Records a deposit (positive `amount`) or withdrawal (negative `amount`) of cash.
+pub fn record_cash_movement(
+    user_id: i32,
+    amount: BigDecimal,
+    currency: &str,
+    description: Option<String>,
+    pool: &PgPool,
+) -> Result<CashLedgerEntity, TradeError> {
+    let entry_type = if amount >= BigDecimal::from(0) {
+        LedgerEntryType::Deposit
+    } else {
+        LedgerEntryType::Withdrawal
+    };
+    let entry = repository::create_cash_ledger_entry(NewCashLedgerEntity {
+        entry_type: entry_type.to_string(),
+        amount,
+        description,
+        stock_id: None,
+        user_id,
+        currency: currency.to_string(),
+    }, &mut *get_connection(pool)?)?;
+    publish_trade_event(&TradeEvent::PortfolioChanged { user_id, symbol: None });
+    Ok(entry)
+}
+
+/// Withdraws `amount` of cash, failing without recording it when the user holds less.
+pub fn withdraw_cash(
+    user_id: i32,
+    amount: BigDecimal,
+    currency: &str,
+    description: Option<String>,
+    pool: &PgPool,
+) -> Result<CashLedgerEntity, TradeError> {
+    let entry = get_connection(pool)?.transaction(|conn| {
+        lock_user(user_id, conn)?;
+        let balance = repository::get_cash_balance(user_id, currency.to_string(), conn)?;
+        if balance < amount {
+            return Err(TradeError::Rejected(format!("Insufficient cash: {} {} available", balance, currency)));
+        }
+        Ok(repository::create_cash_ledger_entry(NewCashLedgerEntity {
+            entry_type: LedgerEntryType::Withdrawal.to_string(),
+            amount: -amount,
+            description,
+            stock_id: None,
+            user_id,
+            currency: currency.to_string(),
+        }, conn)?)
+    })?;
+    publish_trade_event(&TradeEvent::PortfolioChanged { user_id, symbol: None });
+    Ok(entry)
+}
+
+/// Recomputes the summary of the position of `user_id` in `symbol` from its trades.
+fn calculate_stock_summary(user_id: i32, symbol: &str, conn: &mut PgConnection) -> QueryResult<()> {
+    let stocks_by_symbol = repository::get_user_stocks_by_symbol(user_id, symbol.to_string(), conn)?;
+    if stocks_by_symbol.is_empty() {
+        return Ok(());
+    }
+    let mut total_price:BigDecimal = "0.0".parse().unwrap();
+    let mut total_percentage_change:BigDecimal = "0.0".parse().unwrap();
+    let mut total_price_to_get_average_price:BigDecimal = "0.0".parse().unwrap();
+    let mut total_shares:BigDecimal = "0.0".parse().unwrap();
+    let mut i:usize = 0;
+    let mut prices: Vec<BigDecimal> = Vec::new();
+    let mut total_fees:BigDecimal = "0.0".parse().unwrap();
+    let mut total_bought:BigDecimal = "0.0".parse().unwrap();
+    while i<stocks_by_symbol.len()
+    {
+        prices.push(stocks_by_symbol[i].price.parse::<BigDecimal>().unwrap());
+        let fee = &stocks_by_symbol[i].fee;
+        total_fees += fee;
+        // Fees raise the cost of purchases and reduce the proceeds of sales.
+        if stocks_by_symbol[i].action_type == "buy" {
+            let cost = trade_notional(
+                &stocks_by_symbol[i].price, &stocks_by_symbol[i].shares, stocks_by_symbol[i].multiplier
+            ) + fee;
+            total_bought += &cost;
+            total_price += cost;
+            total_shares += &stocks_by_symbol[i].shares;
+        } else {
+            total_price -= trade_notional(
+                &stocks_by_symbol[i].price, &stocks_by_symbol[i].shares, stocks_by_symbol[i].multiplier
+            ) - fee;
+            total_shares -= &stocks_by_symbol[i].shares;
+        }
+        total_percentage_change += &stocks_by_symbol[i].percentage_change.parse::<BigDecimal>().unwrap();
+        total_price_to_get_average_price += &stocks_by_symbol[i].price.parse::<BigDecimal>().unwrap();
+        i += 1;
+    }
+    let average_price = total_price_to_get_average_price / stocks_by_symbol.len().to_string().parse::<BigDecimal>().unwrap();
+    let mut profit_loss = total_percentage_change / stocks_by_symbol.len().to_string().parse::<BigDecimal>().unwrap();
+    // Fees paid are a loss on the money put into the position.
+    if total_bought > BigDecimal::from(0) {
+        profit_loss -= total_fees * BigDecimal::from(100) / total_bought;
+    }
+    let prices_by_hour = prices_by_hour_text(symbol.to_string(), conn)?;
+    let total_price_result = total_price / "1".to_string().parse::<BigDecimal>().unwrap();    
+    let new_stocks_summary = NewStocksSummaryEntity{
+        total_value: total_price_result.with_prec(2).to_string(),
+        symbol: symbol.to_string(),
+        shares: total_shares,
+        lowest_price: prices.iter().min().unwrap().with_prec(2).to_string(),
+        highest_price: prices.iter().max().unwrap().with_prec(2).to_string(),
+        average_price: average_price.with_prec(2).to_string(),
+        profit_loss: profit_loss.to_string(),
+        price_by_hours: prices_by_hour.to_string(),
+        user_id
+    };
+    match repository::get_stock_summary(user_id, symbol.to_string(), conn)? {
+        None => repository::create_stock_summary(new_stocks_summary, conn)?,
+        Some(_) => repository::update_stock_summary(new_stocks_summary, conn)?,
+    };
+    Ok(())
+}
+
+/// A position valued in a base currency, with its unrealized P&L split between the move of the
+/// price in the instrument's currency and the move of that currency against the base.
+pub struct PositionValuation {
+    pub currency: String,
+    pub base_currency: String,
+    pub shares: BigDecimal,
+    pub market_value: BigDecimal,
+    pub cost_basis: BigDecimal,
+    pub price_profit_loss: BigDecimal,
+    pub fx_profit_loss: BigDecimal,
+}
+
+/// Values the open position of `user_id` in `symbol` at its last traded price, in `base_currency`.
+///
+/// The cost basis uses average cost, fees included. Trade-time rates are stored against USD,
+/// so the historical cost in other base currencies is crossed through the current USD rate.
+/// Returns `None` without a position or when a rate is missing. The trades are read with `conn`.
+pub fn value_position(
+    user_id: i32,
+    symbol: &str,
+    base_currency: &str,
+    fx: &impl FxRateSource,
+    conn: &mut PgConnection,
+) -> QueryResult<Option<PositionValuation>> {
+    let trades = repository::get_user_stocks_by_symbol(user_id, symbol.to_string(), conn)?;
+    Ok(trades.last().and_then(|last_trade| value_trades(&trades, &last_trade.price, base_currency, fx)))
+}
+
+/// A position marked to a live quote.
+pub struct LiveValuation {
+    /// Last sale price of the quote, in the instrument's currency.
+    pub price: BigDecimal,
+    /// Move of the market value since the previous close, in the base currency.
+    pub day_change: BigDecimal,
+    pub position: PositionValuation,
+}
+
+/// Values the open position in `symbol` at the last sale of `quote` instead of its last traded
+/// price, like [`value_position`] does otherwise. The trades are read with `conn`. Returns `None`
+/// without a position, a rate or a price in the quote.
+pub fn value_position_at_quote(
+    user_id: i32,
+    symbol: &str,
+    quote: &common_utils::StockData,
+    base_currency: &str,
+    fx: &impl FxRateSource,
+    conn: &mut PgConnection,
+) -> QueryResult<Option<LiveValuation>> {
+    let trades = repository::get_user_stocks_by_symbol(user_id, symbol.to_string(), conn)?;
+    Ok(value_trades_at_quote(&trades, quote, base_currency, fx))
+}
+
+fn value_trades_at_quote(
+    trades: &[StocksEntity],
+    quote: &common_utils::StockData,
+    base_currency: &str,
+    fx: &impl FxRateSource,
+) -> Option<LiveValuation> {
+    let multiplier = trades.last()?.multiplier;
+    let price = quote.last_sale_price().parse::<BigDecimal>().ok()?;
+    let position = value_trades(trades, &price.to_string(), base_currency, fx)?;
+    let rate = fx.rate(&position.currency, base_currency)?;
+    // Nasdaq reports an unchanged price as UNCH.
+    let net_change = quote.net_change().parse::<BigDecimal>().unwrap_or_default();
+    let day_change = trade_notional(&net_change.to_string(), &position.shares, multiplier) * rate;
+    Some(LiveValuation {
+        price,
+        day_change: day_change.with_scale_round(2, RoundingMode::HalfEven),
+        position,
+    })
+}
+
+/// Values the position `trades` add up to at `price`, in the instrument's currency.
+fn value_trades(trades: &[StocksEntity], price: &str, base_currency: &str, fx: &impl FxRateSource) -> Option<PositionValuation> {
+    let last_trade = trades.last()?;
+    let currency = last_trade.currency.to_string();
+    let mut shares = BigDecimal::from(0);
+    let mut cost_local = BigDecimal::from(0);
+    let mut cost_usd = BigDecimal::from(0);
+    for trade in trades {
+        if trade.action_type == "buy" {
+            let cost = trade_notional(&trade.price, &trade.shares, trade.multiplier) + &trade.fee;
+            cost_usd += &cost * &trade.fx_rate;
+            cost_local += cost;
+            shares += &trade.shares;
+        } else if shares > BigDecimal::from(0) {
+            let remaining = (&shares - &trade.shares) / &shares;
+            cost_local *= &remaining;
+            cost_usd *= &remaining;
+            shares -= &trade.shares;
+        }
+    }
+    let rate = fx.rate(&currency, base_currency)?;
+    let usd_rate = fx.rate(DEFAULT_CURRENCY, base_currency)?;
+    let market_value_local = trade_notional(price, &shares, last_trade.multiplier);
+    let cost_basis = cost_usd * usd_rate;
+    let converted_cost = &cost_local * &rate;
+    Some(PositionValuation {
+        currency,
+        base_currency: base_currency.to_string(),
+        market_value: (&market_value_local * &rate).with_scale_round(2, RoundingMode::HalfEven),
+        price_profit_loss: ((market_value_local - cost_local) * &rate).with_scale_round(2, RoundingMode::HalfEven),
+        fx_profit_loss: (converted_cost - &cost_basis).with_scale_round(2, RoundingMode::HalfEven),
+        cost_basis: cost_basis.with_scale_round(2, RoundingMode::HalfEven),
+        shares,
+    })
+}
+
+#[derive(Debug, QueryableByName)]
+pub struct StocksByHours {
+   #[diesel(sql_type = Varchar)]
+   pub symbol: String,
+   #[diesel(sql_type = Text)]
+   pub price : String,
+   #[diesel(sql_type = Text)]
+   pub hour : String,
+}
+
+/// Legacy "HH:MI - price" text of `stocks_summary.price_by_hours`, superseded by [`get_price_series`].
+pub fn calculate_prices_by_hour(symbol: String, pool: &PgPool) -> String {
+    prices_by_hour_text(symbol, &mut pool.get().expect("Can't get DB connection")).unwrap()
+}
+
+fn prices_by_hour_text(symbol: String, conn: &mut PgConnection) -> QueryResult<String> {
+    // The symbol comes from API input, so it's bound rather than spliced into the SQL.
+    let query = sql_query(
+        "
+        SELECT symbol,
+        ROUND(SUM(CAST (price AS DECIMAL))/COUNT(price),2) || '' AS price,
+            substring(generate_series((TO_CHAR(MIN(created_at),'yyyy-mm-dd HH24:MI:SS'))::timestamp
+                         ,(TO_CHAR(MAX(created_at),'yyyy-mm-dd HH24:MI:SS'))::timestamp
+                         ,interval '1 hour')::text, 12, 5) AS hour
+        FROM stocks
+        WHERE symbol = $1
+	    GROUP BY symbol"
+    )
+    .bind::<Varchar, _>(symbol);
+    let results = query.load::<StocksByHours>(conn)?;
+    let mut stocks_by_hour = "".to_string();
+    let mut i:usize = 0;
+    while i<results.len()
+    {
+        if i == 0 {
+            stocks_by_hour = format!("{} - {}", results[i].hour.to_string(), results[i].price.to_string());
+        } else {
+            stocks_by_hour = format!("{}, {} - {}", stocks_by_hour, results[i].hour.to_string(), results[i].price.to_string());
+        }
+        i = i + 1;
+    }
+    Ok(stocks_by_hour)
+}
+
+/// Records the last sale price of every listed instrument in the price history. Fails when the
+/// database is unavailable.
+pub fn sample_prices(pool: &PgPool) -> Result<(), TradeError> {
+    let listed_instruments = repository::get_listed_instruments(&mut *get_connection(pool)?)?;
+    for instrument in listed_instruments {
+        let result = common_utils::get_quote_from_nasdaq(
+            instrument.symbol.to_string(), &get_asset_class(&instrument).to_string()
+        );
+        if !result.success {
+            println!("Can't sample the price of {}: {}", instrument.symbol, result.message);
+            continue;
+        }
+        let last_sale_price = result.stock.unwrap().data.primaryData.lastSalePrice.replace("$", "").replace(",", "");
+        match BigDecimal::from_str(&last_sale_price) {
+            Ok(price) => {
+                repository::create_price_sample(NewPriceSampleEntity {
+                    symbol: instrument.symbol.to_string(),
+                    price,
+                }, &mut *get_connection(pool)?)?;
+            }
+            Err(_e) => println!("Invalid price for {}: {}", instrument.symbol, last_sale_price),
+        }
+    }
+    Ok(())
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum CandleResolution {
+    OneMinute,
+    FiveMinutes,
+    OneHour,
+    OneDay,
+}
+
+impl CandleResolution {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            CandleResolution::OneMinute => 60,
+            CandleResolution::FiveMinutes => 5 * 60,
+            CandleResolution::OneHour => 60 * 60,
+            CandleResolution::OneDay => 24 * 60 * 60,
+        }
+    }
+}
+
+/// OHLC candles of the sampled prices of `symbol` between `from` (inclusive) and `to`, in UTC.
+pub fn get_candles(
+    symbol: &str,
+    resolution: CandleResolution,
+    from: NaiveDateTime,
+    to: NaiveDateTime,
+    pool: &PgPool,
+) -> Vec<CandleEntity> {
+    repository::get_candles(
+        symbol.to_string(), resolution.seconds(), from, to, &mut pool.get().expect("Can't get DB connection")
+    ).expect("Error loading candles")
+}
+
+/// Width of the buckets of a price series, named after the `date_trunc` unit computing them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum TimeBucket {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+/// Average, lowest and highest price and shares `user_id` traded in `symbol` per `bucket`, with
+/// buckets starting at the boundaries of `time_zone` (an IANA name such as `America/New_York`).
+/// Fails when Postgres doesn't know the time zone.
+pub fn get_price_series(
+    user_id: i32,
+    symbol: &str,
+    bucket: TimeBucket,
+    time_zone: &str,
+    pool: &PgPool,
+) -> QueryResult<Vec<PriceBucketEntity>> {
+    repository::get_price_series(
+        symbol.to_string(), user_id, bucket.to_string(), time_zone.to_string(), &mut pool.get().expect("Can't get DB connection")
+    )
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum OrderAction {
+    Buy,
+    Sale,
+}
+
+impl OrderAction {
+    /// The action of a queued order or trade, rejecting anything but `buy` and `sale` so an
+    /// unknown action is never booked as a sale.
+    pub fn parse(action: &str) -> Result<Self, TradeError> {
+        OrderAction::from_str(action).map_err(|_| TradeError::Rejected(format!("Unknown action: {}", action)))
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum OrderType {
+    Market,
+    Limit,
+    Stop,
+    StopLimit,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum OrderStatus {
+    Pending,
+    Triggered,
+    Filled,
+    Cancelled,
+    Rejected,
+}
+
+impl OrderStatus {
+    /// Statuses of orders that are still waiting on the market.
+    pub fn open_statuses() -> Vec<String> {
+        vec![OrderStatus::Pending.to_string(), OrderStatus::Triggered.to_string()]
+    }
+}
+
+/// Checks that the exits of a bracket around an entry at `entry_price` are on the profitable and
+/// the losing side of it: above and below for a buy, below and above for a sale.
+pub fn check_bracket_prices(
+    action: &str,
+    entry_price: &str,
+    take_profit: Option<&str>,
+    stop_loss: Option<&str>,
+) -> Result<(), String> {
+    let parse = |price: &str| {
+        price.parse::<BigDecimal>().map_err(|_e| format!("{} is not a valid price", price))
+    };
+    let entry = parse(entry_price)?;
+    let (profit_side, loss_side) = if action == "buy" { ("above", "below") } else { ("below", "above") };
+    if let Some(take_profit) = take_profit {
+        if (action == "buy") != (parse(take_profit)? > entry) {
+            return Err(format!("takeProfit must be {} the entry price of {}", profit_side, entry_price));
+        }
+    }
+    if let Some(stop_loss) = stop_loss {
+        if (action == "buy") != (parse(stop_loss)? < entry) {
+            return Err(format!("stopLoss must be {} the entry price of {}", loss_side, entry_price));
+        }
+    }
+    Ok(())
+}
+
+/// Creates the take-profit (limit) and stop-loss (stop) sales that close a position `user_id`
+/// bought. Both legs share `parent_id`, so filling one cancels the other.
+pub fn create_bracket_orders(
+    parent_id: i32,
+    user_id: i32,
+    symbol: String,
+    shares: BigDecimal,
+    take_profit: Option<String>,
+    stop_loss: Option<String>,
+    pool: &PgPool,
+) -> Vec<OrderEntity> {
+    let mut legs = Vec::new();
+    if let Some(take_profit) = take_profit {
+        legs.push(new_bracket_leg(parent_id, user_id, &symbol, &shares, OrderType::Limit, None, Some(take_profit)));
+    }
+    if let Some(stop_loss) = stop_loss {
+        legs.push(new_bracket_leg(parent_id, user_id, &symbol, &shares, OrderType::Stop, Some(stop_loss), None));
+    }
+    legs.into_iter()
+        .map(|leg| {
+            let order = repository::create_order(leg, &mut pool.get().expect("Can't get DB connection"))
+                .expect("Error to create a bracket order");
+            publish_order_update(&order);
+            order
+        })
+        .collect()
+}
+
+fn new_bracket_leg(
+    parent_id: i32,
+    user_id: i32,
+    symbol: &str,
+    shares: &BigDecimal,
+    order_type: OrderType,
+    stop_price: Option<String>,
+    limit_price: Option<String>,
+) -> NewOrderEntity {
+    NewOrderEntity {
+        symbol: symbol.to_string(),
+        shares: Some(shares.clone()),
+        amount: None,
+        action_type: "sale".to_string(),
+        order_type: order_type.to_string(),
+        stop_price,
+        limit_price,
+        status: OrderStatus::Pending.to_string(),
+        parent_id: Some(parent_id),
+        user_id,
+    }
+}
+
+/// A stop is reached once the market trades through it: upwards for buys, downwards for sales.
+pub fn is_stop_reached(action: &str, stop_price: &BigDecimal, price: &BigDecimal) -> bool {
+    if action == "buy" {
+        price >= stop_price
+    } else {
+        price <= stop_price
+    }
+}
+
+/// A limit is satisfied when the price is no worse than the limit for the order's side.
+pub fn is_limit_satisfied(action: &str, limit_price: &BigDecimal, price: &BigDecimal) -> bool {
+    if action == "buy" {
+        price <= limit_price
+    } else {
+        price >= limit_price
+    }
+}
+
+/// Returns the status an open order moves to at `price`, or `None` if it keeps waiting.
+pub fn next_order_status(order: &OrderEntity, price: &BigDecimal) -> Option<OrderStatus> {
+    let order_type = OrderType::from_str(&order.order_type).ok()?;
+    let stop_price = order.stop_price.as_ref().and_then(|p| p.parse::<BigDecimal>().ok());
+    let limit_price = order.limit_price.as_ref().and_then(|p| p.parse::<BigDecimal>().ok());
+    let stop_reached = stop_price
+        .is_some_and(|stop| is_stop_reached(&order.action_type, &stop, price));
+    let limit_satisfied = limit_price
+        .is_some_and(|limit| is_limit_satisfied(&order.action_type, &limit, price));
+    match order_type {
+        OrderType::Market => Some(OrderStatus::Filled),
+        OrderType::Limit if limit_satisfied => Some(OrderStatus::Filled),
+        OrderType::Stop if stop_reached => Some(OrderStatus::Filled),
+        OrderType::StopLimit => {
+            let triggered = order.status == OrderStatus::Triggered.to_string();
+            if (triggered || stop_reached) && limit_satisfied {
+                Some(OrderStatus::Filled)
+            } else if !triggered && stop_reached {
+                Some(OrderStatus::Triggered)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Checks every open order against the current Nasdaq quote of its symbol and converts the
+/// ones whose conditions are met into trades. Fails when the database is unavailable, leaving the
+/// orders not reached yet for the next evaluation.
+pub fn evaluate_pending_orders(pool: &PgPool) -> Result<(), TradeError> {
+    let open_orders = repository::get_orders_by_status(
+        OrderStatus::open_statuses(), &mut *get_connection(pool)?
+    )?;
+    let mut quotes: HashMap<String, Option<(String, String)>> = HashMap::new();
+    for order in open_orders {
+        if !quotes.contains_key(&order.symbol) {
+            let quote = get_quote(&order.symbol, pool)?
+                .stock
+                .map(|stock| (stock.data.price(), stock.data.percentage_change()));
+            quotes.insert(order.symbol.to_string(), quote);
+        }
+        let Some((price, percentage_change)) = quotes[&order.symbol].clone() else {
+            continue;
+        };
+        let Ok(current_price) = price.parse::<BigDecimal>() else {
+            continue;
+        };
+        match next_order_status(&order, &current_price) {
+            Some(OrderStatus::Filled) => {
+                if let Err(e) = fill_order(&order, price, percentage_change, pool) {
+                    println!("Can't fill order {}: {}", order.id, e);
+                }
+            }
+            Some(new_status) => {
+                let order = repository::update_order_status(
+                    order.id, new_status.to_string(), &mut *get_connection(pool)?
+                )?;
+                publish_order_update(&order);
+            }
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+/// Records the trade for an order at `price`, marks it filled and cancels the other leg of its
+/// bracket in one transaction, and publishes the changes once it commits. Orders no longer open
+/// are left untouched, and orders the user lacks the cash or the shares for are rejected. Fails
+/// without changing anything when the database is unavailable, so that the order can be retried.
+pub fn fill_order(order: &OrderEntity, price: String, percentage_change: String, pool: &PgPool) -> Result<(), TradeError> {
+    let instrument = get_instrument(&order.symbol, pool)?;
+    let shares = match (&order.shares, &order.amount) {
+        (Some(shares), _) => shares.clone(),
+        (None, Some(amount)) => shares_for_amount(amount, &price, &instrument),
+        (None, None) => BigDecimal::from(0),
+    };
+    if shares <= BigDecimal::from(0) {
+        println!("Rejecting order {}: nothing to fill", order.id);
+        return reject_order(order.id, pool);
+    }
+    let filled = get_connection(pool)?.transaction(|conn| {
+        let claimed_order = repository::fill_open_order(
+            order.id,
+            OrderStatus::open_statuses(),
+            OrderStatus::Filled.to_string(),
+            shares.clone(),
+            conn,
+        )?;
+        let Some(filled_order) = claimed_order else {
+            return Ok(None);
+        };
+        let trade = execute_trade(
+            filled_order.user_id,
+            &instrument,
+            shares,
+            price,
+            percentage_change,
+            filled_order.action_type.to_string(),
+            conn,
+        )?;
+        let cancelled = match filled_order.parent_id {
+            Some(parent_id) => repository::cancel_sibling_orders(
+                filled_order.id,
+                parent_id,
+                OrderStatus::open_statuses(),
+                OrderStatus::Cancelled.to_string(),
+                conn,
+            )?,
+            None => Vec::new(),
+        };
+        Ok(Some((filled_order, trade, cancelled)))
+    });
+    match filled {
+        Ok(Some((filled_order, trade, cancelled))) => {
+            publish_trade_executed(&trade);
+            publish_order_update(&filled_order);
+            cancelled.iter().for_each(publish_order_update);
+            Ok(())
+        }
+        Ok(None) => {
+            println!("Order {} is no longer open, skipping it", order.id);
+            Ok(())
+        }
+        Err(TradeError::Rejected(reason)) => {
+            println!("Rejecting order {}: {}", order.id, reason);
+            reject_order(order.id, pool)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Rejects the order unless it was executed or cancelled in the meantime.
+fn reject_order(order_id: i32, pool: &PgPool) -> Result<(), TradeError> {
+    let rejected = repository::update_open_order_status(
+        order_id,
+        OrderStatus::open_statuses(),
+        OrderStatus::Rejected.to_string(),
+        &mut *get_connection(pool)?,
+    )?;
+    if let Some(order) = rejected {
+        publish_order_update(&order);
+    }
+    Ok(())
+}
+
+/// Executes a market order queued through Kafka, using the order row as it is now so that
+/// cancellations and amendments made after queuing are honoured. Fails, leaving the order open,
+/// when the database or Nasdaq is unavailable, so that the message can be retried, and rejects
+/// orders that don't exist.
+pub fn execute_queued_order(order_id: i32, pool: &PgPool) -> Result<(), TradeError> {
+    let order = match repository::get_order(order_id, &mut *get_connection(pool)?) {
+        Ok(order) => order,
+        Err(diesel::result::Error::NotFound) => {
+            return Err(TradeError::Rejected(format!("Order {} doesn't exist", order_id)));
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if !OrderStatus::open_statuses().contains(&order.status) {
+        println!("Order {} is {}, skipping it", order.id, order.status);
+        return Ok(());
+    }
+    match lookup_instrument(&order.symbol, pool) {
+        Ok(_) => {}
+        Err(TradeError::Rejected(reason)) => {
+            println!("Rejecting order {}: {}", order.id, reason);
+            return reject_order(order.id, pool);
+        }
+        Err(e) => return Err(e),
+    }
+    let result = get_quote(&order.symbol, pool)?;
+    let Some(stock) = result.stock else {
+        return Err(TradeError::Unavailable(format!("Can't get a quote for order {}: {}", order.id, result.message)));
+    };
+    fill_order(&order, stock.data.price(), stock.data.percentage_change(), pool)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn fee_schedule(flat_fee: &str, per_share_fee: &str, percentage_fee: &str, sell_fee_rate: &str) -> FeeScheduleEntity {
+        FeeScheduleEntity {
+            id: 1,
+            flat_fee: decimal(flat_fee),
+            per_share_fee: decimal(per_share_fee),
+            percentage_fee: decimal(percentage_fee),
+            min_fee: None,
+            max_fee: None,
+            sell_fee_rate: decimal(sell_fee_rate),
+            user_id: 1,
+        }
+    }
+
+    fn instrument(share_precision: i32, contract_multiplier: i32) -> InstrumentEntity {
+        InstrumentEntity {
+            symbol: "AAPL".to_string(),
+            share_precision,
+            currency: DEFAULT_CURRENCY.to_string(),
+            asset_class: AssetClass::Stocks.to_string(),
+            contract_multiplier,
+            company_name: None,
+            exchange: None,
+            stock_type: None,
+            is_nasdaq_listed: true,
+            is_nasdaq100: false,
+            is_listed: true,
+            refreshed_at: None,
+        }
+    }
+
+    fn trade(action: &str, shares: &str, price: &str, fx_rate: &str) -> StocksEntity {
+        StocksEntity {
+            id: 1,
+            symbol: "SAP".to_string(),
+            shares: decimal(shares),
+            price: price.to_string(),
+            percentage_change: "0".to_string(),
+            action_type: action.to_string(),
+            user_id: 1,
+            fee: BigDecimal::from(0),
+            currency: "EUR".to_string(),
+            fx_rate: decimal(fx_rate),
+            multiplier: 1,
+        }
+    }
+
+    /// A euro worth 1.25 dollars and a pound worth 2.
+    fn fx() -> StaticFxRates {
+        StaticFxRates::new(HashMap::from([
+            ("EUR".to_string(), decimal("1.25")),
+            ("GBP".to_string(), decimal("2")),
+        ]))
+    }
+
+    fn quote(last_sale_price: &str, net_change: &str) -> common_utils::StockData {
+        serde_json::from_value(serde_json::json!({
+            "symbol": "SAP",
+            "companyName": "SAP SE",
+            "stockType": "ADR",
+            "exchange": "NYSE",
+            "isNasdaqListed": false,
+            "isNasdaq100": false,
+            "isHeld": false,
+            "primaryData": {
+                "lastSalePrice": last_sale_price,
+                "netChange": net_change,
+                "percentageChange": "N/A",
+                "deltaIndicator": "",
+                "lastTradeTimestamp": "",
+                "isRealTime": true,
+                "bidPrice": "N/A",
+                "askPrice": "N/A",
+                "bidSize": "N/A",
+                "askSize": "N/A",
+                "volume": "0"
+            },
+            "secondaryData": null,
+            "marketStatus": "Open",
+            "assetClass": "STOCKS",
+            "keyStats": {
+                "fiftyTwoWeekHighLow": { "label": "", "value": "N/A" },
+                "dayrange": { "label": "", "value": "N/A" }
+            },
+            "notifications": []
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn trades_without_a_fee_schedule_are_free() {
+        assert_eq!(BigDecimal::from(0), calculate_fee(None, "buy", &decimal("10"), "100.00", 1));
+        assert_eq!(BigDecimal::from(0), calculate_fee(None, "sale", &decimal("10"), "100.00", 1));
+    }
+
+    #[test]
+    fn fees_round_half_to_even_to_the_cent() {
+        for (flat_fee, fee) in [("0.125", "0.12"), ("0.135", "0.14"), ("0.145", "0.14"), ("0.1251", "0.13"), ("0.005", "0.00")] {
+            let schedule = fee_schedule(flat_fee, "0", "0", "0");
+            assert_eq!(decimal(fee), calculate_fee(Some(&schedule), "buy", &decimal("1"), "100.00", 1), "{}", flat_fee);
+        }
+        // 0.125% of 100.20 is 0.12525, above the half cent.
+        let schedule = fee_schedule("0", "0", "0.125", "0");
+        assert_eq!(decimal("0.13"), calculate_fee(Some(&schedule), "buy", &decimal("10"), "10.02", 1));
+    }
+
+    #[test]
+    fn commissions_are_clamped_before_the_sale_fee() {
+        let schedule = FeeScheduleEntity {
+            min_fee: Some(decimal("1.00")),
+            max_fee: Some(decimal("5.00")),
+            ..fee_schedule("0", "0.01", "0", "0.0000278")
+        };
+        assert_eq!(decimal("1.00"), calculate_fee(Some(&schedule), "buy", &decimal("10"), "100.00", 1));
+        assert_eq!(decimal("5.00"), calculate_fee(Some(&schedule), "buy", &decimal("1000"), "10.00", 1));
+        // 5.00 capped, plus 0.278 on 10,000 of proceeds.
+        assert_eq!(decimal("5.28"), calculate_fee(Some(&schedule), "sale", &decimal("1000"), "10.00", 1));
+    }
+
+    #[test]
+    fn options_pay_the_per_share_fee_per_contract() {
+        let schedule = fee_schedule("0", "0.65", "0.1", "0");
+        // 2 contracts of 100 at 1.50: 1.30 per contract and 0.1% of 300.
+        assert_eq!(decimal("1.60"), calculate_fee(Some(&schedule), "buy", &decimal("2"), "1.50", 100));
+    }
+
+    #[test]
+    fn amounts_buy_shares_rounded_down_to_the_precision() {
+        let amount = decimal("1000");
+        assert_eq!(decimal("6.6666"), shares_for_amount(&amount, "150.00", &instrument(4, 1)));
+        assert_eq!(decimal("6"), shares_for_amount(&amount, "150.00", &instrument(0, 1)));
+        assert_eq!(decimal("0.03333333"), shares_for_amount(&amount, "30000", &instrument(8, 1)));
+        assert_eq!(decimal("4"), shares_for_amount(&amount, "250", &instrument(4, 1)));
+    }
+
+    #[test]
+    fn amounts_buy_whole_contracts_of_options() {
+        // A contract of 100 at 1.50 costs 150.
+        assert_eq!(decimal("6"), shares_for_amount(&decimal("1000"), "1.50", &instrument(0, 100)));
+        assert_eq!(decimal("0"), shares_for_amount(&decimal("100"), "1.50", &instrument(0, 100)));
+    }
+
+    #[test]
+    fn amounts_buy_nothing_without_a_price() {
+        for price in ["0", "-1", "N/A"] {
+            assert_eq!(BigDecimal::from(0), shares_for_amount(&decimal("1000"), price, &instrument(4, 1)), "{}", price);
+        }
+    }
+
+    #[test]
+    fn positions_split_their_profit_into_price_and_fx_moves() {
+        // Bought when a euro was worth 1.10 dollars, worth 1.25 now.
+        let trades = [trade("buy", "10", "100", "1.10")];
+
+        let valuation = value_trades(&trades, "120", "USD", &fx()).unwrap();
+
+        assert_eq!(("EUR", "USD"), (valuation.currency.as_str(), valuation.base_currency.as_str()));
+        assert_eq!(decimal("10"), valuation.shares);
+        assert_eq!(decimal("1500.00"), valuation.market_value);
+        assert_eq!(decimal("1100.00"), valuation.cost_basis);
+        assert_eq!(decimal("250.00"), valuation.price_profit_loss);
+        assert_eq!(decimal("150.00"), valuation.fx_profit_loss);
+    }
+
+    #[test]
+    fn costs_in_other_base_currencies_are_crossed_through_the_dollar() {
+        let trades = [trade("buy", "10", "100", "1.10")];
+
+        let valuation = value_trades(&trades, "120", "GBP", &fx()).unwrap();
+
+        // A euro is worth 0.625 pounds and the 1,100 dollar cost 550 pounds.
+        assert_eq!(decimal("750.00"), valuation.market_value);
+        assert_eq!(decimal("550.00"), valuation.cost_basis);
+        assert_eq!(decimal("125.00"), valuation.price_profit_loss);
+        assert_eq!(decimal("75.00"), valuation.fx_profit_loss);
+    }
+
+    #[test]
+    fn sales_reduce_the_cost_basis_at_average_cost() {
+        let trades = [trade("buy", "10", "100", "1.10"), trade("sale", "5", "110", "1.20")];
+
+        let valuation = value_trades(&trades, "120", "USD", &fx()).unwrap();
+
+        assert_eq!(decimal("5"), valuation.shares);
+        assert_eq!(decimal("750.00"), valuation.market_value);
+        assert_eq!(decimal("550.00"), valuation.cost_basis);
+    }
+
+    #[test]
+    fn positions_without_a_rate_are_not_valued() {
+        let trades = [trade("buy", "10", "100", "1.10")];
+        assert!(value_trades(&trades, "120", "XYZ", &fx()).is_none());
+        assert!(value_trades(&[], "120", "USD", &fx()).is_none());
+    }
+
+    #[test]
+    fn live_valuations_convert_the_day_change() {
+        let trades = [trade("buy", "10", "100", "1.10")];
+
+        let valuation = value_trades_at_quote(&trades, &quote("$1,120.00", "+2.00"), "USD", &fx()).unwrap();
+
+        assert_eq!(decimal("1120"), valuation.price);
+        assert_eq!(decimal("14000.00"), valuation.position.market_value);
+        assert_eq!(decimal("25.00"), valuation.day_change);
+        let unchanged = value_trades_at_quote(&trades, &quote("$120.00", "UNCH"), "GBP", &fx()).unwrap();
+        assert_eq!(BigDecimal::from(0), unchanged.day_change);
+        assert_eq!(decimal("750.00"), unchanged.position.market_value);
+        assert!(value_trades_at_quote(&trades, &quote("N/A", "N/A"), "USD", &fx()).is_none());
+    }
+
+    #[test]
+    fn asset_classes_parse_from_their_snake_case_names() {
+        for asset_class in [AssetClass::Stocks, AssetClass::Etf, AssetClass::Index, AssetClass::Crypto, AssetClass::Options] {
+            assert_eq!(Ok(asset_class), AssetClass::from_str(&asset_class.to_string()));
+        }
+        assert_eq!("etf", AssetClass::Etf.to_string());
+        assert!(AssetClass::from_str("ETF").is_err());
+        assert!(AssetClass::from_str("bonds").is_err());
+    }
+
+    #[test]
+    fn unknown_stored_asset_classes_trade_as_stocks() {
+        let stored = |asset_class: &str| InstrumentEntity { asset_class: asset_class.to_string(), ..instrument(4, 1) };
+        assert_eq!(AssetClass::Crypto, get_asset_class(&stored("crypto")));
+        assert_eq!(AssetClass::Stocks, get_asset_class(&stored("bonds")));
+        assert_eq!(AssetClass::Stocks, get_asset_class(&stored("")));
+    }
+
+    #[test]
+    fn quantities_follow_the_default_precision_of_the_class() {
+        let valid = |asset_class: AssetClass, shares: &str| is_valid_quantity(&decimal(shares), asset_class.default_share_precision());
+        assert!(valid(AssetClass::Stocks, "0.0001"));
+        assert!(!valid(AssetClass::Etf, "0.00001"));
+        assert!(valid(AssetClass::Crypto, "0.00000001"));
+        assert!(!valid(AssetClass::Crypto, "0.000000001"));
+        assert!(valid(AssetClass::Options, "3"));
+        assert!(!valid(AssetClass::Options, "1.5"));
+        assert!(!valid(AssetClass::Stocks, "0"));
+        assert!(!valid(AssetClass::Stocks, "-1"));
+    }
+
+    #[test]
+    fn only_options_keep_their_contract_multiplier() {
+        assert_eq!(100, AssetClass::Options.multiplier(100));
+        assert_eq!(10, AssetClass::Options.multiplier(10));
+        for asset_class in [AssetClass::Stocks, AssetClass::Etf, AssetClass::Index, AssetClass::Crypto] {
+            assert_eq!(1, asset_class.multiplier(100), "{}", asset_class);
+        }
+        assert_eq!(decimal("700"), trade_notional("3.50", &decimal("2"), AssetClass::Options.multiplier(100)));
+        assert_eq!(decimal("7"), trade_notional("3.50", &decimal("2"), AssetClass::Stocks.multiplier(100)));
+    }
+
+    fn open_order(order_type: OrderType, action: OrderAction, status: OrderStatus, stop: Option<&str>, limit: Option<&str>) -> OrderEntity {
+        OrderEntity {
+            id: 1,
+            symbol: "AAPL".to_string(),
+            shares: Some(decimal("10")),
+            amount: None,
+            action_type: action.to_string(),
+            order_type: order_type.to_string(),
+            stop_price: stop.map(str::to_string),
+            limit_price: limit.map(str::to_string),
+            status: status.to_string(),
+            parent_id: None,
+            user_id: 1,
+        }
+    }
+
+    #[test]
+    fn stops_are_reached_at_the_stop_price() {
+        let stop = decimal("100");
+        assert!(is_stop_reached("buy", &stop, &decimal("100")));
+        assert!(is_stop_reached("buy", &stop, &decimal("100.01")));
+        assert!(!is_stop_reached("buy", &stop, &decimal("99.99")));
+        assert!(is_stop_reached("sale", &stop, &decimal("100")));
+        assert!(is_stop_reached("sale", &stop, &decimal("99.99")));
+        assert!(!is_stop_reached("sale", &stop, &decimal("100.01")));
+    }
+
+    #[test]
+    fn limits_are_satisfied_at_the_limit_price() {
+        let limit = decimal("100");
+        assert!(is_limit_satisfied("buy", &limit, &decimal("100")));
+        assert!(is_limit_satisfied("buy", &limit, &decimal("99.99")));
+        assert!(!is_limit_satisfied("buy", &limit, &decimal("100.01")));
+        assert!(is_limit_satisfied("sale", &limit, &decimal("100")));
+        assert!(is_limit_satisfied("sale", &limit, &decimal("100.01")));
+        assert!(!is_limit_satisfied("sale", &limit, &decimal("99.99")));
+    }
+
+    #[test]
+    fn market_orders_fill_at_any_price() {
+        for action in [OrderAction::Buy, OrderAction::Sale] {
+            let order = open_order(OrderType::Market, action, OrderStatus::Pending, None, None);
+            assert_eq!(Some(OrderStatus::Filled), next_order_status(&order, &decimal("0.01")));
+        }
+    }
+
+    #[test]
+    fn stop_limits_trigger_before_they_fill() {
+        let pending = open_order(OrderType::StopLimit, OrderAction::Sale, OrderStatus::Pending, Some("100"), Some("98"));
+        // Gapping through the limit triggers the order without filling it.
+        assert_eq!(Some(OrderStatus::Triggered), next_order_status(&pending, &decimal("97")));
+        assert_eq!(Some(OrderStatus::Filled), next_order_status(&pending, &decimal("98")));
+        assert_eq!(None, next_order_status(&pending, &decimal("100.01")));
+
+        let triggered = open_order(OrderType::StopLimit, OrderAction::Sale, OrderStatus::Triggered, Some("100"), Some("98"));
+        // Once triggered the stop no longer matters, only the limit.
+        assert_eq!(None, next_order_status(&triggered, &decimal("97")));
+        assert_eq!(Some(OrderStatus::Filled), next_order_status(&triggered, &decimal("105")));
+    }
+
+    #[test]
+    fn orders_missing_their_trigger_price_keep_waiting() {
+        let limit = open_order(OrderType::Limit, OrderAction::Buy, OrderStatus::Pending, None, None);
+        assert_eq!(None, next_order_status(&limit, &decimal("1")));
+        let stop = open_order(OrderType::Stop, OrderAction::Sale, OrderStatus::Pending, None, Some("100"));
+        assert_eq!(None, next_order_status(&stop, &decimal("1")));
+        let stop_limit = open_order(OrderType::StopLimit, OrderAction::Buy, OrderStatus::Pending, Some("100"), Some("N/A"));
+        assert_eq!(Some(OrderStatus::Triggered), next_order_status(&stop_limit, &decimal("100")));
+    }
+
+    #[test]
+    fn candle_resolutions_span_their_width_in_seconds() {
+        assert_eq!(60, CandleResolution::OneMinute.seconds());
+        assert_eq!(300, CandleResolution::FiveMinutes.seconds());
+        assert_eq!(3_600, CandleResolution::OneHour.seconds());
+        assert_eq!(86_400, CandleResolution::OneDay.seconds());
+        // Each resolution is a whole number of the finer ones, so their candles line up.
+        let resolutions = [CandleResolution::OneMinute, CandleResolution::FiveMinutes, CandleResolution::OneHour, CandleResolution::OneDay];
+        for pair in resolutions.windows(2) {
+            assert_eq!(0, pair[1].seconds() % pair[0].seconds(), "{} in {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn time_buckets_name_their_date_trunc_unit() {
+        let buckets = [
+            (TimeBucket::Minute, "minute"),
+            (TimeBucket::Hour, "hour"),
+            (TimeBucket::Day, "day"),
+            (TimeBucket::Week, "week"),
+            (TimeBucket::Month, "month"),
+        ];
+        for (bucket, unit) in buckets {
+            assert_eq!(unit, bucket.to_string());
+            assert_eq!(Ok(bucket), TimeBucket::from_str(unit));
+        }
+        // Only these units reach the query, so callers can't pick another one.
+        assert!(TimeBucket::from_str("second").is_err());
+        assert!(TimeBucket::from_str("HOUR").is_err());
+    }
+}
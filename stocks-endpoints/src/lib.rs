@@ -0,0 +1,276 @@
+//! Handlers of the REST endpoints that queue, cancel and amend orders, kept apart from the server
+//! loop in main.rs so they can be called without a socket.
+
+use bigdecimal::BigDecimal;
+use common_utils::validation::{ normalize_symbol, parse_price, OrderLimits, ValidationError };
+use stocks_domain::persistence::connection::PgPool;
+use stocks_domain::persistence::model::{ NewOrderEntity, OrderChangeset };
+use stocks_domain::persistence::repository;
+use stocks_domain::stock_functions::{ check_shares, lookup_instrument, publish_order_update, OrderStatus, OrderType, TradeError };
+
+#[macro_use]
+extern crate serde_derive;
+
+#[derive(Serialize, Deserialize)]
+struct BuyStocks {
+    symbol: String,
+    shares: Option<BigDecimal>,
+    //dollars to spend instead of a number of shares, buys only
+    amount: Option<BigDecimal>,
+    #[serde(default = "default_user_id")]
+    user_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OrderReference {
+    order_id: i32,
+    #[serde(default = "default_user_id")]
+    user_id: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct OrderAmendment {
+    order_id: i32,
+    shares: Option<BigDecimal>,
+    stop_price: Option<String>,
+    limit_price: Option<String>,
+    #[serde(default = "default_user_id")]
+    user_id: i32,
+}
+
+//orders are placed by the seeded user unless the body names another one
+fn default_user_id() -> i32 {
+    1
+}
+
+//constants
+const OK_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n";
+const NOT_FOUND: &str = "HTTP/1.1 404 NOT FOUND\r\n\r\n";
+const INTERNAL_SERVER_ERROR: &str = "HTTP/1.1 500 INTERNAL SERVER ERROR\r\n\r\n";
+const BAD_REQUEST: &str = "HTTP/1.1 400 BAD REQUEST\r\n\r\n";
+const INVALID_INPUT: &str = "HTTP/1.1 400 BAD REQUEST\r\nContent-Type: application/json\r\n\r\n";
+
+//route the request to its handler and return the status line and content of the response
+pub fn handle_request(request: &str, pool: &PgPool) -> (String, String) {
+    match request {
+        r if r.starts_with("POST /buy_stocks") => handle_post_request_to_buy_stocks(r, pool),
+        r if r.starts_with("POST /sale_stocks") => handle_post_request_to_sale_stocks(r, pool),
+        r if r.starts_with("POST /cancel_order") => handle_post_request_to_cancel_order(r, pool),
+        r if r.starts_with("POST /amend_order") => handle_post_request_to_amend_order(r, pool),
+        _ => (NOT_FOUND.to_string(), "404 Not Found".to_string()),
+    }
+}
+
+//CONTROLLERS
+fn handle_post_request_to_buy_stocks(request: &str, pool: &PgPool) -> (String, String) {
+    match get_stock_request_body(request) {
+        Ok(mut buy_stock) => {
+            if let Err(e) = validate_order(&mut buy_stock) {
+                return invalid_input(e);
+            }
+            if let Err(e) = lookup_instrument(&buy_stock.symbol, pool) {
+                return trade_error(e);
+            }
+            let quantity = match validate_quantity(&buy_stock, "buy", pool) {
+                Ok(quantity) => quantity,
+                Err(e) => return e,
+            };
+            let order_id = match queue_market_order(&buy_stock, "buy", pool) {
+                Ok(order_id) => order_id,
+                Err(e) => return (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", e)),
+            };
+            common_utils::send_message_to_consumer(buy_stock.symbol.to_string(), quantity, "buy".to_string(), order_id, buy_stock.user_id);
+            let response = format!("Successfully purchased shares with the symbol: {}, order id: {}", buy_stock.symbol, order_id);
+            (OK_RESPONSE.to_string(), response)
+        }
+        _ => (INTERNAL_SERVER_ERROR.to_string(), "The body does not mathc with the request".to_string()),
+    }
+}
+
+fn handle_post_request_to_sale_stocks(request: &str, pool: &PgPool) -> (String, String) {
+    match get_stock_request_body(request) {
+        Ok(mut buy_stock) => {
+            if let Err(e) = validate_order(&mut buy_stock) {
+                return invalid_input(e);
+            }
+            if let Err(e) = lookup_instrument(&buy_stock.symbol, pool) {
+                return trade_error(e);
+            }
+            let quantity = match validate_quantity(&buy_stock, "sale", pool) {
+                Ok(quantity) => quantity,
+                Err(e) => return e,
+            };
+            let order_id = match queue_market_order(&buy_stock, "sale", pool) {
+                Ok(order_id) => order_id,
+                Err(e) => return (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", e)),
+            };
+            common_utils::send_message_to_consumer(buy_stock.symbol.to_string(), quantity, "sale".to_string(), order_id, buy_stock.user_id);
+            let response = format!("Successfully queued the sale of shares with the symbol: {}, order id: {}", buy_stock.symbol, order_id);
+            (OK_RESPONSE.to_string(), response)
+        }
+        _ => (INTERNAL_SERVER_ERROR.to_string(), "The body does not mathc with the request".to_string()),
+    }
+}
+
+fn handle_post_request_to_cancel_order(request: &str, pool: &PgPool) -> (String, String) {
+    match serde_json::from_str::<OrderReference>(get_request_body(request)) {
+        Ok(order) => {
+            let mut conn = match pool.get() {
+                Ok(conn) => conn,
+                Err(e) => return (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", e)),
+            };
+            match repository::update_open_user_order_status(
+                order.order_id,
+                order.user_id,
+                OrderStatus::open_statuses(),
+                OrderStatus::Cancelled.to_string(),
+                &mut conn,
+            ) {
+                Ok(None) => (NOT_FOUND.to_string(), format!("Order {} not found or already executed", order.order_id)),
+                Ok(Some(cancelled)) => {
+                    publish_order_update(&cancelled);
+                    (OK_RESPONSE.to_string(), format!("Order {} cancelled", order.order_id))
+                }
+                Err(e) => (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", e)),
+            }
+        }
+        _ => (INTERNAL_SERVER_ERROR.to_string(), "The body does not mathc with the request".to_string()),
+    }
+}
+
+fn handle_post_request_to_amend_order(request: &str, pool: &PgPool) -> (String, String) {
+    match serde_json::from_str::<OrderAmendment>(get_request_body(request)) {
+        Ok(amendment) => {
+            if amendment.shares.is_none() && amendment.stop_price.is_none() && amendment.limit_price.is_none() {
+                return (BAD_REQUEST.to_string(), "Nothing to amend".to_string());
+            }
+            let stop_price = match amended_price("stop_price", &amendment.stop_price) {
+                Ok(price) => price,
+                Err(e) => return invalid_input(e),
+            };
+            let limit_price = match amended_price("limit_price", &amendment.limit_price) {
+                Ok(price) => price,
+                Err(e) => return invalid_input(e),
+            };
+            let mut conn = match pool.get() {
+                Ok(conn) => conn,
+                Err(e) => return (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", e)),
+            };
+            let order = match repository::get_user_order(amendment.order_id, amendment.user_id, &mut conn) {
+                Ok(order) => order,
+                Err(diesel::result::Error::NotFound) => return (NOT_FOUND.to_string(), format!("Order {} not found", amendment.order_id)),
+                Err(e) => return (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", e)),
+            };
+            if let Some(shares) = &amendment.shares {
+                if order.amount.is_some() {
+                    return (BAD_REQUEST.to_string(), format!("Order {} was placed by amount, its shares can't be amended", amendment.order_id));
+                }
+                if let Err(e) = OrderLimits::from_env().check_shares("shares", shares) {
+                    return invalid_input(e);
+                }
+                if let Err(e) = check_shares(&order.symbol, shares, pool) {
+                    return trade_error(e);
+                }
+            }
+            let changes = OrderChangeset { shares: amendment.shares, stop_price, limit_price };
+            match repository::amend_open_order(
+                amendment.order_id,
+                amendment.user_id,
+                vec![OrderStatus::Pending.to_string()],
+                changes,
+                &mut conn,
+            ) {
+                Ok(None) => (NOT_FOUND.to_string(), format!("Order {} not found or no longer pending", amendment.order_id)),
+                Ok(Some(amended)) => {
+                    publish_order_update(&amended);
+                    (OK_RESPONSE.to_string(), format!("Order {} amended", amendment.order_id))
+                }
+                Err(e) => (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", e)),
+            }
+        }
+        _ => (INTERNAL_SERVER_ERROR.to_string(), "The body does not mathc with the request".to_string()),
+    }
+}
+
+//normalize the symbol and check the quantities against the order limits before the order
+//reaches the database or Nasdaq
+fn validate_order(stock: &mut BuyStocks) -> Result<(), ValidationError> {
+    stock.symbol = normalize_symbol(&stock.symbol)?;
+    let limits = OrderLimits::from_env();
+    if let Some(shares) = &stock.shares {
+        limits.check_shares("shares", shares)?;
+    }
+    if let Some(amount) = &stock.amount {
+        limits.check_amount("amount", amount)?;
+    }
+    Ok(())
+}
+
+//a stop or limit price must be a positive decimal, stored as parsed so the consumer can read it back
+fn amended_price(field: &str, price: &Option<String>) -> Result<Option<String>, ValidationError> {
+    price.as_deref().map(|price| parse_price(field, price).map(|price| price.to_string())).transpose()
+}
+
+//respond with the validation error as JSON so clients can tell which field is wrong
+fn invalid_input(e: ValidationError) -> (String, String) {
+    let content = serde_json::to_string(&e).unwrap_or_else(|_| e.message.to_string());
+    (INVALID_INPUT.to_string(), content)
+}
+
+//respond with the reason a domain check turned the order down, or with a server error when the
+//database or Nasdaq couldn't be reached
+fn trade_error(e: TradeError) -> (String, String) {
+    match e {
+        TradeError::Rejected(reason) => (BAD_REQUEST.to_string(), reason),
+        TradeError::Unavailable(reason) => (INTERNAL_SERVER_ERROR.to_string(), format!("Error: {}", reason)),
+    }
+}
+
+//check that the order has either a valid number of shares or, for buys, a dollar amount, and
+//return the quantity to show in the queued message
+fn validate_quantity(stock: &BuyStocks, action: &str, pool: &PgPool) -> Result<String, (String, String)> {
+    let bad_request = |reason: &str| (BAD_REQUEST.to_string(), reason.to_string());
+    match (&stock.shares, &stock.amount) {
+        (Some(shares), None) => {
+            check_shares(&stock.symbol, shares, pool).map_err(trade_error)?;
+            Ok(shares.to_string())
+        }
+        (None, Some(amount)) if action == "buy" => {
+            if amount <= &BigDecimal::from(0) {
+                return Err(bad_request("amount must be positive"));
+            }
+            Ok(format!("${}", amount))
+        }
+        (None, Some(_)) => Err(bad_request("amount is only supported for buys")),
+        _ => Err(bad_request("Exactly one of shares or amount is required")),
+    }
+}
+
+//record a pending market order so it can be cancelled or amended until the consumer executes it
+fn queue_market_order(stock: &BuyStocks, action: &str, pool: &PgPool) -> Result<i32, String> {
+    let mut conn = pool.get().map_err(|e| e.to_string())?;
+    let order = repository::create_order(NewOrderEntity {
+        symbol: stock.symbol.to_string(),
+        shares: stock.shares.clone(),
+        amount: stock.amount.clone(),
+        action_type: action.to_string(),
+        order_type: OrderType::Market.to_string(),
+        stop_price: None,
+        limit_price: None,
+        status: OrderStatus::Pending.to_string(),
+        parent_id: None,
+        user_id: stock.user_id,
+    }, &mut conn)
+    .map_err(|e| e.to_string())?;
+    publish_order_update(&order);
+    Ok(order.id)
+}
+
+fn get_request_body(request: &str) -> &str {
+    request.split("\r\n\r\n").last().unwrap_or_default()
+}
+
+//deserialize stocks details from request body with the symbol and shares to buy
+fn get_stock_request_body(request: &str) -> Result<BuyStocks, serde_json::Error> {
+    serde_json::from_str(get_request_body(request))
+}
\ No newline at end of file
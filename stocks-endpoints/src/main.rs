@@ -1,22 +1,19 @@
+use std::{ process, thread };
 use std::net::{ TcpListener, TcpStream };
 use std::io::{ Read, Write };
-
-#[macro_use]
-extern crate serde_derive;
-
-#[derive(Serialize, Deserialize)]
-struct BuyStocks {
-    symbol: String,
-    shares: i32,
-}
-
-//constants
-const OK_RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n\r\n";
-const NOT_FOUND: &str = "HTTP/1.1 404 NOT FOUND\r\n\r\n";
-const INTERNAL_SERVER_ERROR: &str = "HTTP/1.1 500 INTERNAL SERVER ERROR\r\n\r\n";
+use signal_hook::consts::{ SIGINT, SIGTERM };
+use signal_hook::iterator::Signals;
+use common_utils::event_bus::{ event_bus, flush_event_bus };
+use stocks_domain::persistence::connection::{ create_connection_pool, PgPool };
+use stocks_endpoints::handle_request;
 
 //main function
 fn main() {
+    //create the producer the orders are published through up front, and flush it on the way out
+    event_bus();
+    flush_on_termination();
+    let pool = create_connection_pool();
+
     //start server and print port
     let listener = TcpListener::bind(format!("0.0.0.0:8080")).unwrap();
     println!("Server started at port 8080");
@@ -25,7 +22,7 @@ fn main() {
     for stream in listener.incoming() {
         match stream {
             Ok(stream) => {
-                handle_client(stream);
+                handle_client(stream, &pool);
             }
             Err(e) => {
                 println!("Error: {}", e);
@@ -34,8 +31,20 @@ fn main() {
     }
 }
 
+//deliver the orders still queued before exiting on SIGINT or SIGTERM
+fn flush_on_termination() {
+    let mut signals = Signals::new([SIGINT, SIGTERM]).expect("Can't listen for termination signals");
+    thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            println!("Shutting down on signal {}", signal);
+            flush_event_bus();
+            process::exit(0);
+        }
+    });
+}
+
 //handle_client function
-fn handle_client(mut stream: TcpStream) {
+fn handle_client(mut stream: TcpStream, pool: &PgPool) {
     let mut buffer = [0; 1024];
     let mut request = String::new();
 
@@ -43,11 +52,7 @@ fn handle_client(mut stream: TcpStream) {
         Ok(size) => {
             request.push_str(String::from_utf8_lossy(&buffer[..size]).as_ref());
 
-            let (status_line, content) = match &*request {
-                r if r.starts_with("POST /buy_stocks") => handle_post_request_to_buy_stocks(r),
-                r if r.starts_with("POST /sale_stocks") => handle_post_request_to_sale_stocks(r),
-                _ => (NOT_FOUND.to_string(), "404 Not Found".to_string()),
-            };
+            let (status_line, content) = handle_request(&request, pool);
 
             stream.write_all(format!("{}{}", status_line, content).as_bytes()).unwrap();
         }
@@ -56,39 +61,3 @@ fn handle_client(mut stream: TcpStream) {
         }
     }
 }
-
-//CONTROLLERS
-fn handle_post_request_to_buy_stocks(request: &str) -> (String, String) {
-    match get_stock_request_body(&request) {
-        Ok(buy_stock) => {
-            let resul = common_utils::get_stock_from_nasdaq(buy_stock.symbol.to_string());
-            if !resul.success {
-                return (INTERNAL_SERVER_ERROR.to_string(), resul.message.to_string());
-            }
-            common_utils::send_message_to_consumer(buy_stock.symbol.to_string(), buy_stock.shares, "buy".to_string());
-            let response = format!("Successfully purchased shares with the symbol: {}", buy_stock.symbol.to_string());
-            (OK_RESPONSE.to_string(), response)
-        }
-        _ => (INTERNAL_SERVER_ERROR.to_string(), "The body does not mathc with the request".to_string()),
-    }
-}
-
-fn handle_post_request_to_sale_stocks(request: &str) -> (String, String) {
-    match get_stock_request_body(&request) {
-        Ok(buy_stock) => {
-            let resul = common_utils::get_stock_from_nasdaq(buy_stock.symbol.to_string());
-            if !resul.success {
-                return (INTERNAL_SERVER_ERROR.to_string(), resul.message.to_string());
-            }
-            common_utils::send_message_to_consumer(buy_stock.symbol.to_string(), buy_stock.shares, "sale".to_string());
-            let response = format!("Successfully purchased shares with the symbol: {}", buy_stock.symbol.to_string());
-            (OK_RESPONSE.to_string(), response)
-        }
-        _ => (INTERNAL_SERVER_ERROR.to_string(), "The body does not mathc with the request".to_string()),
-    }
-}
-
-//deserialize stocks details from request body with the symbol and shares to buy
-fn get_stock_request_body(request: &str) -> Result<BuyStocks, serde_json::Error> {
-    serde_json::from_str(request.split("\r\n\r\n").last().unwrap_or_default())
-}
\ No newline at end of file
@@ -1,22 +1,39 @@
 use std::fmt::{self, Formatter, LowerExp};
 use std::iter::Iterator;
 use std::str::FromStr;
-use std::sync::{Mutex};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use actix_web::web;
 use async_graphql::*;
-use bigdecimal::{BigDecimal, ToPrimitive};
+use bigdecimal::{BigDecimal, RoundingMode, ToPrimitive};
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+use common_utils::fx::{FxRateSource, DEFAULT_CURRENCY};
+use common_utils::trade_events::{OrderSnapshot, TradeEvent, TradeSnapshot};
+use common_utils::validation::{normalize_symbol, parse_price, OrderLimits, ValidationError};
 use futures::{Stream, StreamExt};
-use rdkafka::{Message};
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
 
-use crate::get_conn_from_ctx;
+use crate::{get_conn_from_ctx, get_pool_from_ctx};
 use crate::kafka_sockets;
-use crate::persistence::model::{StocksEntity, NewStocksEntity, UserEntity, StocksSummaryEntity};
+use crate::persistence::model::{CandleEntity, PriceBucketEntity, StocksEntity, UserEntity, StocksSummaryEntity, OrderEntity, NewOrderEntity, OrderChangeset, CashLedgerEntity, FeeScheduleEntity, NewFeeScheduleEntity, InstrumentEntity};
+use crate::persistence::connection::PgPool;
 use crate::persistence::repository;
-use crate::stock_functions::{save_stock, calculate_stock_summary};
+use crate::stock_functions::{
+    save_stock, check_bracket_prices, create_bracket_orders, get_cash_balance, held_shares, record_cash_movement, withdraw_cash,
+    set_share_precision, check_shares, shares_for_amount,
+    get_instrument, set_instrument_currency, fx_rates, value_position, value_position_at_quote, LiveValuation, PositionValuation,
+    get_asset_class, get_quote_async, set_asset_class, lookup_instrument_async, AssetClass, OrderAction, OrderStatus, OrderType,
+    get_candles, get_price_series, trade_notional, publish_order_update, CandleResolution, TimeBucket,
+};
 
 pub type AppSchema = Schema<Query, Mutation, Subscription>;
+
+/// Most candles a single `candles` query returns.
+const MAX_CANDLES: i64 = 1500;
+/// Most instruments a single `searchInstruments` query returns.
+const MAX_SEARCH_RESULTS: i64 = 100;
 pub struct Query;
 
 #[Object]
@@ -42,13 +59,146 @@ impl Query {
         find_user_by_id_internal(ctx, id)
     }
 
-    async fn stocks_summary(&self, ctx: &Context<'_>) -> Vec<StockSummary> {
-        repository::get_stocks_summary(&mut get_conn_from_ctx(ctx))
+    async fn stocks_summary(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = "USD")] base_currency: String,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<Vec<StockSummary>> {
+        let base_currency = known_currency(&base_currency)?;
+        let fx = fx_rates();
+        let pool = get_pool_from_ctx(ctx);
+        repository::get_stocks_summary(user_id, &mut get_conn_from_ctx(ctx))
             .expect("Can't get users")
             .iter()
-            .map(StockSummary::from)
+            .map(|entity| {
+                let mut summary = StockSummary::from(entity);
+                summary.asset_class = get_instrument(&entity.symbol, pool)?.asset_class;
+                summary.valuation = value_position(user_id, &entity.symbol, &base_currency, &fx, &mut get_conn_from_ctx(ctx))?
+                    .map(|valuation| Valuation::from(&valuation));
+                Ok(summary)
+            })
             .collect()
     }
+
+    /// Positions and cash of the user converted to `baseCurrency` at current rates.
+    async fn portfolio(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = "USD")] base_currency: String,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<Portfolio> {
+        let base_currency = known_currency(&base_currency)?;
+        let fx = fx_rates();
+        let mut conn = get_conn_from_ctx(ctx);
+        let zero = || CustomBigDecimal(BigDecimal::from(0));
+        let mut portfolio = Portfolio {
+            base_currency: base_currency.to_string(),
+            cash: zero(),
+            market_value: zero(),
+            cost_basis: zero(),
+            price_profit_loss: zero(),
+            fx_profit_loss: zero(),
+            total_value: zero(),
+        };
+        for summary in repository::get_stocks_summary(user_id, &mut conn)? {
+            let valuation = value_position(user_id, &summary.symbol, &base_currency, &fx, &mut conn)?
+                .ok_or_else(|| Error::new(format!("No FX rate to value {} in {}", summary.symbol, base_currency)))?;
+            portfolio.market_value.0 += valuation.market_value;
+            portfolio.cost_basis.0 += valuation.cost_basis;
+            portfolio.price_profit_loss.0 += valuation.price_profit_loss;
+            portfolio.fx_profit_loss.0 += valuation.fx_profit_loss;
+        }
+        for (currency, balance) in repository::get_cash_balances(user_id, &mut conn)? {
+            let cash = fx.convert(&balance, &currency, &base_currency)
+                .ok_or_else(|| Error::new(format!("No FX rate from {} to {}", currency, base_currency)))?;
+            portfolio.cash.0 += cash.with_scale_round(2, RoundingMode::HalfEven);
+        }
+        portfolio.total_value.0 = &portfolio.cash.0 + &portfolio.market_value.0;
+        Ok(portfolio)
+    }
+
+    async fn cash_balance(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1)] user_id: i32,
+        #[graphql(default = "USD")] currency: String,
+    ) -> Result<String> {
+        Ok(get_cash_balance(user_id, &known_currency(&currency)?, get_pool_from_ctx(ctx))?.to_string())
+    }
+
+    async fn cash_ledger(&self, ctx: &Context<'_>, #[graphql(default = 1)] user_id: i32) -> Vec<LedgerEntry> {
+        repository::get_cash_ledger(user_id, &mut get_conn_from_ctx(ctx))
+            .expect("Can't get cash ledger")
+            .iter()
+            .map(LedgerEntry::from)
+            .collect()
+    }
+
+    async fn fee_schedule(&self, ctx: &Context<'_>, #[graphql(default = 1)] user_id: i32) -> Option<FeeSchedule> {
+        repository::get_fee_schedule(user_id, &mut get_conn_from_ctx(ctx))
+            .expect("Can't get fee schedule")
+            .map(|schedule| FeeSchedule::from(&schedule))
+    }
+
+    /// The instrument for `symbol`, fetched from Nasdaq the first time it's asked for.
+    async fn instrument(&self, ctx: &Context<'_>, symbol: String) -> Result<Instrument> {
+        let instrument = lookup_instrument_async(&valid_symbol(&symbol)?, get_pool_from_ctx(ctx)).await?;
+        Ok(Instrument::from(&instrument))
+    }
+
+    /// Known instruments whose symbol or company name starts with `prefix`, at most `limit`
+    /// (from 1 to 100) of them.
+    async fn search_instruments(
+        &self,
+        ctx: &Context<'_>,
+        prefix: String,
+        #[graphql(default = 20)] limit: i64,
+    ) -> Result<Vec<Instrument>> {
+        if !(1..=MAX_SEARCH_RESULTS).contains(&limit) {
+            return Err(Error::new(format!("limit must be from 1 to {}", MAX_SEARCH_RESULTS)));
+        }
+        Ok(repository::search_instruments(prefix, limit, &mut get_conn_from_ctx(ctx))?
+            .iter()
+            .map(Instrument::from)
+            .collect())
+    }
+
+    /// OHLC candles of the prices sampled for `symbol` from `from` (inclusive) to `to`, in UTC.
+    /// Periods without samples have no candle.
+    async fn candles(
+        &self,
+        ctx: &Context<'_>,
+        symbol: String,
+        resolution: CandleResolution,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> Result<Vec<Candle>> {
+        if from >= to {
+            return Err(Error::new("from must be before to"));
+        }
+        if (to - from).num_seconds() / resolution.seconds() > MAX_CANDLES {
+            return Err(Error::new(format!(
+                "At most {} candles can be requested, use a shorter range or a coarser resolution", MAX_CANDLES
+            )));
+        }
+        Ok(get_candles(&valid_symbol(&symbol)?, resolution, from, to, get_pool_from_ctx(ctx))
+            .iter()
+            .map(Candle::from)
+            .collect())
+    }
+
+    async fn orders(&self, ctx: &Context<'_>, status: Option<OrderStatus>) -> Vec<Order> {
+        let mut conn = get_conn_from_ctx(ctx);
+        match status {
+            Some(status) => repository::get_orders_by_status(vec![status.to_string()], &mut conn),
+            None => repository::get_orders(&mut conn),
+        }
+        .expect("Can't get orders")
+        .iter()
+        .map(Order::from)
+        .collect()
+    }
 }
 
 fn find_user_by_id_internal(ctx: &Context<'_>, id: ID) -> Option<User> {
@@ -65,8 +215,16 @@ pub struct Mutation;
 
 #[Object]
 impl Mutation {
-    async fn buy_stocks(&self, ctx: &Context<'_>, stock: StocksInput) -> Result<Stock> {
-        let result = common_utils::get_stock_from_nasdaq(stock.symbol.to_string());
+    async fn buy_stocks(
+        &self,
+        ctx: &Context<'_>,
+        stock: StocksInput,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<Stock> {
+        let stock = StocksInput { symbol: valid_symbol(&stock.symbol)?, ..stock };
+        let pool = get_pool_from_ctx(ctx);
+        let instrument = lookup_instrument_async(&stock.symbol, pool).await?;
+        let result = get_quote_async(&stock.symbol, pool).await?;
         if !result.success {
             return Err(Error{
                 message: result.message,
@@ -86,54 +244,639 @@ impl Mutation {
         let percentage_change = stock_data.primaryData.percentageChange
             .replace("%", "")
             .replace("+", "");
-        let new_stocks = NewStocksEntity {
-            symbol: stock.symbol.to_string(),
-            shares: stock.shares,
-            price: price.to_string(),
-            percentage_change: percentage_change.to_string(),
-            action_type: "buy".to_string(),
-            user_id: 1,
-        };
-        calculate_stock_summary(
-            stock.symbol.to_string(),
-            stock.shares,
-            price.to_string(),
-            percentage_change.to_string()
-        );
-        let created_stock_entity = repository::create_stock(new_stocks, &mut get_conn_from_ctx(ctx))?;
-        common_utils::send_message_to_consumer(stock.symbol.to_string(), stock.shares, "buy".to_string());
+        let take_profit = stock.take_profit.map(|p| required_price(Some(p), "takeProfit")).transpose()?;
+        let stop_loss = stock.stop_loss.map(|p| required_price(Some(p), "stopLoss")).transpose()?;
+        check_bracket_prices("buy", &price, take_profit.as_deref(), stop_loss.as_deref()).map_err(Error::new)?;
+        // Diesel blocks, so the purchase is booked on the blocking thread pool.
+        let pool = pool.clone();
+        let created_stock_entity = web::block(move || -> Result<StocksEntity> {
+            let shares = order_quantity(&stock.symbol, stock.shares, stock.amount, &price, &pool)?;
+            OrderLimits::from_env()
+                .check_notional(&trade_notional(&price, &shares, instrument.contract_multiplier))
+                .map_err(invalid_input)?;
+            let created_stock_entity = save_stock(
+                user_id,
+                stock.symbol.to_string(),
+                shares.clone(),
+                price.to_string(),
+                percentage_change.to_string(),
+                "buy".to_string(),
+                &pool,
+            ).map_err(|e| Error::new(e.to_string()))?;
+            // The purchase is already executed here, so the queued order is recorded as filled
+            // and the consumer only acknowledges it.
+            let order = repository::create_order(NewOrderEntity {
+                symbol: stock.symbol.to_string(),
+                shares: Some(shares.clone()),
+                amount: None,
+                action_type: "buy".to_string(),
+                order_type: OrderType::Market.to_string(),
+                stop_price: None,
+                limit_price: None,
+                status: OrderStatus::Filled.to_string(),
+                parent_id: None,
+                user_id,
+            }, &mut *pool.get()?)?;
+            publish_order_update(&order);
+            if take_profit.is_some() || stop_loss.is_some() {
+                create_bracket_orders(
+                    order.id,
+                    order.user_id,
+                    stock.symbol.to_string(),
+                    shares.clone(),
+                    take_profit,
+                    stop_loss,
+                    &pool,
+                );
+            }
+            common_utils::send_message_to_consumer(stock.symbol.to_string(), shares.to_string(), "buy".to_string(), order.id, order.user_id);
+            Ok(created_stock_entity)
+        })
+        .await??;
         Ok(Stock::from(&created_stock_entity))
     }
+
+    async fn place_order(
+        &self,
+        ctx: &Context<'_>,
+        order: OrderInput,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<Order> {
+        let order = OrderInput { symbol: valid_symbol(&order.symbol)?, ..order };
+        let pool = get_pool_from_ctx(ctx);
+        let (stop_price, limit_price) = match order.order_type {
+            OrderType::Market => {
+                return Err(Error::new("Market orders are placed with buyStocks"));
+            }
+            OrderType::Limit => (None, Some(required_price(order.limit_price, "limitPrice")?)),
+            OrderType::Stop => (Some(required_price(order.stop_price, "stopPrice")?), None),
+            OrderType::StopLimit => (
+                Some(required_price(order.stop_price, "stopPrice")?),
+                Some(required_price(order.limit_price, "limitPrice")?),
+            ),
+        };
+        let shares = match (order.shares, &order.amount) {
+            (Some(CustomBigDecimal(shares)), None) => {
+                check_quantity(&order.symbol, &shares, pool)?;
+                // Checked again when the order fills, in case the position shrank in between.
+                if order.action == OrderAction::Sale {
+                    let held = held_shares(user_id, &order.symbol, pool)?;
+                    if held < shares {
+                        return Err(Error::new(format!(
+                            "Insufficient shares: {} {} to sell, {} held", shares.normalized(), order.symbol, held.normalized()
+                        )));
+                    }
+                }
+                Some(shares)
+            }
+            (None, Some(CustomBigDecimal(amount))) if order.action == OrderAction::Buy => {
+                OrderLimits::from_env().check_amount("amount", amount).map_err(invalid_input)?;
+                None
+            }
+            (None, Some(_)) => return Err(Error::new("amount is only supported for buys")),
+            _ => return Err(Error::new("Exactly one of shares or amount is required")),
+        };
+        lookup_instrument_async(&order.symbol, pool).await?;
+        let new_order = NewOrderEntity {
+            symbol: order.symbol.to_string(),
+            shares,
+            amount: order.amount.map(|CustomBigDecimal(amount)| amount),
+            action_type: order.action.to_string(),
+            order_type: order.order_type.to_string(),
+            stop_price,
+            limit_price,
+            status: OrderStatus::Pending.to_string(),
+            parent_id: None,
+            user_id,
+        };
+        let created_order = repository::create_order(new_order, &mut get_conn_from_ctx(ctx))?;
+        publish_order_update(&created_order);
+        Ok(Order::from(&created_order))
+    }
+
+    async fn deposit(
+        &self,
+        ctx: &Context<'_>,
+        amount: String,
+        #[graphql(default = "USD")] currency: String,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<LedgerEntry> {
+        let amount = positive_amount(&amount)?;
+        let currency = known_currency(&currency)?;
+        let entry = record_cash_movement(user_id, amount, &currency, Some("Deposit".to_string()), get_pool_from_ctx(ctx))?;
+        Ok(LedgerEntry::from(&entry))
+    }
+
+    async fn withdraw(
+        &self,
+        ctx: &Context<'_>,
+        amount: String,
+        #[graphql(default = "USD")] currency: String,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<LedgerEntry> {
+        let amount = positive_amount(&amount)?;
+        let currency = known_currency(&currency)?;
+        let entry = withdraw_cash(user_id, amount, &currency, Some("Withdrawal".to_string()), get_pool_from_ctx(ctx))
+            .map_err(|e| Error::new(e.to_string()))?;
+        Ok(LedgerEntry::from(&entry))
+    }
+
+    async fn set_fee_schedule(
+        &self,
+        ctx: &Context<'_>,
+        schedule: FeeScheduleInput,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<FeeSchedule> {
+        let optional_fee = |fee: Option<String>, field: &str| {
+            fee.map(|fee| non_negative_amount(&fee, field)).transpose()
+        };
+        let new_schedule = NewFeeScheduleEntity {
+            flat_fee: non_negative_amount(&schedule.flat_fee, "flatFee")?,
+            per_share_fee: non_negative_amount(&schedule.per_share_fee, "perShareFee")?,
+            percentage_fee: non_negative_amount(&schedule.percentage_fee, "percentageFee")?,
+            min_fee: optional_fee(schedule.min_fee, "minFee")?,
+            max_fee: optional_fee(schedule.max_fee, "maxFee")?,
+            sell_fee_rate: non_negative_amount(&schedule.sell_fee_rate, "sellFeeRate")?,
+            user_id,
+        };
+        if let (Some(min_fee), Some(max_fee)) = (&new_schedule.min_fee, &new_schedule.max_fee) {
+            if min_fee > max_fee {
+                return Err(Error::new("minFee can't be greater than maxFee"));
+            }
+        }
+        let saved_schedule = repository::save_fee_schedule(new_schedule, &mut get_conn_from_ctx(ctx))?;
+        Ok(FeeSchedule::from(&saved_schedule))
+    }
+
+    async fn set_share_precision(&self, ctx: &Context<'_>, symbol: String, share_precision: i32) -> Result<Instrument> {
+        let symbol = valid_symbol(&symbol)?;
+        let pool = get_pool_from_ctx(ctx);
+        if !(0..=8).contains(&share_precision) {
+            return Err(Error::new("sharePrecision must be between 0 and 8"));
+        }
+        if share_precision != 0 && get_asset_class(&get_instrument(&symbol, pool)?).trades_in_contracts() {
+            return Err(Error::new(format!("{} trades in whole contracts", symbol)));
+        }
+        let instrument = set_share_precision(&symbol, share_precision, pool)?;
+        Ok(Instrument::from(&instrument))
+    }
+
+    /// Sets the asset class an instrument is quoted and validated as. `contractMultiplier` only
+    /// applies to options.
+    async fn set_asset_class(
+        &self,
+        ctx: &Context<'_>,
+        symbol: String,
+        asset_class: AssetClass,
+        #[graphql(default = 100)] contract_multiplier: i32,
+    ) -> Result<Instrument> {
+        if contract_multiplier <= 0 {
+            return Err(Error::new("contractMultiplier must be positive"));
+        }
+        let instrument = set_asset_class(&valid_symbol(&symbol)?, asset_class, contract_multiplier, get_pool_from_ctx(ctx))?;
+        Ok(Instrument::from(&instrument))
+    }
+
+    /// Sets the currency an instrument trades and settles in.
+    async fn set_instrument_currency(&self, ctx: &Context<'_>, symbol: String, currency: String) -> Result<Instrument> {
+        let instrument = set_instrument_currency(&valid_symbol(&symbol)?, &known_currency(&currency)?, get_pool_from_ctx(ctx))?;
+        Ok(Instrument::from(&instrument))
+    }
+
+    async fn cancel_order(&self, ctx: &Context<'_>, id: ID, #[graphql(default = 1)] user_id: i32) -> Result<Order> {
+        let order_id = parse_order_id(&id)?;
+        repository::update_open_user_order_status(
+            order_id,
+            user_id,
+            OrderStatus::open_statuses(),
+            OrderStatus::Cancelled.to_string(),
+            &mut get_conn_from_ctx(ctx),
+        )?
+        .map(|order| {
+            publish_order_update(&order);
+            Order::from(&order)
+        })
+        .ok_or_else(|| Error::new(format!("Order {} not found or already executed", order_id)))
+    }
+
+    async fn amend_order(
+        &self,
+        ctx: &Context<'_>,
+        id: ID,
+        amendment: OrderAmendmentInput,
+        #[graphql(default = 1)] user_id: i32,
+    ) -> Result<Order> {
+        let order_id = parse_order_id(&id)?;
+        if amendment.shares.is_none() && amendment.stop_price.is_none() && amendment.limit_price.is_none() {
+            return Err(Error::new("Nothing to amend"));
+        }
+        let order = repository::get_user_order(order_id, user_id, &mut get_conn_from_ctx(ctx))
+            .map_err(|_| Error::new(format!("Order {} not found", order_id)))?;
+        let shares = amendment.shares.map(|CustomBigDecimal(shares)| shares);
+        if let Some(shares) = &shares {
+            if order.amount.is_some() {
+                return Err(Error::new(format!("Order {} was placed by amount, its shares can't be amended", order_id)));
+            }
+            check_quantity(&order.symbol, shares, get_pool_from_ctx(ctx))?;
+        }
+        let changes = OrderChangeset {
+            shares,
+            stop_price: amendment.stop_price.map(|p| required_price(Some(p), "stopPrice")).transpose()?,
+            limit_price: amendment.limit_price.map(|p| required_price(Some(p), "limitPrice")).transpose()?,
+        };
+        repository::amend_open_order(
+            order_id,
+            user_id,
+            vec![OrderStatus::Pending.to_string()],
+            changes,
+            &mut get_conn_from_ctx(ctx),
+        )?
+        .map(|order| {
+            publish_order_update(&order);
+            Order::from(&order)
+        })
+        .ok_or_else(|| Error::new(format!("Order {} not found or no longer pending", order_id)))
+    }
+}
+
+fn check_quantity(symbol: &str, shares: &BigDecimal, pool: &PgPool) -> Result<()> {
+    OrderLimits::from_env().check_shares("shares", shares).map_err(invalid_input)?;
+    Ok(check_shares(symbol, shares, pool)?)
+}
+
+/// Resolves the shares of an order given either as a quantity or as a dollar amount to
+/// spend at `price`.
+fn order_quantity(
+    symbol: &str,
+    shares: Option<CustomBigDecimal>,
+    amount: Option<CustomBigDecimal>,
+    price: &str,
+    pool: &PgPool,
+) -> Result<BigDecimal> {
+    match (shares, amount) {
+        (Some(CustomBigDecimal(shares)), None) => {
+            check_quantity(symbol, &shares, pool)?;
+            Ok(shares)
+        }
+        (None, Some(CustomBigDecimal(amount))) => {
+            OrderLimits::from_env().check_amount("amount", &amount).map_err(invalid_input)?;
+            let shares = shares_for_amount(&amount, price, &get_instrument(symbol, pool)?);
+            if shares <= BigDecimal::from(0) {
+                return Err(Error::new(format!("{} doesn't buy any shares of {} at {}", amount, symbol, price)));
+            }
+            Ok(shares)
+        }
+        _ => Err(Error::new("Exactly one of shares or amount is required")),
+    }
+}
+
+fn valid_symbol(symbol: &str) -> Result<String> {
+    normalize_symbol(symbol).map_err(invalid_input)
+}
+
+/// The error for rejected input, with the `code` and `field` of the failure in its extensions.
+fn invalid_input(e: ValidationError) -> Error {
+    Error::new(e.message.to_string()).extend_with(|_, extensions| {
+        extensions.set("code", e.code.as_str());
+        extensions.set("field", e.field);
+    })
+}
+
+/// Normalizes a currency code, rejecting the ones without an FX rate.
+fn known_currency(currency: &str) -> Result<String> {
+    let currency = currency.trim().to_uppercase();
+    if fx_rates().rate(&currency, DEFAULT_CURRENCY).is_none() {
+        return Err(Error::new(format!("Unsupported currency: {}", currency)));
+    }
+    Ok(currency)
+}
+
+fn parse_order_id(id: &ID) -> Result<i32> {
+    id.parse::<i32>()
+        .map_err(|_| Error::new(format!("Invalid order id: {}", id.as_str())))
+}
+
+fn positive_amount(amount: &str) -> Result<BigDecimal> {
+    let amount = BigDecimal::from_str(amount)
+        .map_err(|_| Error::new(format!("Invalid amount: {}", amount)))?;
+    if amount <= BigDecimal::from(0) {
+        return Err(Error::new("amount must be positive"));
+    }
+    Ok(amount)
+}
+
+fn non_negative_amount(amount: &str, field: &str) -> Result<BigDecimal> {
+    let amount = BigDecimal::from_str(amount)
+        .map_err(|_| Error::new(format!("{} is not a valid amount", field)))?;
+    if amount < BigDecimal::from(0) {
+        return Err(Error::new(format!("{} can't be negative", field)));
+    }
+    Ok(amount)
+}
+
+fn required_price(price: Option<String>, field: &str) -> Result<String> {
+    let price = price.ok_or_else(|| Error::new(format!("{} is required for this order type", field)))?;
+    parse_price(field, &price).map_err(|e| Error::new(e.message))?;
+    Ok(price)
 }
 
 pub struct Subscription;
 
 #[Subscription]
 impl Subscription {
-    async fn latest_user<'ctx>(
+    /// Orders of the user as they're placed, amended, triggered, filled, rejected or cancelled.
+    async fn order_updates(
         &self,
-        ctx: &'ctx Context<'_>,
-    ) -> impl Stream<Item = User> + 'ctx {
-        let kafka_consumer_counter = ctx
-            .data::<Mutex<i32>>()
-            .expect("Can't get Kafka consumer counter");
-        let consumer_group_id = kafka_sockets::get_kafka_consumer_group_id(kafka_consumer_counter);
-        let consumer = kafka_sockets::create_consumer(consumer_group_id);
-
-        async_stream::stream! {
-            let mut stream = consumer.stream();
-
-            while let Some(value) = stream.next().await {
-                yield match value {
-                    Ok(message) => {
-                        let payload = message.payload().expect("Kafka message should contain payload");
-                        let message = String::from_utf8_lossy(payload).to_string();
-                        serde_json::from_str(&message).expect("Can't deserialize a user")
-                    }
-                    Err(e) => panic!("Error while Kafka message processing: {}", e)
+        ctx: &Context<'_>,
+        #[graphql(default = 1)] user_id: i32,
+        symbol: Option<String>,
+    ) -> Result<impl Stream<Item = Order>> {
+        let events = trade_events(ctx, user_id, symbol)?;
+        Ok(events.filter_map(|event| async move {
+            match event {
+                TradeEvent::OrderUpdated { order } => Some(Order::from(&order)),
+                _ => None,
+            }
+        }))
+    }
+
+    /// Trades recorded for the user.
+    async fn trade_executed(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1)] user_id: i32,
+        symbol: Option<String>,
+    ) -> Result<impl Stream<Item = Stock>> {
+        let events = trade_events(ctx, user_id, symbol)?;
+        Ok(events.filter_map(|event| async move {
+            match event {
+                TradeEvent::TradeExecuted { trade } => Some(Stock::from(&trade)),
+                _ => None,
+            }
+        }))
+    }
+
+    /// Changes to the positions and cash of the user. Changes to cash only reach the
+    /// subscriptions without a symbol.
+    async fn portfolio_changed(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = 1)] user_id: i32,
+        symbol: Option<String>,
+    ) -> Result<impl Stream<Item = PortfolioChange>> {
+        let events = trade_events(ctx, user_id, symbol)?;
+        Ok(events.filter_map(|event| async move {
+            match event {
+                TradeEvent::PortfolioChanged { user_id, symbol } => Some(PortfolioChange { user_id, symbol }),
+                _ => None,
+            }
+        }))
+    }
+
+    /// The holdings of the user re-priced at live quotes, now and then every `intervalSecs` seconds.
+    async fn portfolio_valuation(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default = "USD")] base_currency: String,
+        #[graphql(default = 1)] user_id: i32,
+        #[graphql(default = 30)] interval_secs: i32,
+    ) -> Result<impl Stream<Item = PortfolioValuation>> {
+        let base_currency = known_currency(&base_currency)?;
+        if interval_secs <= 0 {
+            return Err(Error::new("intervalSecs must be positive"));
+        }
+        let interval = Duration::from_secs(interval_secs as u64);
+        let pool = ctx.data::<Arc<PgPool>>().expect("Can't get pool").clone();
+
+        Ok(async_stream::stream! {
+            loop {
+                match value_portfolio_at_quotes(&pool, user_id, &base_currency).await {
+                    Ok(valuation) => yield valuation,
+                    Err(e) => println!("Can't value the portfolio of user {}: {}", user_id, e.message),
+                }
+                actix_rt::time::sleep(interval).await;
+            }
+        })
+    }
+}
+
+/// Marks the open positions of `user_id` to live quotes. Positions without a quote or a rate to
+/// `base_currency` are left out of the totals and listed as unpriced.
+async fn value_portfolio_at_quotes(pool: &Arc<PgPool>, user_id: i32, base_currency: &str) -> Result<PortfolioValuation> {
+    // Diesel blocks, so the database is read on the blocking thread pool.
+    let summaries = {
+        let pool = pool.clone();
+        web::block(move || -> Result<Vec<StocksSummaryEntity>> {
+            Ok(repository::get_stocks_summary(user_id, &mut *pool.get()?)?
+                .into_iter()
+                .filter(|summary| summary.shares != BigDecimal::from(0))
+                .collect())
+        })
+        .await??
+    };
+    let quotes = futures::future::join_all(summaries.iter().map(|summary| get_quote_async(&summary.symbol, pool))).await;
+    let valuations = {
+        let (pool, base_currency) = (pool.clone(), base_currency.to_string());
+        web::block(move || -> Result<Vec<(String, Option<LiveValuation>)>> {
+            let mut conn = pool.get()?;
+            let fx = fx_rates();
+            summaries.into_iter().zip(quotes).map(|(summary, quote)| {
+                let valuation = match quote?.stock {
+                    Some(stock) => value_position_at_quote(user_id, &summary.symbol, &stock.data, &base_currency, &fx, &mut conn)?,
+                    None => None,
                 };
+                Ok((summary.symbol, valuation))
+            }).collect()
+        })
+        .await??
+    };
+    let zero = || CustomBigDecimal(BigDecimal::from(0));
+    let mut portfolio = PortfolioValuation {
+        base_currency: base_currency.to_string(),
+        valued_at: Utc::now().naive_utc(),
+        positions: Vec::new(),
+        unpriced_symbols: Vec::new(),
+        market_value: zero(),
+        day_change: zero(),
+        cost_basis: zero(),
+        unrealized_profit_loss: zero(),
+    };
+    for (symbol, valuation) in valuations {
+        let Some(valuation) = valuation else {
+            portfolio.unpriced_symbols.push(symbol);
+            continue;
+        };
+        let position = LivePosition::new(&symbol, &valuation);
+        portfolio.market_value.0 += &position.market_value.0;
+        portfolio.day_change.0 += &position.day_change.0;
+        portfolio.cost_basis.0 += &position.cost_basis.0;
+        portfolio.unrealized_profit_loss.0 += &position.unrealized_profit_loss.0;
+        portfolio.positions.push(position);
+    }
+    Ok(portfolio)
+}
+
+/// Trade events published from now on about `user_id` and, when given, `symbol`.
+fn trade_events(ctx: &Context<'_>, user_id: i32, symbol: Option<String>) -> Result<impl Stream<Item = TradeEvent>> {
+    let symbol = symbol.map(|symbol| valid_symbol(&symbol)).transpose()?;
+    let kafka_consumer_counter = ctx
+        .data::<Mutex<i32>>()
+        .expect("Can't get Kafka consumer counter");
+    let consumer_group_id = kafka_sockets::get_kafka_consumer_group_id(kafka_consumer_counter);
+    let mut payloads = kafka_sockets::subscribe(consumer_group_id);
+
+    Ok(async_stream::stream! {
+        while let Some(payload) = payloads.recv().await {
+            match serde_json::from_slice::<TradeEvent>(&payload) {
+                Ok(event) if event.concerns(user_id, symbol.as_deref()) => yield event,
+                Ok(_) => {}
+                Err(e) => println!("Skipping invalid trade event {:?}: {}", String::from_utf8_lossy(&payload), e),
             }
         }
+    })
+}
+
+/// The open positions of a user marked to live quotes, in `baseCurrency`.
+struct PortfolioValuation {
+    base_currency: String,
+    valued_at: NaiveDateTime,
+    positions: Vec<LivePosition>,
+    unpriced_symbols: Vec<String>,
+    market_value: CustomBigDecimal,
+    day_change: CustomBigDecimal,
+    cost_basis: CustomBigDecimal,
+    unrealized_profit_loss: CustomBigDecimal,
+}
+
+#[Object]
+impl PortfolioValuation {
+    async fn base_currency(&self) -> &String {
+        &self.base_currency
+    }
+
+    async fn valued_at(&self) -> &NaiveDateTime {
+        &self.valued_at
+    }
+
+    async fn positions(&self) -> &Vec<LivePosition> {
+        &self.positions
+    }
+
+    /// Positions left out of the totals, for lack of a quote or an FX rate.
+    async fn unpriced_symbols(&self) -> &Vec<String> {
+        &self.unpriced_symbols
+    }
+
+    async fn market_value(&self) -> &CustomBigDecimal {
+        &self.market_value
+    }
+
+    /// Move of the market value since the previous close.
+    async fn day_change(&self) -> &CustomBigDecimal {
+        &self.day_change
+    }
+
+    async fn cost_basis(&self) -> &CustomBigDecimal {
+        &self.cost_basis
+    }
+
+    /// Market value less cost basis, from both price and FX moves.
+    async fn unrealized_profit_loss(&self) -> &CustomBigDecimal {
+        &self.unrealized_profit_loss
+    }
+}
+
+struct LivePosition {
+    symbol: String,
+    shares: CustomBigDecimal,
+    price: CustomBigDecimal,
+    currency: String,
+    market_value: CustomBigDecimal,
+    day_change: CustomBigDecimal,
+    cost_basis: CustomBigDecimal,
+    unrealized_profit_loss: CustomBigDecimal,
+}
+
+impl LivePosition {
+    fn new(symbol: &str, valuation: &LiveValuation) -> Self {
+        let position = &valuation.position;
+        LivePosition {
+            symbol: symbol.to_string(),
+            shares: CustomBigDecimal(position.shares.clone()),
+            price: CustomBigDecimal(valuation.price.clone()),
+            currency: position.currency.clone(),
+            market_value: CustomBigDecimal(position.market_value.clone()),
+            day_change: CustomBigDecimal(valuation.day_change.clone()),
+            cost_basis: CustomBigDecimal(position.cost_basis.clone()),
+            unrealized_profit_loss: CustomBigDecimal(&position.market_value - &position.cost_basis),
+        }
+    }
+}
+
+#[Object]
+impl LivePosition {
+    async fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    async fn shares(&self) -> &CustomBigDecimal {
+        &self.shares
+    }
+
+    /// Last sale price, in the instrument's currency.
+    async fn price(&self) -> &CustomBigDecimal {
+        &self.price
+    }
+
+    async fn currency(&self) -> &String {
+        &self.currency
+    }
+
+    async fn market_value(&self) -> &CustomBigDecimal {
+        &self.market_value
+    }
+
+    async fn day_change(&self) -> &CustomBigDecimal {
+        &self.day_change
+    }
+
+    async fn cost_basis(&self) -> &CustomBigDecimal {
+        &self.cost_basis
+    }
+
+    async fn unrealized_profit_loss(&self) -> &CustomBigDecimal {
+        &self.unrealized_profit_loss
+    }
+}
+
+/// A change to the position of a user in a symbol, or to their cash when there's no symbol,
+/// resolved to the portfolio as it is now.
+struct PortfolioChange {
+    user_id: i32,
+    symbol: Option<String>,
+}
+
+#[Object]
+impl PortfolioChange {
+    async fn user_id(&self) -> ID {
+        self.user_id.into()
+    }
+
+    async fn symbol(&self) -> &Option<String> {
+        &self.symbol
+    }
+
+    /// The position in `symbol`, unless none was ever opened.
+    async fn position(&self, ctx: &Context<'_>) -> Option<StockSummary> {
+        let symbol = self.symbol.as_ref()?;
+        repository::get_stock_summary(self.user_id, symbol.to_string(), &mut get_conn_from_ctx(ctx))
+            .expect("Can't get the position")
+            .as_ref()
+            .map(StockSummary::from)
+    }
+
+    async fn cash_balance(&self, ctx: &Context<'_>, #[graphql(default = "USD")] currency: String) -> Result<String> {
+        Ok(get_cash_balance(self.user_id, &known_currency(&currency)?, get_pool_from_ctx(ctx))?.to_string())
     }
 }
 
@@ -163,11 +906,13 @@ impl User {
 struct Stock {
     id: ID,
     symbol: String,
-    shares: i32,
+    shares: CustomBigDecimal,
     price: String,
     percentage_change: String,
     action_type: String,
     user_id: ID,
+    fee: String,
+    currency: String,
 }
 
 #[Object]
@@ -180,7 +925,7 @@ impl Stock {
         &self.symbol
     }
 
-    async fn shares(&self) -> &i32 {
+    async fn shares(&self) -> &CustomBigDecimal {
         &self.shares
     }
 
@@ -199,6 +944,14 @@ impl Stock {
     async fn user_id(&self) -> &ID {
         &self.user_id
     }
+
+    async fn fee(&self) -> &String {
+        &self.fee
+    }
+
+    async fn currency(&self) -> &String {
+        &self.currency
+    }
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Enum, Display, EnumString)]
@@ -237,7 +990,7 @@ impl LowerExp for CustomBigInt {
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct CustomBigDecimal(BigDecimal);
 
 #[Scalar(name = "BigDecimal")]
@@ -248,6 +1001,10 @@ impl ScalarType for CustomBigDecimal {
                 let parsed_value = BigDecimal::from_str(&s)?;
                 Ok(CustomBigDecimal(parsed_value))
             }
+            Value::Number(n) => {
+                let parsed_value = BigDecimal::from_str(&n.to_string())?;
+                Ok(CustomBigDecimal(parsed_value))
+            }
             _ => Err(InputValueError::expected_type(value)),
         }
     }
@@ -267,7 +1024,46 @@ struct UserInput {
 #[derive(InputObject)]
 struct StocksInput {
     symbol: String,
-    shares: i32,
+    shares: Option<CustomBigDecimal>,
+    /// Dollars to spend instead of a number of shares, converted at the fill price.
+    amount: Option<CustomBigDecimal>,
+    take_profit: Option<String>,
+    stop_loss: Option<String>,
+}
+
+#[derive(InputObject)]
+struct FeeScheduleInput {
+    #[graphql(default = "0")]
+    flat_fee: String,
+    #[graphql(default = "0")]
+    per_share_fee: String,
+    /// Percentage of the trade value, e.g. "0.1" for 0.1%.
+    #[graphql(default = "0")]
+    percentage_fee: String,
+    min_fee: Option<String>,
+    max_fee: Option<String>,
+    /// Fraction of the proceeds charged on sales on top of the capped commission.
+    #[graphql(default = "0")]
+    sell_fee_rate: String,
+}
+
+#[derive(InputObject)]
+struct OrderAmendmentInput {
+    shares: Option<CustomBigDecimal>,
+    stop_price: Option<String>,
+    limit_price: Option<String>,
+}
+
+#[derive(InputObject)]
+struct OrderInput {
+    symbol: String,
+    shares: Option<CustomBigDecimal>,
+    /// Dollars to spend instead of a number of shares, for buys only.
+    amount: Option<CustomBigDecimal>,
+    action: OrderAction,
+    order_type: OrderType,
+    stop_price: Option<String>,
+    limit_price: Option<String>,
 }
 
 impl From<&UserEntity> for User {
@@ -280,16 +1076,34 @@ impl From<&UserEntity> for User {
     }
 }
 
+impl From<&TradeSnapshot> for Stock {
+    fn from(trade: &TradeSnapshot) -> Self {
+        Stock {
+            id: trade.id.into(),
+            symbol: trade.symbol.clone(),
+            shares: CustomBigDecimal(trade.shares.clone()),
+            price: trade.price.clone(),
+            percentage_change: trade.percentage_change.clone(),
+            action_type: trade.action_type.clone(),
+            user_id: trade.user_id.into(),
+            fee: trade.fee.to_string(),
+            currency: trade.currency.clone(),
+        }
+    }
+}
+
 impl From<&StocksEntity> for Stock {
     fn from(entity: &StocksEntity) -> Self {
         Stock {
             id: entity.id.into(),
             symbol: entity.symbol.clone(),
-            shares: entity.shares.into(),
+            shares: CustomBigDecimal(entity.shares.clone()),
             price: entity.price.clone(),
             percentage_change: entity.percentage_change.clone(),
             action_type: entity.action_type.clone(),
             user_id: entity.user_id.into(),
+            fee: entity.fee.to_string(),
+            currency: entity.currency.clone(),
         }
     }
 }
@@ -298,7 +1112,7 @@ impl From<&StocksEntity> for Stock {
 struct StockSummary {
     id: ID,
     symbol: String,
-    shares: i32,
+    shares: CustomBigDecimal,
     total_value: String,
     lowest_price: String,
     highest_price: String,
@@ -306,6 +1120,8 @@ struct StockSummary {
     price_by_hours: String,
     profit_loss: String,
     user_id: ID,
+    asset_class: String,
+    valuation: Option<Valuation>,
 }
 
 #[Object]
@@ -318,7 +1134,7 @@ impl StockSummary {
         &self.symbol
     }
 
-    async fn shares(&self) -> &i32 {
+    async fn shares(&self) -> &CustomBigDecimal {
         &self.shares
     }
 
@@ -338,10 +1154,25 @@ impl StockSummary {
         &self.average_price
     }
 
+    #[graphql(deprecation = "Use priceSeries")]
     async fn price_by_hours(&self) -> &String {
         &self.price_by_hours
     }
 
+    /// Prices and volume of the user's trades in the symbol per `bucket`, with buckets aligned on
+    /// `timeZone` (an IANA name such as "America/New_York").
+    async fn price_series(
+        &self,
+        ctx: &Context<'_>,
+        #[graphql(default_with = "TimeBucket::Hour")] bucket: TimeBucket,
+        #[graphql(default = "UTC")] time_zone: String,
+    ) -> Result<Vec<PricePoint>> {
+        let user_id = self.user_id.parse::<i32>()?;
+        let series = get_price_series(user_id, &self.symbol, bucket, &time_zone, get_pool_from_ctx(ctx))
+            .map_err(|e| Error::new(format!("Can't compute the price series in {}: {}", time_zone, e)))?;
+        Ok(series.iter().map(PricePoint::from).collect())
+    }
+
     async fn profit_loss(&self) -> &String {
         &self.profit_loss
     }
@@ -349,6 +1180,15 @@ impl StockSummary {
     async fn user_id(&self) -> &ID {
         &self.user_id
     }
+
+    async fn asset_class(&self) -> &String {
+        &self.asset_class
+    }
+
+    /// The position valued in the requested base currency.
+    async fn valuation(&self) -> &Option<Valuation> {
+        &self.valuation
+    }
 }
 
 impl From<&StocksSummaryEntity> for StockSummary {
@@ -356,7 +1196,7 @@ impl From<&StocksSummaryEntity> for StockSummary {
         StockSummary {
             id: entity.id.into(),
             symbol: entity.symbol.clone(),
-            shares: entity.shares.into(),
+            shares: CustomBigDecimal(entity.shares.clone()),
             total_value: entity.total_value.clone(),
             lowest_price: entity.lowest_price.clone(),
             highest_price: entity.highest_price.clone(),
@@ -364,7 +1204,506 @@ impl From<&StocksSummaryEntity> for StockSummary {
             price_by_hours: entity.price_by_hours.clone(),
             profit_loss: entity.profit_loss.clone(),
             user_id: entity.user_id.into(),
+            asset_class: AssetClass::Stocks.to_string(),
+            valuation: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PricePoint {
+    bucket_start: DateTime<FixedOffset>,
+    avg_price: CustomBigDecimal,
+    min_price: CustomBigDecimal,
+    max_price: CustomBigDecimal,
+    volume: CustomBigDecimal,
+}
+
+#[Object]
+impl PricePoint {
+    /// Start of the bucket, with the offset of the requested time zone at that moment.
+    async fn bucket_start(&self) -> &DateTime<FixedOffset> {
+        &self.bucket_start
+    }
+
+    async fn avg_price(&self) -> &CustomBigDecimal {
+        &self.avg_price
+    }
+
+    async fn min_price(&self) -> &CustomBigDecimal {
+        &self.min_price
+    }
+
+    async fn max_price(&self) -> &CustomBigDecimal {
+        &self.max_price
+    }
+
+    /// Shares bought and sold in the bucket.
+    async fn volume(&self) -> &CustomBigDecimal {
+        &self.volume
+    }
+}
+
+impl From<&PriceBucketEntity> for PricePoint {
+    fn from(entity: &PriceBucketEntity) -> Self {
+        let offset_secs = (entity.local_start - entity.bucket_start.naive_utc()).num_seconds() as i32;
+        let offset = FixedOffset::east_opt(offset_secs).unwrap_or(FixedOffset::east_opt(0).unwrap());
+        PricePoint {
+            bucket_start: entity.bucket_start.with_timezone(&offset),
+            avg_price: CustomBigDecimal(entity.avg_price.clone()),
+            min_price: CustomBigDecimal(entity.min_price.clone()),
+            max_price: CustomBigDecimal(entity.max_price.clone()),
+            volume: CustomBigDecimal(entity.volume.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Valuation {
+    currency: String,
+    base_currency: String,
+    market_value: CustomBigDecimal,
+    cost_basis: CustomBigDecimal,
+    price_profit_loss: CustomBigDecimal,
+    fx_profit_loss: CustomBigDecimal,
+}
+
+#[Object]
+impl Valuation {
+    /// Currency the instrument trades in.
+    async fn currency(&self) -> &String {
+        &self.currency
+    }
+
+    async fn base_currency(&self) -> &String {
+        &self.base_currency
+    }
+
+    async fn market_value(&self) -> &CustomBigDecimal {
+        &self.market_value
+    }
+
+    async fn cost_basis(&self) -> &CustomBigDecimal {
+        &self.cost_basis
+    }
+
+    /// P&L from the price moving in the instrument's currency.
+    async fn price_profit_loss(&self) -> &CustomBigDecimal {
+        &self.price_profit_loss
+    }
+
+    /// P&L from the instrument's currency moving against the base currency.
+    async fn fx_profit_loss(&self) -> &CustomBigDecimal {
+        &self.fx_profit_loss
+    }
+}
+
+impl From<&PositionValuation> for Valuation {
+    fn from(valuation: &PositionValuation) -> Self {
+        Valuation {
+            currency: valuation.currency.clone(),
+            base_currency: valuation.base_currency.clone(),
+            market_value: CustomBigDecimal(valuation.market_value.clone()),
+            cost_basis: CustomBigDecimal(valuation.cost_basis.clone()),
+            price_profit_loss: CustomBigDecimal(valuation.price_profit_loss.clone()),
+            fx_profit_loss: CustomBigDecimal(valuation.fx_profit_loss.clone()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Candle {
+    bucket_start: NaiveDateTime,
+    open: CustomBigDecimal,
+    high: CustomBigDecimal,
+    low: CustomBigDecimal,
+    close: CustomBigDecimal,
+    samples: i64,
+}
+
+#[Object]
+impl Candle {
+    async fn bucket_start(&self) -> &NaiveDateTime {
+        &self.bucket_start
+    }
+
+    async fn open(&self) -> &CustomBigDecimal {
+        &self.open
+    }
+
+    async fn high(&self) -> &CustomBigDecimal {
+        &self.high
+    }
+
+    async fn low(&self) -> &CustomBigDecimal {
+        &self.low
+    }
+
+    async fn close(&self) -> &CustomBigDecimal {
+        &self.close
+    }
+
+    /// Number of prices sampled in the bucket.
+    async fn samples(&self) -> &i64 {
+        &self.samples
+    }
+}
+
+impl From<&CandleEntity> for Candle {
+    fn from(entity: &CandleEntity) -> Self {
+        Candle {
+            bucket_start: entity.bucket_start,
+            open: CustomBigDecimal(entity.open.clone()),
+            high: CustomBigDecimal(entity.high.clone()),
+            low: CustomBigDecimal(entity.low.clone()),
+            close: CustomBigDecimal(entity.close.clone()),
+            samples: entity.samples,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Portfolio {
+    base_currency: String,
+    cash: CustomBigDecimal,
+    market_value: CustomBigDecimal,
+    cost_basis: CustomBigDecimal,
+    price_profit_loss: CustomBigDecimal,
+    fx_profit_loss: CustomBigDecimal,
+    total_value: CustomBigDecimal,
+}
+
+#[Object]
+impl Portfolio {
+    async fn base_currency(&self) -> &String {
+        &self.base_currency
+    }
+
+    async fn cash(&self) -> &CustomBigDecimal {
+        &self.cash
+    }
+
+    async fn market_value(&self) -> &CustomBigDecimal {
+        &self.market_value
+    }
+
+    async fn cost_basis(&self) -> &CustomBigDecimal {
+        &self.cost_basis
+    }
+
+    async fn price_profit_loss(&self) -> &CustomBigDecimal {
+        &self.price_profit_loss
+    }
+
+    async fn fx_profit_loss(&self) -> &CustomBigDecimal {
+        &self.fx_profit_loss
+    }
+
+    /// Cash plus the market value of the positions.
+    async fn total_value(&self) -> &CustomBigDecimal {
+        &self.total_value
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Order {
+    id: ID,
+    symbol: String,
+    shares: Option<CustomBigDecimal>,
+    amount: Option<CustomBigDecimal>,
+    action_type: String,
+    order_type: String,
+    stop_price: Option<String>,
+    limit_price: Option<String>,
+    status: String,
+    parent_id: Option<ID>,
+    user_id: ID,
+}
+
+#[Object]
+impl Order {
+    async fn id(&self) -> &ID {
+        &self.id
+    }
+
+    async fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    async fn shares(&self) -> &Option<CustomBigDecimal> {
+        &self.shares
+    }
+
+    async fn amount(&self) -> &Option<CustomBigDecimal> {
+        &self.amount
+    }
+
+    async fn action_type(&self) -> &String {
+        &self.action_type
+    }
+
+    async fn order_type(&self) -> &String {
+        &self.order_type
+    }
+
+    async fn stop_price(&self) -> &Option<String> {
+        &self.stop_price
+    }
+
+    async fn limit_price(&self) -> &Option<String> {
+        &self.limit_price
+    }
+
+    async fn status(&self) -> &String {
+        &self.status
+    }
+
+    async fn parent_id(&self) -> &Option<ID> {
+        &self.parent_id
+    }
+
+    async fn user_id(&self) -> &ID {
+        &self.user_id
+    }
+}
+
+impl From<&OrderSnapshot> for Order {
+    fn from(order: &OrderSnapshot) -> Self {
+        Order {
+            id: order.id.into(),
+            symbol: order.symbol.clone(),
+            shares: order.shares.clone().map(CustomBigDecimal),
+            amount: order.amount.clone().map(CustomBigDecimal),
+            action_type: order.action_type.clone(),
+            order_type: order.order_type.clone(),
+            stop_price: order.stop_price.clone(),
+            limit_price: order.limit_price.clone(),
+            status: order.status.clone(),
+            parent_id: order.parent_id.map(ID::from),
+            user_id: order.user_id.into(),
+        }
+    }
+}
+
+impl From<&OrderEntity> for Order {
+    fn from(entity: &OrderEntity) -> Self {
+        Order {
+            id: entity.id.into(),
+            symbol: entity.symbol.clone(),
+            shares: entity.shares.clone().map(CustomBigDecimal),
+            amount: entity.amount.clone().map(CustomBigDecimal),
+            action_type: entity.action_type.clone(),
+            order_type: entity.order_type.clone(),
+            stop_price: entity.stop_price.clone(),
+            limit_price: entity.limit_price.clone(),
+            status: entity.status.clone(),
+            parent_id: entity.parent_id.map(ID::from),
+            user_id: entity.user_id.into(),
         }
     }
 }
 
+#[derive(Serialize, Deserialize)]
+struct LedgerEntry {
+    id: ID,
+    entry_type: String,
+    amount: String,
+    description: Option<String>,
+    stock_id: Option<ID>,
+    user_id: ID,
+    currency: String,
+}
+
+#[Object]
+impl LedgerEntry {
+    async fn id(&self) -> &ID {
+        &self.id
+    }
+
+    async fn entry_type(&self) -> &String {
+        &self.entry_type
+    }
+
+    async fn amount(&self) -> &String {
+        &self.amount
+    }
+
+    async fn description(&self) -> &Option<String> {
+        &self.description
+    }
+
+    async fn stock_id(&self) -> &Option<ID> {
+        &self.stock_id
+    }
+
+    async fn user_id(&self) -> &ID {
+        &self.user_id
+    }
+
+    async fn currency(&self) -> &String {
+        &self.currency
+    }
+}
+
+impl From<&CashLedgerEntity> for LedgerEntry {
+    fn from(entity: &CashLedgerEntity) -> Self {
+        LedgerEntry {
+            id: entity.id.into(),
+            entry_type: entity.entry_type.clone(),
+            amount: entity.amount.to_string(),
+            description: entity.description.clone(),
+            stock_id: entity.stock_id.map(ID::from),
+            user_id: entity.user_id.into(),
+            currency: entity.currency.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct FeeSchedule {
+    id: ID,
+    flat_fee: String,
+    per_share_fee: String,
+    percentage_fee: String,
+    min_fee: Option<String>,
+    max_fee: Option<String>,
+    sell_fee_rate: String,
+    user_id: ID,
+}
+
+#[Object]
+impl FeeSchedule {
+    async fn id(&self) -> &ID {
+        &self.id
+    }
+
+    async fn flat_fee(&self) -> &String {
+        &self.flat_fee
+    }
+
+    async fn per_share_fee(&self) -> &String {
+        &self.per_share_fee
+    }
+
+    async fn percentage_fee(&self) -> &String {
+        &self.percentage_fee
+    }
+
+    async fn min_fee(&self) -> &Option<String> {
+        &self.min_fee
+    }
+
+    async fn max_fee(&self) -> &Option<String> {
+        &self.max_fee
+    }
+
+    async fn sell_fee_rate(&self) -> &String {
+        &self.sell_fee_rate
+    }
+
+    async fn user_id(&self) -> &ID {
+        &self.user_id
+    }
+}
+
+impl From<&FeeScheduleEntity> for FeeSchedule {
+    fn from(entity: &FeeScheduleEntity) -> Self {
+        FeeSchedule {
+            id: entity.id.into(),
+            flat_fee: entity.flat_fee.to_string(),
+            per_share_fee: entity.per_share_fee.to_string(),
+            percentage_fee: entity.percentage_fee.to_string(),
+            min_fee: entity.min_fee.as_ref().map(BigDecimal::to_string),
+            max_fee: entity.max_fee.as_ref().map(BigDecimal::to_string),
+            sell_fee_rate: entity.sell_fee_rate.to_string(),
+            user_id: entity.user_id.into(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Instrument {
+    symbol: String,
+    share_precision: i32,
+    currency: String,
+    asset_class: String,
+    contract_multiplier: i32,
+    company_name: Option<String>,
+    exchange: Option<String>,
+    stock_type: Option<String>,
+    is_nasdaq_listed: bool,
+    is_nasdaq100: bool,
+    is_listed: bool,
+    refreshed_at: Option<NaiveDateTime>,
+}
+
+#[Object]
+impl Instrument {
+    async fn symbol(&self) -> &String {
+        &self.symbol
+    }
+
+    async fn share_precision(&self) -> &i32 {
+        &self.share_precision
+    }
+
+    async fn currency(&self) -> &String {
+        &self.currency
+    }
+
+    async fn asset_class(&self) -> &String {
+        &self.asset_class
+    }
+
+    async fn contract_multiplier(&self) -> &i32 {
+        &self.contract_multiplier
+    }
+
+    async fn company_name(&self) -> &Option<String> {
+        &self.company_name
+    }
+
+    async fn exchange(&self) -> &Option<String> {
+        &self.exchange
+    }
+
+    async fn stock_type(&self) -> &Option<String> {
+        &self.stock_type
+    }
+
+    async fn is_nasdaq_listed(&self) -> &bool {
+        &self.is_nasdaq_listed
+    }
+
+    async fn is_nasdaq100(&self) -> &bool {
+        &self.is_nasdaq100
+    }
+
+    /// False once Nasdaq no longer knows the symbol.
+    async fn is_listed(&self) -> &bool {
+        &self.is_listed
+    }
+
+    async fn refreshed_at(&self) -> &Option<NaiveDateTime> {
+        &self.refreshed_at
+    }
+}
+
+impl From<&InstrumentEntity> for Instrument {
+    fn from(entity: &InstrumentEntity) -> Self {
+        Instrument {
+            symbol: entity.symbol.clone(),
+            share_precision: entity.share_precision,
+            currency: entity.currency.clone(),
+            asset_class: entity.asset_class.clone(),
+            contract_multiplier: entity.contract_multiplier,
+            company_name: entity.company_name.clone(),
+            exchange: entity.exchange.clone(),
+            stock_type: entity.stock_type.clone(),
+            is_nasdaq_listed: entity.is_nasdaq_listed,
+            is_nasdaq100: entity.is_nasdaq100,
+            is_listed: entity.is_listed,
+            refreshed_at: entity.refreshed_at,
+        }
+    }
+}
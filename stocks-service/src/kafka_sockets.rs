@@ -1,36 +1,32 @@
-use std::sync::Mutex;
-use lazy_static::lazy_static;
-use rdkafka::config::RDKafkaLogLevel;
-use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::ClientConfig;
+use std::env;
+use std::process;
+use std::sync::{Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-lazy_static! {
-    static ref KAFKA_BROKER: String =
-        std::env::var("KAFKA_BROKER").expect("Can't read Kafka broker address");
-    static ref KAFKA_TOPIC: String =
-        std::env::var("KAFKA_TOPIC").expect("Can't read Kafka topic name");
-}
-
-pub fn create_consumer(group_id: String) -> StreamConsumer {
-    let consumer: StreamConsumer = ClientConfig::new()
-        .set("group.id", &group_id)
-        .set("bootstrap.servers", KAFKA_BROKER.as_str())
-        .set("enable.partition.eof", "false")
-        .set("session.timeout.ms", "6000")
-        .set("enable.auto.commit", "true")
-        .set_log_level(RDKafkaLogLevel::Debug)
-        .create()
-        .expect("Consumer creation failed");
+use common_utils::event_bus::{event_bus, spawn_receiver, trade_events_topic, Commit, Payloads, StartFrom};
 
-    consumer
-        .subscribe(&[&KAFKA_TOPIC])
+/// Messages published to the trade events topic from now on, read through the process-wide event bus.
+pub fn subscribe(group_id: String) -> Payloads {
+    let subscriber = event_bus()
+        .subscribe(trade_events_topic(), &group_id, StartFrom::Latest, Commit::OnPoll)
         .expect("Can't subscribe to specified topics");
-
-    consumer
+    spawn_receiver(subscriber)
 }
 
+/// A group of its own for each subscription. The counter restarts with the process, so the id also
+/// names the host, the process and when it started, and subscriptions never share the offsets of a
+/// group left by another replica or an earlier run.
 pub fn get_kafka_consumer_group_id(kafka_consumer_counter: &Mutex<i32>) -> String {
     let mut counter = kafka_consumer_counter.lock().expect("Can't lock counter");
     *counter += 1;
-    format!("graphql-group-{}", *counter)
-}
\ No newline at end of file
+    format!("graphql-group-{}-{}", process_id(), *counter)
+}
+
+fn process_id() -> &'static str {
+    static PROCESS_ID: OnceLock<String> = OnceLock::new();
+    PROCESS_ID.get_or_init(|| {
+        let host = env::var("HOSTNAME").unwrap_or_else(|_| "localhost".to_string());
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis()).unwrap_or_default();
+        format!("{}-{}-{}", host, process::id(), started)
+    })
+}
@@ -5,18 +5,13 @@ use async_graphql::{Context, Schema};
 use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
 use diesel::r2d2::{ConnectionManager, PooledConnection};
 use diesel::PgConnection;
-use diesel_migrations::MigrationHarness;
 
 use crate::graphql::{AppSchema, Mutation, Query, Subscription};
 use crate::persistence::connection::PgPool;
 
 pub mod graphql;
 mod kafka_sockets;
-pub mod persistence;
-pub mod stock_functions;
-
-const MIGRATIONS: diesel_migrations::EmbeddedMigrations =
-    diesel_migrations::embed_migrations!("./migrations");
+pub use stocks_domain::{persistence, run_migrations, stock_functions};
 
 pub fn configure_service(cfg: &mut web::ServiceConfig) {
     cfg.service(
@@ -67,14 +62,12 @@ pub fn create_schema_with_context(pool: PgPool) -> Schema<Query, Mutation, Subsc
         .finish()
 }
 
-pub fn run_migrations(conn: &mut PooledConnection<ConnectionManager<PgConnection>>) {
-    conn.run_pending_migrations(MIGRATIONS)
-        .expect("Failed to run database migrations");
+pub fn get_pool_from_ctx<'a>(ctx: &Context<'a>) -> &'a PgPool {
+    ctx.data::<Arc<PgPool>>().expect("Can't get pool")
 }
 
 pub fn get_conn_from_ctx(ctx: &Context<'_>) -> PooledConnection<ConnectionManager<PgConnection>> {
-    ctx.data::<Arc<PgPool>>()
-        .expect("Can't get pool")
+    get_pool_from_ctx(ctx)
         .get()
         .expect("Can't get DB connection")
 }
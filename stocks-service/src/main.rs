@@ -1,9 +1,12 @@
 extern crate stocks_service;
 
 use std::env;
+use std::thread;
 extern crate serde_json;
 
 use actix_web::{web, App, HttpServer};
+use common_utils::event_bus::{event_bus, flush_event_bus, is_in_memory};
+use consumer_stocks_service::run_consumer;
 use dotenv::dotenv;
 
 use stocks_service::persistence::connection::create_connection_pool;
@@ -12,9 +15,21 @@ use stocks_service::{configure_service, create_schema_with_context, run_migratio
 #[actix_rt::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
+    // Orders are published through one producer for the lifetime of the service.
+    event_bus();
     let pool = create_connection_pool();
     run_migrations(&mut pool.get().expect("Can't get DB connection"));
 
+    // Without a broker the consumer only sees the orders queued by this process, so it runs here.
+    if is_in_memory() {
+        let pool = pool.clone();
+        thread::spawn(move || {
+            if let Err(e) = run_consumer(event_bus(), pool) {
+                println!("The consumer stopped: {}", e);
+            }
+        });
+    }
+
     let schema = web::Data::new(create_schema_with_context(pool));
 
     #[allow(unused_assignments)]
@@ -28,12 +43,15 @@ async fn main() -> std::io::Result<()> {
         }
     };
 
-    HttpServer::new(move || {
+    let result = HttpServer::new(move || {
         App::new()
             .configure(configure_service)
             .app_data(schema.clone())
     })
     .bind(format!("0.0.0.0:{}", server_port))?
     .run()
-    .await
+    .await;
+    // The server has stopped taking orders, so the ones still queued can be delivered.
+    flush_event_bus();
+    result
 }
\ No newline at end of file
@@ -1,4 +0,0 @@
-pub mod connection;
-pub mod model;
-pub mod repository;
-pub mod schema;
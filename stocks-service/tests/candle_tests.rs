@@ -0,0 +1,163 @@
+use std::str::FromStr;
+
+use async_graphql::Request;
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use testcontainers::clients::Cli;
+
+use stocks_service::create_schema_with_context;
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::model::CandleEntity;
+use stocks_service::persistence::schema::price_history;
+use stocks_service::stock_functions::{get_candles, lookup_instrument, sample_prices, CandleResolution};
+
+mod common;
+
+fn decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).unwrap()
+}
+
+fn time(value: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S").unwrap()
+}
+
+fn sample(pool: &PgPool, symbol: &str, price: &str, sampled_at: &str) {
+    diesel::insert_into(price_history::table)
+        .values((
+            price_history::symbol.eq(symbol),
+            price_history::price.eq(decimal(price)),
+            price_history::sampled_at.eq(time(sampled_at)),
+        ))
+        .execute(&mut pool.get().unwrap())
+        .unwrap();
+}
+
+/// Candles as (bucket start, open, high, low, close, samples) for readable assertions.
+fn ohlc(candles: &[CandleEntity]) -> Vec<(String, String, String, String, String, i64)> {
+    candles
+        .iter()
+        .map(|candle| {
+            (
+                candle.bucket_start.to_string(),
+                candle.open.to_string(),
+                candle.high.to_string(),
+                candle.low.to_string(),
+                candle.close.to_string(),
+                candle.samples,
+            )
+        })
+        .collect()
+}
+
+fn row(bucket_start: &str, open: &str, high: &str, low: &str, close: &str, samples: i64) -> (String, String, String, String, String, i64) {
+    (bucket_start.to_string(), open.to_string(), high.to_string(), low.to_string(), close.to_string(), samples)
+}
+
+#[test]
+fn test_candles_aggregate_samples_per_bucket() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    sample(&pool, "AAPL", "99.00", "2023-10-26 09:59:59");
+    sample(&pool, "AAPL", "100.00", "2023-10-26 10:00:05");
+    sample(&pool, "AAPL", "105.00", "2023-10-26 10:00:30");
+    sample(&pool, "AAPL", "98.00", "2023-10-26 10:00:50");
+    sample(&pool, "AAPL", "101.00", "2023-10-26 10:00:59");
+    sample(&pool, "AAPL", "110.00", "2023-10-26 10:02:10");
+    sample(&pool, "AAPL", "120.00", "2023-10-26 10:03:00");
+    sample(&pool, "MSFT", "300.00", "2023-10-26 10:00:10");
+
+    let candles = get_candles("AAPL", CandleResolution::OneMinute, time("2023-10-26 10:00:00"), time("2023-10-26 10:03:00"), &pool);
+
+    assert_eq!(
+        vec![
+            row("2023-10-26 10:00:00", "100.00", "105.00", "98.00", "101.00", 4),
+            row("2023-10-26 10:02:00", "110.00", "110.00", "110.00", "110.00", 1),
+        ],
+        ohlc(&candles)
+    );
+}
+
+#[test]
+fn test_candles_are_aligned_on_utc_boundaries() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    sample(&pool, "AAPL", "100.00", "2023-10-26 10:07:00");
+    sample(&pool, "AAPL", "102.00", "2023-10-26 10:09:59");
+    sample(&pool, "AAPL", "104.00", "2023-10-26 23:59:59");
+    sample(&pool, "AAPL", "106.00", "2023-10-27 00:00:00");
+
+    let from = time("2023-10-26 00:00:00");
+    let to = time("2023-10-28 00:00:00");
+
+    assert_eq!(
+        vec![
+            row("2023-10-26 10:05:00", "100.00", "102.00", "100.00", "102.00", 2),
+            row("2023-10-26 23:55:00", "104.00", "104.00", "104.00", "104.00", 1),
+            row("2023-10-27 00:00:00", "106.00", "106.00", "106.00", "106.00", 1),
+        ],
+        ohlc(&get_candles("AAPL", CandleResolution::FiveMinutes, from, to, &pool))
+    );
+    assert_eq!(
+        vec![
+            row("2023-10-26 00:00:00", "100.00", "104.00", "100.00", "104.00", 3),
+            row("2023-10-27 00:00:00", "106.00", "106.00", "106.00", "106.00", 1),
+        ],
+        ohlc(&get_candles("AAPL", CandleResolution::OneDay, from, to, &pool))
+    );
+}
+
+#[test]
+fn test_samples_taken_at_the_same_time_keep_their_order() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    sample(&pool, "AAPL", "100.00", "2023-10-26 10:00:00");
+    sample(&pool, "AAPL", "101.00", "2023-10-26 10:00:00");
+
+    let candles = get_candles("AAPL", CandleResolution::OneHour, time("2023-10-26 10:00:00"), time("2023-10-26 11:00:00"), &pool);
+
+    assert_eq!(vec![row("2023-10-26 10:00:00", "100.00", "101.00", "100.00", "101.00", 2)], ohlc(&candles));
+}
+
+#[test]
+fn test_sampling_records_listed_instruments() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "150.25");
+    fakes.quotes.set_price("MSFT", "310.00");
+    lookup_instrument("AAPL", &pool).unwrap();
+    lookup_instrument("MSFT", &pool).unwrap();
+    // Instruments that can't be quoted are skipped.
+    fakes.quotes.delist("MSFT");
+
+    sample_prices(&pool).unwrap();
+
+    let samples: Vec<(String, BigDecimal)> = price_history::table
+        .select((price_history::symbol, price_history::price))
+        .load(&mut pool.get().unwrap())
+        .unwrap();
+    assert_eq!(vec![("AAPL".to_string(), decimal("150.25"))], samples);
+}
+
+#[actix_rt::test]
+async fn test_candle_queries_are_bounded() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let schema = create_schema_with_context(pool);
+    let query = |from: &str, to: &str, resolution: &str| {
+        format!(
+            r#"{{ candles(symbol: "AAPL", resolution: {}, from: "{}", to: "{}") {{ bucketStart }} }}"#,
+            resolution, from, to
+        )
+    };
+
+    let response = schema.execute(Request::new(query("2023-10-26T10:00:00", "2023-10-26T10:00:00", "ONE_MINUTE"))).await;
+    assert_eq!("from must be before to", response.errors[0].message);
+
+    let response = schema.execute(Request::new(query("2023-10-26T00:00:00", "2023-10-28T00:00:00", "ONE_MINUTE"))).await;
+    assert!(response.errors[0].message.starts_with("At most 1500 candles"), "{}", response.errors[0].message);
+
+    let response = schema.execute(Request::new(query("2023-10-26T00:00:00", "2023-10-28T00:00:00", "FIVE_MINUTES"))).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+}
@@ -1,20 +1,169 @@
+use std::collections::HashMap;
 use std::env;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::Duration;
 
+use common_utils::event_bus::{install_event_bus, InMemoryEventBus};
+use common_utils::quote_cache::{install_quote_cache, QuoteCache};
+use common_utils::quote_client::QuoteError;
+use common_utils::{CustomResult, Stock};
 use dotenv::dotenv;
 use jsonpath_lib as jsonpath;
+use serde_json::json;
 use testcontainers::clients::Cli;
 use testcontainers::images::postgres::Postgres;
 use testcontainers::{Container, RunnableImage};
 
+use diesel::RunQueryDsl;
 use stocks_service::persistence::connection::{create_connection_pool, PgPool};
+use stocks_service::persistence::model::NewUserEntity;
+use stocks_service::persistence::schema::users;
 use stocks_service::run_migrations;
 
-pub fn setup(docker: &Cli) -> (Container<Postgres>, PgPool) {
+/// The services find the database through `DATABASE_URL`, so tests using one run one at a time.
+static DATABASE_LOCK: Mutex<()> = Mutex::new(());
+
+/// A Postgres container owned by one test, which keeps other tests waiting until it's dropped.
+pub struct TestDatabase<'d> {
+    _container: Container<'d, Postgres>,
+    _guard: MutexGuard<'static, ()>,
+}
+
+/// Starts a migrated Postgres and resets the [`fakes`] the services run against.
+pub fn setup(docker: &Cli) -> (TestDatabase<'_>, PgPool) {
     dotenv().ok();
+    let guard = DATABASE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    fakes().reset();
     let pg_container = setup_database(docker);
     let pool = create_connection_pool();
     run_migrations(&mut pool.get().expect("Can't get DB connection"));
-    (pg_container, pool)
+    (TestDatabase { _container: pg_container, _guard: guard }, pool)
+}
+
+/// Adds a user besides the seeded one and returns their id.
+#[allow(dead_code)]
+pub fn create_user(pool: &PgPool, name: &str) -> i32 {
+    diesel::insert_into(users::table)
+        .values(NewUserEntity { name: name.to_string(), email: format!("{}@example.com", name.to_lowercase()) })
+        .returning(users::id)
+        .get_result(&mut pool.get().expect("Can't get DB connection"))
+        .expect("Can't create a user")
+}
+
+/// Stand-ins for Nasdaq and Kafka, installed for the whole test binary.
+pub struct Fakes {
+    pub quotes: FakeQuotes,
+    pub events: InMemoryEventBus,
+}
+
+impl Fakes {
+    fn reset(&self) {
+        self.quotes.prices.lock().unwrap().clear();
+        self.quotes.requests.lock().unwrap().clear();
+        self.events.clear();
+    }
+}
+
+static FAKES: OnceLock<Fakes> = OnceLock::new();
+
+pub fn fakes() -> &'static Fakes {
+    FAKES.get_or_init(|| {
+        let quotes = FakeQuotes::default();
+        let events = InMemoryEventBus::default();
+        let blocking_quotes = quotes.clone();
+        let async_quotes = quotes.clone();
+        // Not caching lets tests move prices between calls.
+        install_quote_cache(QuoteCache::new(
+            Duration::ZERO,
+            Duration::ZERO,
+            move |symbol, asset_class| blocking_quotes.quote(symbol, asset_class),
+            move |symbol, asset_class| {
+                let quote = async_quotes.quote(&symbol, &asset_class);
+                async move { quote }
+            },
+        ))
+        .unwrap_or_else(|_| panic!("Quotes were requested before installing the fake"));
+        install_event_bus(Box::new(events.clone()))
+            .unwrap_or_else(|_| panic!("Events were published before installing the fake"));
+        Fakes { quotes, events }
+    })
+}
+
+/// Nasdaq quotes for the symbols given a price, in the asset class they're asked for; other
+/// symbols aren't found.
+#[derive(Clone, Default)]
+pub struct FakeQuotes {
+    prices: Arc<Mutex<HashMap<String, String>>>,
+    requests: Arc<Mutex<Vec<(String, String)>>>,
+}
+
+impl FakeQuotes {
+    #[allow(dead_code)]
+    pub fn set_price(&self, symbol: &str, price: &str) {
+        self.prices.lock().unwrap().insert(symbol.to_string(), price.to_string());
+    }
+
+    /// Makes Nasdaq stop knowing `symbol`, as when it's delisted.
+    #[allow(dead_code)]
+    pub fn delist(&self, symbol: &str) {
+        self.prices.lock().unwrap().remove(symbol);
+    }
+
+    /// The symbols and asset classes quotes were asked for, oldest first.
+    #[allow(dead_code)]
+    pub fn requests(&self) -> Vec<(String, String)> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    fn quote(&self, symbol: &str, asset_class: &str) -> Result<CustomResult, QuoteError> {
+        self.requests.lock().unwrap().push((symbol.to_string(), asset_class.to_string()));
+        let Some(price) = self.prices.lock().unwrap().get(symbol).cloned() else {
+            return Ok(CustomResult {
+                stock: None,
+                success: false,
+                message: "Symbol not exists".to_string(),
+            });
+        };
+        let stock: Stock = serde_json::from_value(json!({
+            "data": {
+                "symbol": symbol,
+                "companyName": format!("{} Inc. Common Stock", symbol),
+                "stockType": "Common Stock",
+                "exchange": "NASDAQ-GS",
+                "isNasdaqListed": true,
+                "isNasdaq100": false,
+                "isHeld": false,
+                "primaryData": {
+                    "lastSalePrice": format!("${}", price),
+                    "netChange": "+0.75",
+                    "percentageChange": "+0.50%",
+                    "deltaIndicator": "up",
+                    "lastTradeTimestamp": "Oct 26, 2023 4:00 PM ET",
+                    "isRealTime": true,
+                    "bidPrice": "N/A",
+                    "askPrice": "N/A",
+                    "bidSize": "N/A",
+                    "askSize": "N/A",
+                    "volume": "1,000,000"
+                },
+                "secondaryData": null,
+                "marketStatus": "Open",
+                "assetClass": asset_class.to_uppercase(),
+                "keyStats": {
+                    "fiftyTwoWeekHighLow": { "label": "52 Week Range:", "value": "N/A" },
+                    "dayrange": { "label": "High/Low:", "value": "N/A" }
+                },
+                "notifications": []
+            },
+            "status": { "rCode": 200 }
+        }))
+        .map_err(|e| QuoteError::Parse(e.to_string()))?;
+        Ok(CustomResult {
+            stock: Some(stock),
+            success: true,
+            message: "success!".to_string(),
+        })
+    }
 }
 
 fn setup_database(docker: &Cli) -> Container<Postgres> {
@@ -33,6 +182,7 @@ fn get_pg_image() -> RunnableImage<Postgres> {
         .with_env_var(("POSTGRES_PASSWORD", "postgres"))
 }
 
+#[allow(dead_code)]
 pub fn check_user(
     user_json: &serde_json::Value,
     id: i32,
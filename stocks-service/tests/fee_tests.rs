@@ -0,0 +1,99 @@
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use testcontainers::clients::Cli;
+
+use stocks_service::persistence::model::{FeeScheduleEntity, NewFeeScheduleEntity};
+use stocks_service::persistence::repository;
+use stocks_service::stock_functions::{calculate_fee, get_cash_balance, record_cash_movement, save_stock};
+
+mod common;
+
+fn decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).unwrap()
+}
+
+fn schedule(flat: &str, per_share: &str, percentage: &str, min: Option<&str>, max: Option<&str>, sell_rate: &str) -> FeeScheduleEntity {
+    FeeScheduleEntity {
+        id: 1,
+        flat_fee: decimal(flat),
+        per_share_fee: decimal(per_share),
+        percentage_fee: decimal(percentage),
+        min_fee: min.map(decimal),
+        max_fee: max.map(decimal),
+        sell_fee_rate: decimal(sell_rate),
+        user_id: 1,
+    }
+}
+
+#[test]
+fn test_fee_schedules() {
+    let cases = [
+        // (schedule, action, shares, price, multiplier, fee)
+        (schedule("1", "0", "0", None, None, "0"), "buy", "10", "100", 1, "1.00"),
+        (schedule("0", "0.005", "0", None, None, "0"), "buy", "100", "100", 1, "0.50"),
+        (schedule("0", "0", "0.1", None, None, "0"), "buy", "10", "100", 1, "1.00"),
+        (schedule("1", "0.01", "0.1", None, None, "0"), "buy", "10", "100", 1, "2.10"),
+        // The minimum lifts small commissions and the maximum caps large ones.
+        (schedule("0", "0.005", "0", Some("1"), None, "0"), "buy", "10", "100", 1, "1.00"),
+        (schedule("0", "0", "1", None, Some("5"), "0"), "buy", "10", "100", 1, "5.00"),
+        (schedule("0", "0", "1", Some("1"), Some("5"), "0"), "buy", "3", "10", 1, "1.00"),
+        // Sales also pay the regulatory rate, on top of a capped commission.
+        (schedule("0", "0", "0", None, None, "0.0000278"), "buy", "100", "100", 1, "0.00"),
+        (schedule("0", "0", "0", None, None, "0.0000278"), "sale", "100", "100", 1, "0.28"),
+        (schedule("0", "0", "1", None, Some("5"), "0.0001"), "sale", "100", "100", 1, "6.00"),
+        // Options pay the per-share part per contract and the percentage on the contract value.
+        (schedule("0", "0.65", "0", None, None, "0"), "buy", "2", "1.50", 100, "1.30"),
+        (schedule("0", "0", "1", None, None, "0"), "buy", "2", "1.50", 100, "3.00"),
+        // Half cents round to the even cent.
+        (schedule("0", "0.005", "0", None, None, "0"), "buy", "3", "100", 1, "0.02"),
+        (schedule("0", "0.005", "0", None, None, "0"), "buy", "5", "100", 1, "0.02"),
+        (schedule("0", "0", "0.1", None, None, "0"), "buy", "10", "123.55", 1, "1.24"),
+        (schedule("0", "0", "0.1", None, None, "0"), "buy", "10", "123.45", 1, "1.23"),
+    ];
+
+    for (schedule, action, shares, price, multiplier, fee) in cases {
+        assert_eq!(
+            decimal(fee),
+            calculate_fee(Some(&schedule), action, &decimal(shares), price, multiplier),
+            "{} {} at {} with flat {} per share {} percentage {} min {:?} max {:?} sell rate {}",
+            action, shares, price, schedule.flat_fee, schedule.per_share_fee, schedule.percentage_fee,
+            schedule.min_fee, schedule.max_fee, schedule.sell_fee_rate
+        );
+    }
+    assert_eq!(BigDecimal::from(0), calculate_fee(None, "sale", &decimal("100"), "100", 1));
+}
+
+#[actix_rt::test]
+async fn test_sale_fees_are_debited() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    record_cash_movement(1, BigDecimal::from(10_000), "USD", None, &pool).unwrap();
+    repository::save_fee_schedule(NewFeeScheduleEntity {
+        flat_fee: decimal("1"),
+        per_share_fee: BigDecimal::from(0),
+        percentage_fee: BigDecimal::from(0),
+        min_fee: None,
+        max_fee: None,
+        sell_fee_rate: decimal("0.001"),
+        user_id: 1,
+    }, &mut pool.get().unwrap())
+    .expect("Can't save the fee schedule");
+
+    let trade = |action: &str| {
+        save_stock(1, "AAPL".to_string(), BigDecimal::from(10), "100.00".to_string(), "0.5".to_string(), action.to_string(), &pool)
+            .unwrap_or_else(|e| panic!("Can't {}: {}", action, e))
+    };
+    assert_eq!(decimal("1.00"), trade("buy").fee);
+    assert_eq!(decimal("2.00"), trade("sale").fee);
+
+    let fees = repository::get_cash_ledger(1, &mut pool.get().unwrap())
+        .unwrap()
+        .into_iter()
+        .filter(|entry| entry.entry_type == "fee")
+        .map(|entry| entry.amount)
+        .collect::<Vec<_>>();
+    assert_eq!(vec![decimal("-2.00"), decimal("-1.00")], fees);
+    assert_eq!(decimal("9997.00"), get_cash_balance(1, "USD", &pool).unwrap());
+}
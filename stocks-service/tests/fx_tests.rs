@@ -0,0 +1,127 @@
+use std::env;
+use std::str::FromStr;
+
+use async_graphql::{Request, Variables};
+use bigdecimal::{BigDecimal, RoundingMode};
+use serde_json::json;
+use testcontainers::clients::Cli;
+
+use common_utils::fx::{FxRateSource, StaticFxRates};
+use stocks_service::create_schema_with_context;
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::model::NewOrderEntity;
+use stocks_service::persistence::repository;
+use stocks_service::stock_functions::{
+    execute_queued_order, get_cash_balance, record_cash_movement, save_stock, set_instrument_currency, TradeError,
+};
+
+mod common;
+
+fn decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).unwrap()
+}
+
+fn rounded(rate: Option<BigDecimal>) -> Option<BigDecimal> {
+    rate.map(|rate| rate.with_scale_round(6, RoundingMode::HalfEven))
+}
+
+async fn execute(pool: &PgPool, query: &str, variables: serde_json::Value) -> serde_json::Value {
+    let response = create_schema_with_context(pool.clone())
+        .execute(Request::new(query).variables(Variables::from_json(variables)))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().expect("Can't read response data")
+}
+
+#[test]
+fn test_rates_are_crossed_through_usd() {
+    let fx = StaticFxRates::default();
+    let cases = [
+        // (from, to, rate)
+        ("EUR", "USD", Some("1.06")),
+        ("usd", "eur", Some("0.943396")),
+        ("EUR", "GBP", Some("0.868852")),
+        ("JPY", "JPY", Some("1")),
+        ("USD", "USD", Some("1")),
+        ("PLN", "USD", None),
+        ("USD", "PLN", None),
+    ];
+
+    for (from, to, rate) in cases {
+        assert_eq!(rounded(rate.map(decimal)), rounded(fx.rate(from, to)), "{} to {}", from, to);
+    }
+    assert_eq!(Some(decimal("1060")), fx.convert(&decimal("1000"), "EUR", "USD"));
+}
+
+#[test]
+fn test_fx_rates_from_env_ignore_invalid_rates() {
+    env::set_var("FX_RATES", "SEK=0.09, NOK=0,DKK=-1,ZAR=abc,=2,HUF");
+    let fx = StaticFxRates::from_env();
+    env::remove_var("FX_RATES");
+
+    assert_eq!(Some(decimal("0.09")), fx.rate("SEK", "USD"));
+    for code in ["NOK", "DKK", "ZAR", "HUF"] {
+        assert_eq!(None, fx.rate(code, "USD"), "{}", code);
+    }
+    assert_eq!(Some(decimal("1.06")), fx.rate("EUR", "USD"));
+}
+
+#[actix_rt::test]
+async fn test_trades_in_other_currencies_are_booked_and_valued_in_usd() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("SAP", "100.00");
+    set_instrument_currency("SAP", "EUR", &pool).unwrap();
+    execute(&pool, r#"mutation { deposit(amount: "10000", currency: "EUR") { id } }"#, json!({})).await;
+
+    execute(&pool, r#"mutation { buyStocks(stock: { symbol: "SAP", shares: 10 }) { id } }"#, json!({})).await;
+
+    let trade = &repository::get_stocks_by_symbol("SAP".to_string(), &mut pool.get().unwrap())[0];
+    assert_eq!("EUR", trade.currency);
+    assert_eq!(decimal("1.06"), trade.fx_rate);
+    assert_eq!(decimal("9000"), get_cash_balance(1, "EUR", &pool).unwrap());
+    assert_eq!(BigDecimal::from(0), get_cash_balance(1, "USD", &pool).unwrap());
+    let portfolio = &execute(
+        &pool,
+        r#"{ portfolio(baseCurrency: "USD") { cash marketValue costBasis fxProfitLoss totalValue } }"#,
+        json!({}),
+    )
+    .await["portfolio"];
+    let value = |field: &str| decimal(portfolio[field].as_str().unwrap());
+    assert_eq!(decimal("9540"), value("cash"));
+    assert_eq!(decimal("1060"), value("marketValue"));
+    assert_eq!(decimal("1060"), value("costBasis"));
+    assert_eq!(BigDecimal::from(0), value("fxProfitLoss"));
+    assert_eq!(decimal("10600"), value("totalValue"));
+}
+
+#[actix_rt::test]
+async fn test_trades_without_an_fx_rate_are_rejected() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("CDR", "100.00");
+    set_instrument_currency("CDR", "PLN", &pool).unwrap();
+    record_cash_movement(1, BigDecimal::from(10_000), "PLN", None, &pool).unwrap();
+
+    let trade = save_stock(1, "CDR".to_string(), BigDecimal::from(10), "100.00".to_string(), "0.5".to_string(), "buy".to_string(), &pool);
+    assert!(matches!(&trade, Err(TradeError::Rejected(reason)) if reason.contains("No FX rate")), "{:?}", trade.err());
+
+    let order = repository::create_order(NewOrderEntity {
+        symbol: "CDR".to_string(),
+        shares: Some(BigDecimal::from(10)),
+        amount: None,
+        action_type: "buy".to_string(),
+        order_type: "market".to_string(),
+        stop_price: None,
+        limit_price: None,
+        status: "pending".to_string(),
+        parent_id: None,
+        user_id: 1,
+    }, &mut pool.get().unwrap())
+    .expect("Can't create an order");
+    execute_queued_order(order.id, &pool).expect("Can't execute the order");
+
+    assert_eq!("rejected", repository::get_order(order.id, &mut pool.get().unwrap()).unwrap().status);
+    assert!(repository::get_stocks_by_symbol("CDR".to_string(), &mut pool.get().unwrap()).is_empty());
+    assert_eq!(BigDecimal::from(10_000), get_cash_balance(1, "PLN", &pool).unwrap());
+}
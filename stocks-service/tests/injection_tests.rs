@@ -0,0 +1,189 @@
+use std::str::FromStr;
+
+use actix_web::{web, App};
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map};
+use testcontainers::clients::Cli;
+
+use consumer_stocks_service::process_order_message;
+use stocks_endpoints::handle_request;
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::model::NewStocksEntity;
+use stocks_service::persistence::repository;
+use stocks_service::stock_functions::calculate_prices_by_hour;
+use stocks_service::{configure_service, create_schema_with_context};
+
+mod common;
+
+const HOSTILE_SYMBOLS: [&str; 7] = [
+    "AAPL'; DROP TABLE stocks; --",
+    "' OR '1'='1",
+    "' OR 1=1 --",
+    "AAPL' UNION SELECT name, email, email FROM users --",
+    "'); DELETE FROM cash_ledger; --",
+    "\\'; TRUNCATE instruments; --",
+    "AAPL%_",
+];
+
+fn create_trade(pool: &PgPool) {
+    repository::create_stock(NewStocksEntity {
+        symbol: "AAPL".to_string(),
+        shares: BigDecimal::from(10),
+        price: "150.00".to_string(),
+        percentage_change: "0.5".to_string(),
+        action_type: "buy".to_string(),
+        user_id: 1,
+        fee: BigDecimal::from(0),
+        currency: "USD".to_string(),
+        fx_rate: BigDecimal::from(1),
+        multiplier: 1,
+    }, &mut pool.get().expect("Can't get DB connection"))
+    .expect("Can't create a trade");
+}
+
+fn check_trade_intact(pool: &PgPool) {
+    let trades = repository::get_stocks_by_symbol("AAPL".to_string(), &mut pool.get().expect("Can't get DB connection"));
+    assert_eq!(1, trades.len());
+    assert_eq!(BigDecimal::from_str("150.00").unwrap(), trades[0].price.parse::<BigDecimal>().unwrap());
+}
+
+#[actix_rt::test]
+async fn test_prices_by_hour_binds_symbol() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    create_trade(&pool);
+
+    assert_ne!("", calculate_prices_by_hour("AAPL".to_string(), &pool));
+    for symbol in HOSTILE_SYMBOLS {
+        assert_eq!("", calculate_prices_by_hour(symbol.to_string(), &pool), "{}", symbol);
+    }
+    check_trade_intact(&pool);
+}
+
+#[actix_rt::test]
+async fn test_raw_queries_bind_parameters() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    create_trade(&pool);
+    let mut conn = pool.get().expect("Can't get DB connection");
+    let from = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+    let to = NaiveDate::from_ymd_opt(2100, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+
+    for symbol in HOSTILE_SYMBOLS {
+        let series = repository::get_price_series(symbol.to_string(), 1, "hour".to_string(), "UTC".to_string(), &mut conn)
+            .expect("Can't get price series");
+        assert!(series.is_empty(), "{}", symbol);
+        let candles = repository::get_candles(symbol.to_string(), 60, from, to, &mut conn)
+            .expect("Can't get candles");
+        assert!(candles.is_empty(), "{}", symbol);
+        let instruments = repository::search_instruments(symbol.to_string(), 20, &mut conn)
+            .expect("Can't search instruments");
+        assert!(instruments.is_empty(), "{}", symbol);
+        // Hostile units and time zones are rejected by Postgres as values, not run as SQL.
+        assert!(repository::get_price_series("AAPL".to_string(), 1, symbol.to_string(), "UTC".to_string(), &mut conn).is_err());
+        assert!(repository::get_price_series("AAPL".to_string(), 1, "hour".to_string(), symbol.to_string(), &mut conn).is_err());
+    }
+    check_trade_intact(&pool);
+}
+
+#[actix_rt::test]
+async fn test_graphql_entry_points_reject_hostile_symbols() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    create_trade(&pool);
+
+    let service = actix_web::test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool.clone()))),
+    )
+    .await;
+
+    let operations = [
+        r#"query($symbol: String!) { searchInstruments(prefix: $symbol) { symbol } }"#,
+        r#"query($symbol: String!) {
+            candles(symbol: $symbol, resolution: ONE_MINUTE, from: "2023-10-01T00:00:00", to: "2023-10-01T12:00:00") { open }
+        }"#,
+        r#"query($symbol: String!) { instrument(symbol: $symbol) { symbol } }"#,
+        r#"mutation($symbol: String!) { buyStocks(stock: { symbol: $symbol, shares: 1 }) { id } }"#,
+        r#"mutation($symbol: String!) {
+            placeOrder(order: { symbol: $symbol, shares: 1, action: BUY, orderType: LIMIT, limitPrice: "1.00" }) { id }
+        }"#,
+    ];
+
+    for symbol in HOSTILE_SYMBOLS {
+        for operation in operations {
+            let mut variables = Map::new();
+            variables.insert("symbol".to_string(), symbol.into());
+            let request_body = GraphQLCustomRequest {
+                query: operation.to_string(),
+                variables,
+            };
+            let request = actix_web::test::TestRequest::post()
+                .uri("/stocks")
+                .set_json(&request_body)
+                .to_request();
+
+            let response: GraphQLCustomResponse = actix_web::test::call_and_read_body_json(&service, request).await;
+
+            let returned_rows = response.data
+                .as_ref()
+                .and_then(|data| data.as_object())
+                .and_then(|data| data.values().next())
+                .is_some_and(|value| value.as_array().map_or(!value.is_null(), |rows| !rows.is_empty()));
+            assert!(!returned_rows, "{} returned data for {}", operation, symbol);
+        }
+    }
+    check_trade_intact(&pool);
+}
+
+// The REST handlers block on the database and on Nasdaq, so they can't run inside an async runtime.
+#[test]
+fn test_rest_handlers_reject_hostile_symbols() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    create_trade(&pool);
+
+    for symbol in HOSTILE_SYMBOLS {
+        for path in ["/buy_stocks", "/sale_stocks"] {
+            let body = json!({ "symbol": symbol, "shares": 1 });
+            let (status, content) = handle_request(&format!("POST {} HTTP/1.1\r\nHost: localhost:8080\r\n\r\n{}", path, body), &pool);
+            assert!(status.starts_with("HTTP/1.1 400"), "{} accepted {}: {}", path, symbol, content);
+            assert!(content.contains("invalid_symbol"), "{}", content);
+        }
+    }
+    assert!(repository::get_orders(&mut pool.get().unwrap()).unwrap().is_empty());
+    assert!(common::fakes().quotes.requests().is_empty());
+    check_trade_intact(&pool);
+}
+
+#[test]
+fn test_consumer_rejects_hostile_messages() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    create_trade(&pool);
+
+    for symbol in HOSTILE_SYMBOLS {
+        let message = format!("{},1,buy", symbol);
+        assert!(process_order_message(message.as_bytes(), &pool).is_err(), "{}", message);
+        let message = format!("AAPL,1,buy,1 OR 1=1; {}", symbol);
+        assert!(process_order_message(message.as_bytes(), &pool).is_err(), "{}", message);
+    }
+    assert!(common::fakes().quotes.requests().is_empty());
+    check_trade_intact(&pool);
+}
+
+#[derive(Serialize)]
+struct GraphQLCustomRequest {
+    query: String,
+    variables: Map<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct GraphQLCustomResponse {
+    data: Option<serde_json::Value>,
+    #[allow(dead_code)]
+    errors: Option<serde_json::Value>,
+}
@@ -0,0 +1,213 @@
+use std::str::FromStr;
+
+use async_graphql::{Request, Variables};
+use bigdecimal::BigDecimal;
+use serde_json::json;
+use testcontainers::clients::Cli;
+
+use stocks_service::create_schema_with_context;
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::repository;
+use stocks_service::stock_functions::{
+    get_cash_balance, lookup_instrument, record_cash_movement, refresh_stale_instruments, trade_notional, AssetClass,
+};
+
+mod common;
+
+const OPTION: &str = "AAPL240119C00150000";
+
+fn decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).unwrap()
+}
+
+/// Runs `query` and returns its data, or the message of its first error.
+async fn execute(pool: &PgPool, query: &str, variables: serde_json::Value) -> Result<serde_json::Value, String> {
+    let response = create_schema_with_context(pool.clone())
+        .execute(Request::new(query).variables(Variables::from_json(variables)))
+        .await;
+    match response.errors.first() {
+        Some(error) => Err(error.message.to_string()),
+        None => Ok(response.data.into_json().expect("Can't read response data")),
+    }
+}
+
+async fn set_asset_class(pool: &PgPool, symbol: &str, asset_class: &str) -> Result<serde_json::Value, String> {
+    let data = execute(
+        pool,
+        r#"mutation($symbol: String!, $assetClass: AssetClass!) {
+            setAssetClass(symbol: $symbol, assetClass: $assetClass) { assetClass sharePrecision contractMultiplier }
+        }"#,
+        json!({ "symbol": symbol, "assetClass": asset_class }),
+    )
+    .await?;
+    Ok(data["setAssetClass"].clone())
+}
+
+async fn buy(pool: &PgPool, symbol: &str, shares: &str) -> Result<(), String> {
+    execute(
+        pool,
+        r#"mutation($symbol: String!, $shares: BigDecimal!) { buyStocks(stock: { symbol: $symbol, shares: $shares }) { id } }"#,
+        json!({ "symbol": symbol, "shares": shares }),
+    )
+    .await
+    .map(|_| ())
+}
+
+#[test]
+fn test_asset_classes() {
+    let cases = [
+        // (name, asset class, trades in contracts, default share precision)
+        ("stocks", AssetClass::Stocks, false, 4),
+        ("etf", AssetClass::Etf, false, 4),
+        ("index", AssetClass::Index, false, 4),
+        ("crypto", AssetClass::Crypto, false, 8),
+        ("options", AssetClass::Options, true, 0),
+    ];
+
+    for (name, asset_class, trades_in_contracts, share_precision) in cases {
+        assert_eq!(Ok(asset_class), AssetClass::from_str(name));
+        assert_eq!(name, asset_class.to_string());
+        assert_eq!(trades_in_contracts, asset_class.trades_in_contracts(), "{}", name);
+        assert_eq!(share_precision, asset_class.default_share_precision(), "{}", name);
+    }
+    assert!(AssetClass::from_str("bonds").is_err());
+    assert_eq!(decimal("700"), trade_notional("3.50", &BigDecimal::from(2), 100));
+}
+
+#[actix_rt::test]
+async fn test_options_trade_in_whole_contracts() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price(OPTION, "3.50");
+    record_cash_movement(1, BigDecimal::from(1000), "USD", None, &pool).unwrap();
+
+    let instrument = set_asset_class(&pool, OPTION, "OPTIONS").await.unwrap();
+    assert_eq!(json!({ "assetClass": "options", "sharePrecision": 0, "contractMultiplier": 100 }), instrument);
+    let error = execute(&pool, &format!(r#"mutation {{ setSharePrecision(symbol: "{}", sharePrecision: 2) {{ symbol }} }}"#, OPTION), json!({}))
+        .await
+        .unwrap_err();
+    assert!(error.contains("whole contracts"), "{}", error);
+
+    let error = buy(&pool, OPTION, "1.5").await.unwrap_err();
+    assert!(error.contains("whole contracts"), "{}", error);
+    buy(&pool, OPTION, "2").await.unwrap();
+
+    let trade = &repository::get_stocks_by_symbol(OPTION.to_string(), &mut pool.get().unwrap())[0];
+    assert_eq!(100, trade.multiplier);
+    assert_eq!(decimal("300"), get_cash_balance(1, "USD", &pool).unwrap());
+    assert!(fakes.quotes.requests().iter().all(|(symbol, asset_class)| symbol == OPTION && asset_class == "options"));
+    let error = buy(&pool, OPTION, "1").await.unwrap_err();
+    assert!(error.contains("Insufficient buying power"), "{}", error);
+}
+
+#[actix_rt::test]
+async fn test_crypto_trades_in_fractions_quoted_as_crypto() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("BTC", "30000.00");
+    record_cash_movement(1, BigDecimal::from(1000), "USD", None, &pool).unwrap();
+
+    let instrument = set_asset_class(&pool, "BTC", "CRYPTO").await.unwrap();
+    assert_eq!(json!({ "assetClass": "crypto", "sharePrecision": 8, "contractMultiplier": 1 }), instrument);
+    buy(&pool, "BTC", "0.00012345").await.unwrap();
+
+    assert_eq!(decimal("996.2965"), get_cash_balance(1, "USD", &pool).unwrap());
+    assert_eq!(vec![("BTC".to_string(), "crypto".to_string())], fakes.quotes.requests()[..1].to_vec());
+    let summary = &repository::get_stocks_summary(1, &mut pool.get().unwrap()).unwrap()[0];
+    assert_eq!(decimal("0.00012345"), summary.shares);
+}
+
+#[actix_rt::test]
+async fn test_contract_multipliers_must_be_positive() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let error = execute(
+        &pool,
+        r#"mutation { setAssetClass(symbol: "SPY", assetClass: ETF, contractMultiplier: 0) { symbol } }"#,
+        json!({}),
+    )
+    .await
+    .unwrap_err();
+
+    assert!(error.contains("contractMultiplier must be positive"), "{}", error);
+    assert!(repository::get_instrument("SPY".to_string(), &mut pool.get().unwrap()).unwrap().is_none());
+}
+
+#[actix_rt::test]
+async fn test_instrument_metadata_is_fetched_once() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "150.00");
+
+    let instrument = lookup_instrument("AAPL", &pool).unwrap();
+    assert_eq!(Some("AAPL Inc. Common Stock".to_string()), instrument.company_name);
+    assert_eq!(Some("NASDAQ-GS".to_string()), instrument.exchange);
+    assert!(instrument.is_listed);
+    lookup_instrument("AAPL", &pool).unwrap();
+
+    assert_eq!(vec![("AAPL".to_string(), "stocks".to_string())], fakes.quotes.requests());
+    let stored = repository::get_instrument("AAPL".to_string(), &mut pool.get().unwrap()).unwrap().unwrap();
+    assert!(stored.refreshed_at.is_some());
+}
+
+#[actix_rt::test]
+async fn test_instrument_searches_are_limited() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "150.00");
+    lookup_instrument("AAPL", &pool).unwrap();
+    let query = "query($limit: Int!) { searchInstruments(prefix: \"aa\", limit: $limit) { symbol } }";
+
+    let found = execute(&pool, query, json!({ "limit": 100 })).await.unwrap();
+    assert_eq!(json!({ "searchInstruments": [{ "symbol": "AAPL" }] }), found);
+    for limit in [0, 101, -1] {
+        let error = execute(&pool, query, json!({ "limit": limit })).await.unwrap_err();
+        assert!(error.contains("limit must be from 1 to 100"), "{}", error);
+    }
+}
+
+#[actix_rt::test]
+async fn test_unknown_symbols_are_not_stored() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    assert!(lookup_instrument("NOPE", &pool).is_err());
+
+    assert!(repository::get_instrument("NOPE".to_string(), &mut pool.get().unwrap()).unwrap().is_none());
+}
+
+#[actix_rt::test]
+async fn test_refreshing_marks_delisted_instruments() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "150.00");
+    lookup_instrument("AAPL", &pool).unwrap();
+
+    fakes.quotes.delist("AAPL");
+    refresh_stale_instruments(chrono::Duration::zero(), &pool).unwrap();
+
+    let error = lookup_instrument("AAPL", &pool).err().unwrap().to_string();
+    assert!(error.contains("no longer listed"), "{}", error);
+    let error = buy(&pool, "AAPL", "1").await.unwrap_err();
+    assert!(error.contains("no longer listed"), "{}", error);
+}
+
+#[actix_rt::test]
+async fn test_fresh_instruments_are_not_refreshed() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "150.00");
+    lookup_instrument("AAPL", &pool).unwrap();
+
+    refresh_stale_instruments(chrono::Duration::hours(1), &pool).unwrap();
+    assert_eq!(1, fakes.quotes.requests().len());
+    refresh_stale_instruments(chrono::Duration::zero(), &pool).unwrap();
+    assert_eq!(2, fakes.quotes.requests().len());
+}
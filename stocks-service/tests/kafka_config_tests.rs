@@ -0,0 +1,35 @@
+use common_utils::event_bus::KafkaEventBus;
+use common_utils::kafka_config::KafkaConfig;
+
+fn config(security_protocol: &str, sasl_mechanism: Option<&str>, compression: &str) -> KafkaConfig {
+    KafkaConfig {
+        brokers: "localhost:9".to_string(),
+        security_protocol: security_protocol.to_string(),
+        sasl_mechanism: sasl_mechanism.map(str::to_string),
+        sasl_username: sasl_mechanism.map(|_| "stocks".to_string()),
+        sasl_password: sasl_mechanism.map(|_| "secret".to_string()),
+        compression: compression.to_string(),
+        ..KafkaConfig::from_env()
+    }
+}
+
+#[test]
+fn test_documented_security_and_compression_settings_are_supported() {
+    let cases = [
+        ("plaintext", None, "none"),
+        ("ssl", None, "gzip"),
+        ("sasl_plaintext", Some("PLAIN"), "snappy"),
+        ("sasl_ssl", Some("SCRAM-SHA-256"), "lz4"),
+        ("sasl_ssl", Some("SCRAM-SHA-512"), "zstd"),
+    ];
+
+    for (security_protocol, sasl_mechanism, compression) in cases {
+        let bus = KafkaEventBus::new(config(security_protocol, sasl_mechanism, compression));
+        assert!(bus.is_ok(), "{} {:?} {}: {:?}", security_protocol, sasl_mechanism, compression, bus.err());
+    }
+}
+
+#[test]
+fn test_kerberos_is_not_supported() {
+    assert!(KafkaEventBus::new(config("sasl_ssl", Some("GSSAPI"), "none")).is_err());
+}
@@ -0,0 +1,33 @@
+use diesel_migrations::MigrationHarness;
+use testcontainers::clients::Cli;
+
+use stocks_domain::{check_schema_version, MIGRATIONS};
+
+mod common;
+
+#[actix_rt::test]
+async fn test_every_migration_reverts() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+
+    let applied = conn.applied_migrations().expect("Can't get applied migrations").len();
+    conn.revert_all_migrations(MIGRATIONS).expect("Can't revert the migrations");
+    assert!(conn.applied_migrations().expect("Can't get applied migrations").is_empty());
+
+    conn.run_pending_migrations(MIGRATIONS).expect("Can't reapply the migrations");
+    assert_eq!(applied, conn.applied_migrations().expect("Can't get applied migrations").len());
+}
+
+#[actix_rt::test]
+async fn test_schema_version_check_fails_on_pending_migrations() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut conn = pool.get().expect("Can't get DB connection");
+
+    assert!(check_schema_version(&mut conn).is_ok());
+
+    conn.revert_last_migration(MIGRATIONS).expect("Can't revert the last migration");
+    let error = check_schema_version(&mut conn).expect_err("Schema check should fail");
+    assert!(error.contains("missing 1 migration"), "{}", error);
+}
@@ -1,17 +1,21 @@
-use std::env;
+use std::str::FromStr;
 
 use actix_web::{test, web, App};
+use bigdecimal::BigDecimal;
 use jsonpath_lib as jsonpath;
 use serde::{Deserialize, Serialize};
 use serde_json::Map;
 use testcontainers::clients::Cli;
 
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::model::NewOrderEntity;
+use stocks_service::persistence::repository;
 use stocks_service::{configure_service, create_schema_with_context};
 
 mod common;
 
 #[actix_rt::test]
-async fn test_create_user() {
+async fn test_deposit_and_withdraw() {
     let docker = Cli::default();
     let (_pg_container, pool) = common::setup(&docker);
 
@@ -23,46 +27,177 @@ async fn test_create_user() {
     .await;
 
     let mutation = r#"
-        mutation(
-            $name: String!
-            $email: String!
-        ) {
-            createUser(
-                user: {
-                    name: $name
-                    email: $email
-                }
-            ) {
-                id
-                name
-                email
+        mutation($deposit: String!, $withdrawal: String!) {
+            deposit(amount: $deposit) {
+                entryType
+                amount
+                currency
+            }
+            withdraw(amount: $withdrawal) {
+                entryType
+                amount
+                currency
             }
         }
-        "#
-    .to_string();
-
+        "#;
     let mut variables = Map::new();
-    variables.insert("name".to_string(), "Test PZM".into());
-    variables.insert("email".to_string(), "pablo.zuniga.mata@gmail.com".into());
+    variables.insert("deposit".to_string(), "1000.00".into());
+    variables.insert("withdrawal".to_string(), "250".into());
 
-    let request_body = GraphQLCustomRequest {
-        query: mutation,
-        variables,
-    };
+    let request = test::TestRequest::post()
+        .uri("/stocks")
+        .set_json(&GraphQLCustomRequest { query: mutation.to_string(), variables })
+        .to_request();
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    let response_data = response.data.expect("Response doesn't contain data");
+
+    check_entry(&response_data, "$.deposit", "deposit", "1000.00");
+    check_entry(&response_data, "$.withdraw", "withdrawal", "-250");
 
     let request = test::TestRequest::post()
-        .uri("/")
-        .set_json(&request_body)
+        .uri("/stocks")
+        .set_json(&GraphQLCustomRequest { query: "{ cashBalance }".to_string(), variables: Map::new() })
         .to_request();
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    let balance = jsonpath::select(&response.data.expect("Response doesn't contain data"), "$.cashBalance")
+        .expect("Can't get cash balance by JSON path")[0]
+        .as_str()
+        .expect("Can't get cash balance as str")
+        .parse::<BigDecimal>()
+        .expect("Can't parse cash balance");
+    assert_eq!(BigDecimal::from(750), balance);
+}
 
+#[actix_rt::test]
+async fn test_withdraw_more_than_balance_fails() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool))),
+    )
+    .await;
+
+    let request = test::TestRequest::post()
+        .uri("/stocks")
+        .set_json(&GraphQLCustomRequest {
+            query: r#"mutation { withdraw(amount: "1") { id } }"#.to_string(),
+            variables: Map::new(),
+        })
+        .to_request();
     let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
 
-    let response_data = response.data.expect("Response doesn't contain data");
+    assert!(response.data.is_none_or(|data| data["withdraw"].is_null()));
+    assert!(response.errors.is_some());
+}
+
+#[actix_rt::test]
+async fn test_orders_of_other_users_cant_be_cancelled_or_amended() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let order_id = create_pending_order(&pool, 2, Some("10"), None);
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool.clone()))),
+    )
+    .await;
+
+    let mutation = format!(
+        r#"mutation {{
+            amendOrder(id: "{0}", amendment: {{ limitPrice: "90" }}) {{ id }}
+        }}"#,
+        order_id
+    );
+    let request = test::TestRequest::post()
+        .uri("/stocks")
+        .set_json(&GraphQLCustomRequest { query: mutation, variables: Map::new() })
+        .to_request();
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    assert!(response.errors.is_some());
+
+    let mutation = format!(r#"mutation {{ cancelOrder(id: "{}") {{ id }} }}"#, order_id);
+    let request = test::TestRequest::post()
+        .uri("/stocks")
+        .set_json(&GraphQLCustomRequest { query: mutation, variables: Map::new() })
+        .to_request();
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    assert!(response.errors.is_some());
 
-    let created_user_json = jsonpath::select(&response_data, "$.createUser")
-        .expect("Can't get created user by JSON path")[0];
+    let order = repository::get_order(order_id, &mut pool.get().expect("Can't get DB connection")).unwrap();
+    assert_eq!("pending", order.status);
+    assert_eq!(None, order.limit_price);
 
-    common::check_user(created_user_json, 9, "Test PZM", "pablo.zuniga.mata@gmail.com");
+    let mutation = format!(r#"mutation {{ cancelOrder(id: "{}", userId: 2) {{ status }} }}"#, order_id);
+    let request = test::TestRequest::post()
+        .uri("/stocks")
+        .set_json(&GraphQLCustomRequest { query: mutation, variables: Map::new() })
+        .to_request();
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+    assert!(response.errors.is_none());
+    let order = repository::get_order(order_id, &mut pool.get().expect("Can't get DB connection")).unwrap();
+    assert_eq!("cancelled", order.status);
+}
+
+#[actix_rt::test]
+async fn test_shares_of_orders_placed_by_amount_cant_be_amended() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let order_id = create_pending_order(&pool, 1, None, Some("1000"));
+
+    let service = test::init_service(
+        App::new()
+            .configure(configure_service)
+            .app_data(web::Data::new(create_schema_with_context(pool.clone()))),
+    )
+    .await;
+
+    let mutation = format!(
+        r#"mutation {{
+            amendOrder(id: "{}", amendment: {{ shares: "5" }}) {{ id }}
+        }}"#,
+        order_id
+    );
+    let request = test::TestRequest::post()
+        .uri("/stocks")
+        .set_json(&GraphQLCustomRequest { query: mutation, variables: Map::new() })
+        .to_request();
+    let response: GraphQLCustomResponse = test::call_and_read_body_json(&service, request).await;
+
+    let errors = response.errors.expect("Amending the shares should fail").to_string();
+    assert!(errors.contains("placed by amount"), "{}", errors);
+    let order = repository::get_order(order_id, &mut pool.get().expect("Can't get DB connection")).unwrap();
+    assert_eq!(None, order.shares);
+}
+
+fn create_pending_order(pool: &PgPool, user_id: i32, shares: Option<&str>, amount: Option<&str>) -> i32 {
+    repository::create_order(NewOrderEntity {
+        symbol: "AAPL".to_string(),
+        shares: shares.map(|shares| BigDecimal::from_str(shares).unwrap()),
+        amount: amount.map(|amount| BigDecimal::from_str(amount).unwrap()),
+        action_type: "buy".to_string(),
+        order_type: "market".to_string(),
+        stop_price: None,
+        limit_price: None,
+        status: "pending".to_string(),
+        parent_id: None,
+        user_id,
+    }, &mut pool.get().expect("Can't get DB connection"))
+    .expect("Can't create an order")
+    .id
+}
+
+fn check_entry(response_data: &serde_json::Value, path: &str, entry_type: &str, amount: &str) {
+    let entry = jsonpath::select(response_data, path).expect("Can't get ledger entry by JSON path")[0];
+    assert_eq!(entry_type, entry["entryType"].as_str().expect("Can't get entry type as str"));
+    assert_eq!("USD", entry["currency"].as_str().expect("Can't get currency as str"));
+    assert_eq!(
+        BigDecimal::from_str(amount).unwrap(),
+        entry["amount"].as_str().expect("Can't get amount as str").parse::<BigDecimal>().unwrap()
+    );
 }
 
 #[derive(Serialize)]
@@ -74,6 +209,5 @@ struct GraphQLCustomRequest {
 #[derive(Deserialize)]
 struct GraphQLCustomResponse {
     data: Option<serde_json::Value>,
-    #[allow(dead_code)]
     errors: Option<serde_json::Value>,
-}
\ No newline at end of file
+}
@@ -0,0 +1,175 @@
+use std::str::FromStr;
+
+use async_graphql::{Request, Variables};
+use bigdecimal::BigDecimal;
+use serde_json::json;
+use testcontainers::clients::Cli;
+
+use stocks_service::create_schema_with_context;
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::model::InstrumentEntity;
+use stocks_service::persistence::repository;
+use stocks_service::stock_functions::{
+    evaluate_pending_orders, get_cash_balance, is_valid_quantity, record_cash_movement, shares_for_amount,
+};
+
+mod common;
+
+fn decimal(value: &str) -> BigDecimal {
+    BigDecimal::from_str(value).unwrap()
+}
+
+fn instrument(share_precision: i32, contract_multiplier: i32) -> InstrumentEntity {
+    InstrumentEntity {
+        symbol: "AAPL".to_string(),
+        share_precision,
+        currency: "USD".to_string(),
+        asset_class: if contract_multiplier == 1 { "stocks" } else { "options" }.to_string(),
+        contract_multiplier,
+        company_name: None,
+        exchange: None,
+        stock_type: None,
+        is_nasdaq_listed: true,
+        is_nasdaq100: false,
+        is_listed: true,
+        refreshed_at: None,
+    }
+}
+
+/// Runs `query` and returns its data, or the message of its first error.
+async fn execute(pool: &PgPool, query: &str, variables: serde_json::Value) -> Result<serde_json::Value, String> {
+    let response = create_schema_with_context(pool.clone())
+        .execute(Request::new(query).variables(Variables::from_json(variables)))
+        .await;
+    match response.errors.first() {
+        Some(error) => Err(error.message.to_string()),
+        None => Ok(response.data.into_json().expect("Can't read response data")),
+    }
+}
+
+async fn buy(pool: &PgPool, quantity: serde_json::Value) -> Result<BigDecimal, String> {
+    let data = execute(
+        pool,
+        r#"mutation($shares: BigDecimal, $amount: BigDecimal) {
+            buyStocks(stock: { symbol: "AAPL", shares: $shares, amount: $amount }) { shares }
+        }"#,
+        quantity,
+    )
+    .await?;
+    Ok(decimal(data["buyStocks"]["shares"].as_str().expect("Can't get shares")))
+}
+
+#[test]
+fn test_quantities_respect_the_share_precision() {
+    let cases = [
+        // (shares, share precision, valid)
+        ("1", 0, true),
+        ("1.5", 0, false),
+        ("0.5", 4, true),
+        ("0.0001", 4, true),
+        ("0.00001", 4, false),
+        ("1.50000", 1, true),
+        ("0", 4, false),
+        ("-1", 4, false),
+    ];
+
+    for (shares, share_precision, valid) in cases {
+        assert_eq!(valid, is_valid_quantity(&decimal(shares), share_precision), "{} at precision {}", shares, share_precision);
+    }
+}
+
+#[test]
+fn test_amounts_buy_shares_rounded_down_to_the_precision() {
+    let cases = [
+        // (amount, price, share precision, multiplier, shares)
+        ("250", "100", 4, 1, "2.5"),
+        ("100", "3", 4, 1, "33.3333"),
+        ("100", "3", 0, 1, "33"),
+        ("0.001", "100", 4, 1, "0"),
+        // Options buy whole contracts of `multiplier` units.
+        ("1000", "4.50", 0, 100, "2"),
+        ("100", "0", 4, 1, "0"),
+    ];
+
+    for (amount, price, share_precision, multiplier, shares) in cases {
+        assert_eq!(
+            decimal(shares),
+            shares_for_amount(&decimal(amount), price, &instrument(share_precision, multiplier)),
+            "{} at {} with precision {} and multiplier {}", amount, price, share_precision, multiplier
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn test_buy_fractional_shares() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    record_cash_movement(1, BigDecimal::from(1000), "USD", None, &pool).unwrap();
+
+    assert_eq!(decimal("0.5"), buy(&pool, json!({ "shares": "0.5" })).await.unwrap());
+    let error = buy(&pool, json!({ "shares": "0.00001" })).await.unwrap_err();
+    assert!(error.contains("at most 4 decimals"), "{}", error);
+
+    execute(&pool, r#"mutation { setSharePrecision(symbol: "AAPL", sharePrecision: 0) { symbol } }"#, json!({}))
+        .await
+        .unwrap();
+    let error = buy(&pool, json!({ "shares": "1.5" })).await.unwrap_err();
+    assert!(error.contains("at most 0 decimals"), "{}", error);
+
+    let summaries = repository::get_stocks_summary(1, &mut pool.get().unwrap()).unwrap();
+    assert_eq!(decimal("0.5"), summaries[0].shares);
+    assert_eq!(decimal("950"), get_cash_balance(1, "USD", &pool).unwrap());
+}
+
+#[actix_rt::test]
+async fn test_buy_by_amount() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "80.00");
+    record_cash_movement(1, BigDecimal::from(1000), "USD", None, &pool).unwrap();
+
+    assert_eq!(decimal("3.125"), buy(&pool, json!({ "amount": "250" })).await.unwrap());
+    let error = buy(&pool, json!({ "amount": "0.001" })).await.unwrap_err();
+    assert!(error.contains("doesn't buy any shares"), "{}", error);
+    let error = buy(&pool, json!({ "shares": "1", "amount": "100" })).await.unwrap_err();
+    assert!(error.contains("Exactly one of shares or amount"), "{}", error);
+    assert_eq!(decimal("750"), get_cash_balance(1, "USD", &pool).unwrap());
+}
+
+#[actix_rt::test]
+async fn test_amount_orders_get_their_shares_at_the_fill_price() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "110.00");
+    record_cash_movement(1, BigDecimal::from(1000), "USD", None, &pool).unwrap();
+
+    let data = execute(
+        &pool,
+        r#"mutation {
+            placeOrder(order: { symbol: "AAPL", amount: "500", action: BUY, orderType: LIMIT, limitPrice: "100.00" }) { id shares }
+        }"#,
+        json!({}),
+    )
+    .await
+    .unwrap();
+    assert!(data["placeOrder"]["shares"].is_null());
+    let order_id = data["placeOrder"]["id"].as_str().unwrap().parse::<i32>().unwrap();
+    let error = execute(
+        &pool,
+        r#"mutation { placeOrder(order: { symbol: "AAPL", amount: "500", action: SALE, orderType: LIMIT, limitPrice: "100.00" }) { id } }"#,
+        json!({}),
+    )
+    .await
+    .unwrap_err();
+    assert!(error.contains("only supported for buys"), "{}", error);
+
+    fakes.quotes.set_price("AAPL", "80.00");
+    evaluate_pending_orders(&pool).unwrap();
+
+    let order = repository::get_order(order_id, &mut pool.get().unwrap()).unwrap();
+    assert_eq!("filled", order.status);
+    assert_eq!(Some(decimal("6.25")), order.shares);
+    assert_eq!(decimal("500"), get_cash_balance(1, "USD", &pool).unwrap());
+}
@@ -0,0 +1,72 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use common_utils::quote_cache::QuoteCache;
+use common_utils::quote_client::QuoteError;
+use common_utils::CustomResult;
+
+/// A cache over a provider that knows every symbol but `UNKNOWN`, counting its requests.
+fn counting_cache(ttl: Duration, stale_while_revalidate: Duration) -> (QuoteCache, Arc<AtomicUsize>) {
+    let requests = Arc::new(AtomicUsize::new(0));
+    let blocking_requests = requests.clone();
+    let cache = QuoteCache::new(
+        ttl,
+        stale_while_revalidate,
+        move |symbol, _| {
+            blocking_requests.fetch_add(1, Ordering::SeqCst);
+            quote(symbol)
+        },
+        |symbol, _| async move { quote(&symbol) },
+    );
+    (cache, requests)
+}
+
+fn quote(symbol: &str) -> Result<CustomResult, QuoteError> {
+    if symbol == "UNKNOWN" {
+        return Err(QuoteError::Status(404));
+    }
+    Ok(CustomResult {
+        stock: None,
+        success: true,
+        message: symbol.to_string(),
+    })
+}
+
+#[test]
+fn test_quotes_are_served_from_the_cache_until_they_expire() {
+    let (cache, requests) = counting_cache(Duration::from_millis(100), Duration::ZERO);
+
+    assert_eq!("AAPL", cache.get("AAPL", "stocks").message);
+    assert_eq!("AAPL", cache.get("AAPL", "stocks").message);
+    assert_eq!(1, requests.load(Ordering::SeqCst));
+
+    thread::sleep(Duration::from_millis(150));
+    cache.get("AAPL", "stocks");
+    assert_eq!(2, requests.load(Ordering::SeqCst));
+}
+
+#[test]
+fn test_expired_quotes_are_evicted_when_another_is_stored() {
+    let (cache, _) = counting_cache(Duration::from_millis(50), Duration::from_millis(50));
+
+    cache.get("AAPL", "stocks");
+    cache.get("MSFT", "stocks");
+    assert_eq!(2, cache.len());
+
+    thread::sleep(Duration::from_millis(150));
+    cache.get("TSLA", "stocks");
+    assert_eq!(1, cache.len());
+}
+
+#[test]
+fn test_failed_fetches_are_not_kept() {
+    let (cache, requests) = counting_cache(Duration::from_secs(60), Duration::ZERO);
+
+    assert!(!cache.get("UNKNOWN", "stocks").success);
+    assert!(!cache.get("UNKNOWN", "stocks").success);
+
+    assert!(cache.is_empty());
+    assert_eq!(2, requests.load(Ordering::SeqCst));
+}
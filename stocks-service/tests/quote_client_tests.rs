@@ -0,0 +1,97 @@
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use common_utils::quote_client::{QuoteClient, QuoteClientConfig, QuoteError};
+
+/// Serves every request with `status` on a local port, returning its URL and the request lines it
+/// answered.
+fn serve_status(status: &'static str) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let answered = requests.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let request_line = String::from_utf8_lossy(&request).lines().next().unwrap_or_default().to_string();
+            answered.lock().unwrap().push(request_line);
+            let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (url, requests)
+}
+
+fn config(base_url: String, max_attempts: u32, failure_threshold: u32) -> QuoteClientConfig {
+    QuoteClientConfig {
+        base_url,
+        connect_timeout: Duration::from_secs(1),
+        request_timeout: Duration::from_secs(1),
+        max_attempts,
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(1),
+        failure_threshold,
+        open_duration: Duration::from_secs(60),
+    }
+}
+
+#[test]
+fn test_the_circuit_counts_failed_requests_not_attempts() {
+    let (url, requests) = serve_status("503 Service Unavailable");
+    let client = QuoteClient::new(config(url, 3, 2));
+
+    assert!(matches!(client.get_quote_blocking("AAPL", "stocks"), Err(QuoteError::Status(503))));
+    assert_eq!(3, requests.lock().unwrap().len());
+    assert!(matches!(client.get_quote_blocking("AAPL", "stocks"), Err(QuoteError::Status(503))));
+    assert_eq!(6, requests.lock().unwrap().len());
+
+    assert!(matches!(client.get_quote_blocking("AAPL", "stocks"), Err(QuoteError::CircuitOpen)));
+    assert_eq!(6, requests.lock().unwrap().len());
+}
+
+#[test]
+fn test_permanent_errors_are_not_retried() {
+    let (url, requests) = serve_status("404 Not Found");
+    let client = QuoteClient::new(config(url, 3, 1));
+
+    assert!(matches!(client.get_quote_blocking("AAPL", "stocks"), Err(QuoteError::Status(404))));
+    assert_eq!(1, requests.lock().unwrap().len());
+    assert!(matches!(client.get_quote_blocking("AAPL", "stocks"), Err(QuoteError::CircuitOpen)));
+}
+
+#[actix_rt::test]
+async fn test_async_requests_share_the_circuit() {
+    let (url, requests) = serve_status("503 Service Unavailable");
+    let client = QuoteClient::new(config(url, 2, 1));
+
+    assert!(matches!(client.get_quote("AAPL", "stocks").await, Err(QuoteError::Status(503))));
+    assert_eq!(2, requests.lock().unwrap().len());
+
+    assert!(matches!(client.get_quote_blocking("AAPL", "stocks"), Err(QuoteError::CircuitOpen)));
+    assert_eq!(2, requests.lock().unwrap().len());
+}
+
+#[test]
+fn test_symbols_and_asset_classes_are_percent_encoded() {
+    let (url, requests) = serve_status("404 Not Found");
+    let client = QuoteClient::new(config(format!("{}/", url), 1, 10));
+
+    assert!(client.get_quote_blocking("A/../B?c#d", "stocks&assetclass=crypto").is_err());
+
+    assert_eq!(
+        vec!["GET /api/quote/A%2F..%2FB%3Fc%23d/info?assetclass=stocks%26assetclass%3Dcrypto HTTP/1.1".to_string()],
+        *requests.lock().unwrap()
+    );
+}
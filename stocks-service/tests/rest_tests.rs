@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use serde_json::{json, Value};
+use testcontainers::clients::Cli;
+
+use stocks_endpoints::handle_request;
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::model::{NewOrderEntity, OrderEntity};
+use stocks_service::persistence::repository;
+
+mod common;
+
+// The REST handlers block on the database and on Nasdaq, so they can't run inside an async runtime.
+
+fn post(pool: &PgPool, path: &str, body: Value) -> (String, String) {
+    handle_request(&format!("POST {} HTTP/1.1\r\nHost: localhost:8080\r\n\r\n{}", path, body), pool)
+}
+
+fn assert_status(status: &str, response: &(String, String)) {
+    assert!(response.0.starts_with(&format!("HTTP/1.1 {}", status)), "{:?}", response);
+}
+
+fn create_order(pool: &PgPool, shares: Option<i32>, amount: Option<i32>, order_type: &str, stop_price: Option<&str>) -> OrderEntity {
+    repository::create_order(NewOrderEntity {
+        symbol: "AAPL".to_string(),
+        shares: shares.map(BigDecimal::from),
+        amount: amount.map(BigDecimal::from),
+        action_type: if amount.is_some() { "buy" } else { "sale" }.to_string(),
+        order_type: order_type.to_string(),
+        stop_price: stop_price.map(str::to_string),
+        limit_price: None,
+        status: "pending".to_string(),
+        parent_id: None,
+        user_id: 1,
+    }, &mut pool.get().expect("Can't get DB connection"))
+    .expect("Can't create an order")
+}
+
+fn get_order(pool: &PgPool, order: &OrderEntity) -> OrderEntity {
+    repository::get_order(order.id, &mut pool.get().expect("Can't get DB connection")).expect("Can't get order")
+}
+
+#[test]
+fn test_rest_amend_rejects_invalid_prices() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let order = create_order(&pool, Some(10), None, "stop", Some("90.00"));
+
+    for (stop_price, code) in [("lots", "invalid_price"), ("1e", "invalid_price"), ("0", "not_positive"), ("-5.00", "not_positive")] {
+        let response = post(&pool, "/amend_order", json!({ "order_id": order.id, "stop_price": stop_price }));
+        assert_status("400", &response);
+        let error: Value = serde_json::from_str(&response.1).expect("Not a validation error");
+        assert_eq!(json!({ "field": "stop_price", "code": code }), json!({ "field": error["field"], "code": error["code"] }));
+    }
+    assert_eq!(Some("90.00".to_string()), get_order(&pool, &order).stop_price);
+
+    assert_status("200", &post(&pool, "/amend_order", json!({ "order_id": order.id, "stop_price": "85.50" })));
+    let amended = get_order(&pool, &order).stop_price.expect("No stop price");
+    assert_eq!(BigDecimal::from_str("85.50").unwrap(), BigDecimal::from_str(&amended).unwrap());
+}
+
+#[test]
+fn test_rest_orders_can_only_be_changed_by_their_owner() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let other_user = common::create_user(&pool, "Grace");
+    let order = create_order(&pool, Some(10), None, "stop", Some("90.00"));
+
+    assert_status("404", &post(&pool, "/amend_order", json!({ "order_id": order.id, "user_id": other_user, "shares": 1 })));
+    assert_status("404", &post(&pool, "/cancel_order", json!({ "order_id": order.id, "user_id": other_user })));
+    let unchanged = get_order(&pool, &order);
+    assert_eq!("pending", unchanged.status);
+    assert_eq!(Some(BigDecimal::from(10)), unchanged.shares);
+
+    assert_status("200", &post(&pool, "/cancel_order", json!({ "order_id": order.id, "user_id": 1 })));
+    assert_eq!("cancelled", get_order(&pool, &order).status);
+}
+
+#[test]
+fn test_rest_amount_orders_cant_be_amended_to_shares() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let order = create_order(&pool, None, Some(500), "market", None);
+
+    let response = post(&pool, "/amend_order", json!({ "order_id": order.id, "shares": 5 }));
+
+    assert_status("400", &response);
+    assert!(response.1.contains("placed by amount"), "{}", response.1);
+    let unchanged = get_order(&pool, &order);
+    assert_eq!(None, unchanged.shares);
+    assert_eq!(Some(BigDecimal::from(500)), unchanged.amount);
+}
+
+#[test]
+fn test_rest_sales_are_queued_as_sales() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "150.00");
+
+    let response = post(&pool, "/sale_stocks", json!({ "symbol": "aapl", "shares": 2 }));
+
+    assert_status("200", &response);
+    assert!(response.1.contains("queued the sale"), "{}", response.1);
+    let orders = repository::get_orders(&mut pool.get().expect("Can't get DB connection")).expect("Can't get orders");
+    assert_eq!(1, orders.len());
+    assert_eq!(("AAPL", "sale", Some(BigDecimal::from(2))), (orders[0].symbol.as_str(), orders[0].action_type.as_str(), orders[0].shares.clone()));
+}
+
+#[test]
+fn test_rest_orders_trade_in_the_instrument_precision() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "150.00");
+
+    let response = post(&pool, "/buy_stocks", json!({ "symbol": "AAPL", "shares": "0.00001" }));
+
+    assert_status("400", &response);
+    assert!(response.1.contains("at most 4 decimals"), "{}", response.1);
+    assert!(repository::get_orders(&mut pool.get().expect("Can't get DB connection")).expect("Can't get orders").is_empty());
+}
@@ -0,0 +1,159 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use async_graphql::{Request, Variables};
+use bigdecimal::BigDecimal;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use serde_json::json;
+use testcontainers::clients::Cli;
+
+use common_utils::event_bus::{event_bus, trade_events_topic};
+use stocks_service::create_schema_with_context;
+use stocks_service::persistence::connection::PgPool;
+
+mod common;
+
+async fn execute(pool: &PgPool, query: &str, variables: serde_json::Value) -> serde_json::Value {
+    let response = create_schema_with_context(pool.clone())
+        .execute(Request::new(query).variables(Variables::from_json(variables)))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().expect("Can't read response data")
+}
+
+async fn buy(pool: &PgPool, symbol: &str, shares: u32) {
+    execute(
+        pool,
+        r#"mutation($symbol: String!, $shares: BigDecimal!) {
+            buyStocks(stock: { symbol: $symbol, shares: $shares }) { id }
+        }"#,
+        json!({ "symbol": symbol, "shares": shares.to_string() }),
+    )
+    .await;
+}
+
+/// Starts the subscription in `query`, which only receives the events published afterwards.
+async fn subscribe(pool: &PgPool, query: &str) -> BoxStream<'static, async_graphql::Response> {
+    let mut stream = create_schema_with_context(pool.clone()).execute_stream(Request::new(query)).boxed();
+    // The first poll subscribes.
+    assert!(actix_rt::time::timeout(Duration::from_millis(100), stream.next()).await.is_err());
+    stream
+}
+
+async fn next(stream: &mut BoxStream<'static, async_graphql::Response>, field: &str) -> serde_json::Value {
+    let response = actix_rt::time::timeout(Duration::from_secs(5), stream.next())
+        .await
+        .expect("No event received")
+        .expect("Stream ended");
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().expect("Can't read response data")[field].clone()
+}
+
+fn decimal(value: &serde_json::Value) -> BigDecimal {
+    BigDecimal::from_str(value.as_str().expect("Can't get value as str")).expect("Can't parse decimal")
+}
+
+#[actix_rt::test]
+async fn test_order_updates_are_filtered_by_symbol() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "100.00");
+    fakes.quotes.set_price("MSFT", "300.00");
+    execute(&pool, r#"mutation { deposit(amount: "10000") { id } }"#, json!({})).await;
+    let mut aapl_orders = subscribe(&pool, r#"subscription { orderUpdates(symbol: "aapl") { symbol status actionType } }"#).await;
+    let mut all_orders = subscribe(&pool, "subscription { orderUpdates { symbol status } }").await;
+
+    buy(&pool, "MSFT", 1).await;
+    buy(&pool, "AAPL", 10).await;
+
+    let order = next(&mut aapl_orders, "orderUpdates").await;
+    assert_eq!(json!({ "symbol": "AAPL", "status": "filled", "actionType": "buy" }), order);
+    assert_eq!("MSFT", next(&mut all_orders, "orderUpdates").await["symbol"]);
+    assert_eq!("AAPL", next(&mut all_orders, "orderUpdates").await["symbol"]);
+}
+
+#[actix_rt::test]
+async fn test_trade_executed_and_portfolio_changed() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    let mut trades = subscribe(&pool, "subscription { tradeExecuted { symbol shares price actionType } }").await;
+    let mut positions = subscribe(
+        &pool,
+        r#"subscription { portfolioChanged(symbol: "AAPL") { symbol position { shares } cashBalance } }"#,
+    )
+    .await;
+    let mut cash = subscribe(&pool, "subscription { portfolioChanged { symbol cashBalance } }").await;
+
+    execute(&pool, r#"mutation { deposit(amount: "10000") { id } }"#, json!({})).await;
+    let deposit = next(&mut cash, "portfolioChanged").await;
+    assert_eq!(serde_json::Value::Null, deposit["symbol"]);
+    assert_eq!(BigDecimal::from(10_000), decimal(&deposit["cashBalance"]));
+
+    buy(&pool, "AAPL", 10).await;
+    let trade = next(&mut trades, "tradeExecuted").await;
+    assert_eq!("AAPL", trade["symbol"]);
+    assert_eq!("buy", trade["actionType"]);
+    assert_eq!(BigDecimal::from(10), decimal(&trade["shares"]));
+    assert_eq!(BigDecimal::from(100), decimal(&trade["price"]));
+    let position = next(&mut positions, "portfolioChanged").await;
+    assert_eq!("AAPL", position["symbol"]);
+    assert_eq!(BigDecimal::from(10), decimal(&position["position"]["shares"]));
+    assert_eq!("AAPL", next(&mut cash, "portfolioChanged").await["symbol"]);
+}
+
+#[actix_rt::test]
+async fn test_invalid_trade_events_are_skipped() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let mut trades = subscribe(&pool, "subscription { tradeExecuted { symbol } }").await;
+
+    event_bus()
+        .publish(trade_events_topic(), None, b"AAPL,10,buy")
+        .expect("Can't publish an event");
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    execute(&pool, r#"mutation { deposit(amount: "10000") { id } }"#, json!({})).await;
+    buy(&pool, "AAPL", 1).await;
+
+    assert_eq!("AAPL", next(&mut trades, "tradeExecuted").await["symbol"]);
+}
+
+#[actix_rt::test]
+async fn test_portfolio_valuation_reprices_holdings() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "100.00");
+    execute(&pool, r#"mutation { deposit(amount: "10000") { id } }"#, json!({})).await;
+    buy(&pool, "AAPL", 10).await;
+    let mut valuations = create_schema_with_context(pool.clone())
+        .execute_stream(Request::new(
+            "subscription {
+                portfolioValuation(intervalSecs: 1) {
+                    marketValue dayChange costBasis unrealizedProfitLoss unpricedSymbols
+                    positions { symbol shares price marketValue dayChange }
+                }
+            }",
+        ))
+        .boxed();
+
+    let first = next(&mut valuations, "portfolioValuation").await;
+    assert_eq!(BigDecimal::from(1000), decimal(&first["marketValue"]));
+    // The fake quotes moved 0.75 since the previous close.
+    assert_eq!(BigDecimal::from_str("7.5").unwrap(), decimal(&first["dayChange"]));
+    assert_eq!(json!([]), first["unpricedSymbols"]);
+    let position = &first["positions"][0];
+    assert_eq!("AAPL", position["symbol"]);
+    assert_eq!(BigDecimal::from(10), decimal(&position["shares"]));
+    assert_eq!(BigDecimal::from(100), decimal(&position["price"]));
+
+    fakes.quotes.set_price("AAPL", "120.00");
+    let second = next(&mut valuations, "portfolioValuation").await;
+    assert_eq!(BigDecimal::from(1200), decimal(&second["marketValue"]));
+    assert_eq!(
+        decimal(&second["marketValue"]) - decimal(&second["costBasis"]),
+        decimal(&second["unrealizedProfitLoss"])
+    );
+}
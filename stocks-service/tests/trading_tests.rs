@@ -0,0 +1,641 @@
+use std::str::FromStr;
+
+use async_graphql::{Request, Variables};
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::sql_types::{Int4, Timestamp};
+use diesel::RunQueryDsl;
+use serde_json::json;
+use testcontainers::clients::Cli;
+
+use common_utils::event_bus::orders_topic;
+use consumer_stocks_service::process_order_message;
+use stocks_service::create_schema_with_context;
+use stocks_service::persistence::connection::PgPool;
+use stocks_service::persistence::model::{NewStocksEntity, OrderEntity, StocksEntity};
+use stocks_service::persistence::repository;
+use stocks_service::stock_functions::{
+    check_bracket_prices, evaluate_pending_orders, get_cash_balance, get_price_series, next_order_status,
+    record_cash_movement, save_stock, withdraw_cash, OrderStatus, TimeBucket, TradeError,
+};
+
+mod common;
+
+const DEPOSIT: u32 = 10_000;
+
+async fn execute(pool: &PgPool, query: &str, variables: serde_json::Value) -> serde_json::Value {
+    let response = create_schema_with_context(pool.clone())
+        .execute(Request::new(query).variables(Variables::from_json(variables)))
+        .await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().expect("Can't read response data")
+}
+
+async fn deposit(pool: &PgPool) {
+    execute(pool, r#"mutation($amount: String!) { deposit(amount: $amount) { id } }"#, json!({ "amount": DEPOSIT.to_string() }))
+        .await;
+}
+
+async fn buy(pool: &PgPool, symbol: &str, shares: u32) -> serde_json::Value {
+    execute(
+        pool,
+        r#"mutation($symbol: String!, $shares: BigDecimal!) {
+            buyStocks(stock: { symbol: $symbol, shares: $shares }) { id symbol shares price actionType }
+        }"#,
+        json!({ "symbol": symbol, "shares": shares.to_string() }),
+    )
+    .await["buyStocks"]
+        .clone()
+}
+
+async fn summary(pool: &PgPool, symbol: &str) -> serde_json::Value {
+    let data = execute(
+        pool,
+        "{ stocksSummary { symbol shares lowestPrice highestPrice averagePrice } }",
+        json!({}),
+    )
+    .await;
+    data["stocksSummary"]
+        .as_array()
+        .expect("Can't get summaries as array")
+        .iter()
+        .find(|summary| summary["symbol"] == symbol)
+        .cloned()
+        .unwrap_or_else(|| panic!("No summary for {}", symbol))
+}
+
+async fn cash_balance(pool: &PgPool) -> BigDecimal {
+    decimal(&execute(pool, "{ cashBalance }", json!({})).await["cashBalance"])
+}
+
+fn decimal(value: &serde_json::Value) -> BigDecimal {
+    BigDecimal::from_str(value.as_str().expect("Can't get value as str")).expect("Can't parse decimal")
+}
+
+/// Cash the trades in `symbol` moved, fees included.
+fn traded_cash(pool: &PgPool, symbol: &str) -> BigDecimal {
+    repository::get_stocks_by_symbol(symbol.to_string(), &mut pool.get().expect("Can't get DB connection"))
+        .iter()
+        .map(|trade| {
+            let notional = trade.price.parse::<BigDecimal>().unwrap() * &trade.shares;
+            if trade.action_type == "buy" {
+                -(notional + &trade.fee)
+            } else {
+                notional - &trade.fee
+            }
+        })
+        .sum()
+}
+
+fn create_trade(pool: &PgPool, symbol: &str, shares: u32, price: &str) -> StocksEntity {
+    repository::create_stock(new_trade(symbol, shares, price), &mut pool.get().expect("Can't get DB connection"))
+        .expect("Can't create a trade")
+}
+
+/// A buy of the seeded user.
+fn new_trade(symbol: &str, shares: u32, price: &str) -> NewStocksEntity {
+    NewStocksEntity {
+        symbol: symbol.to_string(),
+        shares: BigDecimal::from(shares),
+        price: price.to_string(),
+        percentage_change: "0.5".to_string(),
+        action_type: "buy".to_string(),
+        user_id: 1,
+        fee: BigDecimal::from(0),
+        currency: "USD".to_string(),
+        fx_rate: BigDecimal::from(1),
+        multiplier: 1,
+    }
+}
+
+fn set_trade_time(pool: &PgPool, trade: &StocksEntity, created_at: NaiveDateTime) {
+    diesel::sql_query("UPDATE stocks SET created_at = $1 WHERE id = $2")
+        .bind::<Timestamp, _>(created_at)
+        .bind::<Int4, _>(trade.id)
+        .execute(&mut pool.get().expect("Can't get DB connection"))
+        .expect("Can't update trade time");
+}
+
+fn at(hour: u32, minute: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2023, 10, 26).unwrap().and_hms_opt(hour, minute, 0).unwrap()
+}
+
+#[actix_rt::test]
+async fn test_buy_stocks() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+
+    let stock = buy(&pool, "aapl", 10).await;
+
+    assert_eq!("AAPL", stock["symbol"]);
+    assert_eq!("buy", stock["actionType"]);
+    assert_eq!(BigDecimal::from(10), decimal(&stock["shares"]));
+    assert_eq!(BigDecimal::from(100), decimal(&stock["price"]));
+    assert_eq!(BigDecimal::from(DEPOSIT) + traded_cash(&pool, "AAPL"), cash_balance(&pool).await);
+    assert!(traded_cash(&pool, "AAPL") <= BigDecimal::from(-1000));
+    assert_eq!(BigDecimal::from(10), decimal(&summary(&pool, "AAPL").await["shares"]));
+
+    let orders = repository::get_orders_by_status(vec!["filled".to_string()], &mut pool.get().unwrap())
+        .expect("Can't get orders");
+    assert_eq!(1, orders.len());
+    assert_eq!(vec![format!("AAPL,10,buy,{}", orders[0].id)], fakes.events.messages(orders_topic()));
+}
+
+#[actix_rt::test]
+async fn test_buy_stocks_fails_for_unknown_symbol() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    deposit(&pool).await;
+
+    let response = create_schema_with_context(pool.clone())
+        .execute(r#"mutation { buyStocks(stock: { symbol: "NOPE", shares: 1 }) { id } }"#)
+        .await;
+
+    assert!(!response.errors.is_empty());
+    assert!(repository::get_stocks_by_symbol("NOPE".to_string(), &mut pool.get().unwrap()).is_empty());
+    assert_eq!(BigDecimal::from(DEPOSIT), cash_balance(&pool).await);
+    assert!(common::fakes().events.messages(orders_topic()).is_empty());
+}
+
+#[actix_rt::test]
+async fn test_limit_sale_fills_when_price_reaches_limit() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+    buy(&pool, "AAPL", 10).await;
+
+    let order = execute(
+        &pool,
+        r#"mutation {
+            placeOrder(order: { symbol: "AAPL", shares: 4, action: SALE, orderType: LIMIT, limitPrice: "110.00" }) { id status }
+        }"#,
+        json!({}),
+    )
+    .await["placeOrder"]
+        .clone();
+    assert_eq!("pending", order["status"]);
+    let order_id = order["id"].as_str().unwrap().parse::<i32>().unwrap();
+
+    evaluate_pending_orders(&pool).unwrap();
+    assert_eq!("pending", repository::get_order(order_id, &mut pool.get().unwrap()).unwrap().status);
+
+    fakes.quotes.set_price("AAPL", "120.00");
+    evaluate_pending_orders(&pool).unwrap();
+
+    assert_eq!("filled", repository::get_order(order_id, &mut pool.get().unwrap()).unwrap().status);
+    let trades = repository::get_stocks_by_symbol("AAPL".to_string(), &mut pool.get().unwrap());
+    let sale = trades.iter().find(|trade| trade.action_type == "sale").expect("No sale recorded");
+    assert_eq!(BigDecimal::from(4), sale.shares);
+    assert_eq!(BigDecimal::from(120), sale.price.parse::<BigDecimal>().unwrap());
+    assert_eq!(BigDecimal::from(6), decimal(&summary(&pool, "AAPL").await["shares"]));
+    assert_eq!(BigDecimal::from(DEPOSIT) + traded_cash(&pool, "AAPL"), cash_balance(&pool).await);
+}
+
+#[actix_rt::test]
+async fn test_summary_is_recomputed_after_each_trade() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    deposit(&pool).await;
+
+    fakes.quotes.set_price("AAPL", "100.00");
+    buy(&pool, "AAPL", 10).await;
+    let first = summary(&pool, "AAPL").await;
+    assert_eq!(BigDecimal::from(10), decimal(&first["shares"]));
+    assert_eq!(BigDecimal::from(100), decimal(&first["lowestPrice"]));
+    assert_eq!(BigDecimal::from(100), decimal(&first["highestPrice"]));
+
+    fakes.quotes.set_price("AAPL", "120.00");
+    buy(&pool, "AAPL", 5).await;
+    let second = summary(&pool, "AAPL").await;
+    assert_eq!(BigDecimal::from(15), decimal(&second["shares"]));
+    assert_eq!(BigDecimal::from(100), decimal(&second["lowestPrice"]));
+    assert_eq!(BigDecimal::from(120), decimal(&second["highestPrice"]));
+    assert_eq!(BigDecimal::from(110), decimal(&second["averagePrice"]));
+}
+
+#[actix_rt::test]
+async fn test_price_series_aggregates_trades_by_hour() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let trades = [
+        (create_trade(&pool, "AAPL", 1, "100.00"), at(10, 5)),
+        (create_trade(&pool, "AAPL", 2, "110.00"), at(10, 55)),
+        (create_trade(&pool, "AAPL", 5, "130.00"), at(11, 0)),
+        (create_trade(&pool, "MSFT", 7, "300.00"), at(10, 30)),
+    ];
+    for (trade, created_at) in &trades {
+        set_trade_time(&pool, trade, *created_at);
+    }
+
+    let series = get_price_series(1, "AAPL", TimeBucket::Hour, "UTC", &pool).expect("Can't get price series");
+
+    assert_eq!(2, series.len());
+    assert_eq!(at(10, 0), series[0].local_start);
+    assert_eq!(at(10, 0), series[0].bucket_start.naive_utc());
+    assert_eq!(BigDecimal::from(105), series[0].avg_price);
+    assert_eq!(BigDecimal::from(100), series[0].min_price);
+    assert_eq!(BigDecimal::from(110), series[0].max_price);
+    assert_eq!(BigDecimal::from(3), series[0].volume);
+    assert_eq!(at(11, 0), series[1].local_start);
+    assert_eq!(BigDecimal::from(130), series[1].avg_price);
+    assert_eq!(BigDecimal::from(5), series[1].volume);
+
+    let daily = get_price_series(1, "AAPL", TimeBucket::Day, "UTC", &pool).expect("Can't get price series");
+    assert_eq!(1, daily.len());
+    assert_eq!(BigDecimal::from(8), daily[0].volume);
+}
+
+#[actix_rt::test]
+async fn test_price_series_only_has_the_trades_of_the_user() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let other_user = common::create_user(&pool, "Grace");
+    set_trade_time(&pool, &create_trade(&pool, "AAPL", 1, "100.00"), at(10, 5));
+    let other_trade = repository::create_stock(NewStocksEntity {
+        user_id: other_user,
+        ..new_trade("AAPL", 9, "200.00")
+    }, &mut pool.get().expect("Can't get DB connection"))
+    .expect("Can't create a trade");
+    set_trade_time(&pool, &other_trade, at(10, 10));
+
+    let series = get_price_series(1, "AAPL", TimeBucket::Hour, "UTC", &pool).expect("Can't get price series");
+
+    assert_eq!(1, series.len());
+    assert_eq!(BigDecimal::from(100), series[0].avg_price);
+    assert_eq!(BigDecimal::from(1), series[0].volume);
+    let others = get_price_series(other_user, "AAPL", TimeBucket::Hour, "UTC", &pool).expect("Can't get price series");
+    assert_eq!(BigDecimal::from(9), others[0].volume);
+}
+
+fn november(day: u32, hour: u32) -> NaiveDateTime {
+    NaiveDate::from_ymd_opt(2023, 11, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+}
+
+#[actix_rt::test]
+async fn test_price_series_buckets_start_at_local_boundaries() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    // 23:30 on October 25th and 01:00 on October 26th in New York (UTC-4).
+    set_trade_time(&pool, &create_trade(&pool, "AAPL", 1, "100.00"), at(3, 30));
+    set_trade_time(&pool, &create_trade(&pool, "AAPL", 2, "110.00"), at(5, 0));
+
+    assert_eq!(1, get_price_series(1, "AAPL", TimeBucket::Day, "UTC", &pool).unwrap().len());
+    let series = get_price_series(1, "AAPL", TimeBucket::Day, "America/New_York", &pool).unwrap();
+    assert_eq!(2, series.len());
+    assert_eq!(NaiveDate::from_ymd_opt(2023, 10, 25).unwrap().and_hms_opt(0, 0, 0).unwrap(), series[0].local_start);
+    assert_eq!(NaiveDate::from_ymd_opt(2023, 10, 25).unwrap().and_hms_opt(4, 0, 0).unwrap(), series[0].bucket_start.naive_utc());
+    assert_eq!(BigDecimal::from(1), series[0].volume);
+    assert_eq!(at(0, 0), series[1].local_start);
+    assert_eq!(at(4, 0), series[1].bucket_start.naive_utc());
+    assert_eq!(BigDecimal::from(2), series[1].volume);
+
+    // India is 5:30 ahead of UTC, so its hours start on the half hour.
+    let series = get_price_series(1, "AAPL", TimeBucket::Hour, "Asia/Kolkata", &pool).unwrap();
+    assert_eq!(vec![at(3, 30), at(4, 30)], series.iter().map(|bucket| bucket.bucket_start.naive_utc()).collect::<Vec<_>>());
+}
+
+#[actix_rt::test]
+async fn test_price_series_offsets_follow_daylight_saving_time() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+    buy(&pool, "AAPL", 1).await;
+    buy(&pool, "AAPL", 2).await;
+    let mut trades = repository::get_stocks_by_symbol("AAPL".to_string(), &mut pool.get().unwrap());
+    trades.sort_by_key(|trade| trade.id);
+    // New York leaves daylight saving time on November 5th at 2:00.
+    set_trade_time(&pool, &trades[0], november(4, 16));
+    set_trade_time(&pool, &trades[1], november(6, 16));
+
+    let data = execute(
+        &pool,
+        r#"{ stocksSummary { priceSeries(bucket: DAY, timeZone: "America/New_York") { bucketStart volume } } }"#,
+        json!({}),
+    )
+    .await;
+
+    let series = data["stocksSummary"][0]["priceSeries"].as_array().unwrap();
+    assert_eq!(2, series.len());
+    assert_eq!("2023-11-04T00:00:00-04:00", series[0]["bucketStart"]);
+    assert_eq!("2023-11-06T00:00:00-05:00", series[1]["bucketStart"]);
+    assert_eq!(BigDecimal::from(2), decimal(&series[1]["volume"]));
+}
+
+#[actix_rt::test]
+async fn test_price_series_rejects_unknown_time_zones() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+    buy(&pool, "AAPL", 1).await;
+
+    assert!(get_price_series(1, "AAPL", TimeBucket::Hour, "Mars/Olympus_Mons", &pool).is_err());
+    let response = create_schema_with_context(pool.clone())
+        .execute(r#"{ stocksSummary { priceSeries(timeZone: "Mars/Olympus_Mons") { bucketStart } } }"#)
+        .await;
+    assert!(
+        response.errors[0].message.starts_with("Can't compute the price series in Mars/Olympus_Mons"),
+        "{}",
+        response.errors[0].message
+    );
+}
+
+#[actix_rt::test]
+async fn test_get_stocks_by_symbol() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    create_trade(&pool, "AAPL", 1, "100.00");
+    create_trade(&pool, "AAPL", 2, "110.00");
+    create_trade(&pool, "MSFT", 3, "300.00");
+    let mut conn = pool.get().expect("Can't get DB connection");
+
+    let trades = repository::get_stocks_by_symbol("AAPL".to_string(), &mut conn);
+    assert_eq!(2, trades.len());
+    assert!(trades.iter().all(|trade| trade.symbol == "AAPL"));
+    assert_eq!(1, repository::get_stocks_by_symbol("MSFT".to_string(), &mut conn).len());
+    assert!(repository::get_stocks_by_symbol("aapl".to_string(), &mut conn).is_empty());
+    assert!(repository::get_stocks_by_symbol("GOOG".to_string(), &mut conn).is_empty());
+}
+
+fn open_order(order_type: &str, action: &str, status: &str, stop_price: Option<&str>, limit_price: Option<&str>) -> OrderEntity {
+    OrderEntity {
+        id: 1,
+        symbol: "AAPL".to_string(),
+        shares: Some(BigDecimal::from(1)),
+        amount: None,
+        action_type: action.to_string(),
+        order_type: order_type.to_string(),
+        stop_price: stop_price.map(str::to_string),
+        limit_price: limit_price.map(str::to_string),
+        status: status.to_string(),
+        parent_id: None,
+        user_id: 1,
+    }
+}
+
+#[test]
+fn test_next_order_status_transitions() {
+    use OrderStatus::{Filled, Triggered};
+    let cases = [
+        // (order type, action, status, stop, limit, price, next status)
+        ("market", "buy", "pending", None, None, "100", Some(Filled)),
+        ("limit", "buy", "pending", None, Some("100"), "101", None),
+        ("limit", "buy", "pending", None, Some("100"), "100", Some(Filled)),
+        ("limit", "sale", "pending", None, Some("100"), "99", None),
+        ("limit", "sale", "pending", None, Some("100"), "101", Some(Filled)),
+        ("stop", "buy", "pending", Some("100"), None, "99", None),
+        ("stop", "buy", "pending", Some("100"), None, "100", Some(Filled)),
+        ("stop", "sale", "pending", Some("100"), None, "101", None),
+        ("stop", "sale", "pending", Some("100"), None, "95", Some(Filled)),
+        // A stop-limit sale triggers at its stop and fills once the price is at or above its limit.
+        ("stop_limit", "sale", "pending", Some("100"), Some("98"), "101", None),
+        ("stop_limit", "sale", "pending", Some("100"), Some("98"), "99", Some(Filled)),
+        ("stop_limit", "sale", "pending", Some("100"), Some("98"), "97", Some(Triggered)),
+        ("stop_limit", "sale", "triggered", Some("100"), Some("98"), "97", None),
+        ("stop_limit", "sale", "triggered", Some("100"), Some("98"), "102", Some(Filled)),
+        ("stop_limit", "buy", "pending", Some("100"), Some("102"), "101", Some(Filled)),
+        ("stop_limit", "buy", "pending", Some("100"), Some("102"), "103", Some(Triggered)),
+        ("stop_limit", "buy", "triggered", Some("100"), Some("102"), "99", Some(Filled)),
+        // Orders with unreadable prices or types keep waiting.
+        ("stop", "sale", "pending", Some("not-a-price"), None, "1", None),
+        ("trailing_stop", "sale", "pending", Some("100"), None, "1", None),
+    ];
+
+    for (order_type, action, status, stop_price, limit_price, price, expected) in cases {
+        let order = open_order(order_type, action, status, stop_price, limit_price);
+        let price = BigDecimal::from_str(price).unwrap();
+        assert_eq!(
+            expected,
+            next_order_status(&order, &price),
+            "{} {} {} stop {:?} limit {:?} at {}", status, order_type, action, stop_price, limit_price, price
+        );
+    }
+}
+
+#[test]
+fn test_bracket_prices_must_surround_the_entry() {
+    let cases = [
+        // (action, take profit, stop loss, valid)
+        ("buy", Some("110"), Some("90"), true),
+        ("buy", Some("110"), None, true),
+        ("buy", None, Some("90"), true),
+        ("buy", Some("100"), None, false),
+        ("buy", Some("90"), None, false),
+        ("buy", None, Some("100"), false),
+        ("buy", None, Some("110"), false),
+        ("sale", Some("90"), Some("110"), true),
+        ("sale", Some("110"), None, false),
+        ("sale", None, Some("90"), false),
+        ("buy", Some("lots"), None, false),
+    ];
+
+    for (action, take_profit, stop_loss, valid) in cases {
+        assert_eq!(
+            valid,
+            check_bracket_prices(action, "100", take_profit, stop_loss).is_ok(),
+            "{} take profit {:?} stop loss {:?}", action, take_profit, stop_loss
+        );
+    }
+}
+
+#[actix_rt::test]
+async fn test_bracket_stop_loss_fills_and_cancels_take_profit() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    let fakes = common::fakes();
+    fakes.quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+
+    execute(
+        &pool,
+        r#"mutation {
+            buyStocks(stock: { symbol: "AAPL", shares: 10, takeProfit: "120.00", stopLoss: "90.00" }) { id }
+        }"#,
+        json!({}),
+    )
+    .await;
+    let legs = repository::get_orders_by_status(vec!["pending".to_string()], &mut pool.get().unwrap())
+        .expect("Can't get orders");
+    assert_eq!(2, legs.len());
+    assert!(legs.iter().all(|leg| leg.action_type == "sale" && leg.user_id == 1 && leg.parent_id.is_some()));
+    let stop_loss = legs.iter().find(|leg| leg.order_type == "stop").expect("No stop-loss leg");
+    let take_profit = legs.iter().find(|leg| leg.order_type == "limit").expect("No take-profit leg");
+
+    fakes.quotes.set_price("AAPL", "95.00");
+    evaluate_pending_orders(&pool).unwrap();
+    assert_eq!("pending", repository::get_order(stop_loss.id, &mut pool.get().unwrap()).unwrap().status);
+
+    fakes.quotes.set_price("AAPL", "85.00");
+    evaluate_pending_orders(&pool).unwrap();
+
+    let mut conn = pool.get().unwrap();
+    assert_eq!("filled", repository::get_order(stop_loss.id, &mut conn).unwrap().status);
+    assert_eq!("cancelled", repository::get_order(take_profit.id, &mut conn).unwrap().status);
+    assert_eq!(BigDecimal::from(0), decimal(&summary(&pool, "AAPL").await["shares"]));
+}
+
+#[actix_rt::test]
+async fn test_bracket_on_the_wrong_side_of_the_price_is_rejected() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+
+    let response = create_schema_with_context(pool.clone())
+        .execute(r#"mutation { buyStocks(stock: { symbol: "AAPL", shares: 10, stopLoss: "110.00" }) { id } }"#)
+        .await;
+
+    assert!(response.errors[0].message.contains("stopLoss"), "{:?}", response.errors);
+    assert!(repository::get_stocks_by_symbol("AAPL".to_string(), &mut pool.get().unwrap()).is_empty());
+    assert!(repository::get_orders(&mut pool.get().unwrap()).unwrap().is_empty());
+    assert_eq!(BigDecimal::from(DEPOSIT), cash_balance(&pool).await);
+}
+
+#[actix_rt::test]
+async fn test_trades_and_summaries_belong_to_the_buyer() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    let other_user = common::create_user(&pool, "Grace");
+    deposit(&pool).await;
+    record_cash_movement(other_user, BigDecimal::from(DEPOSIT), "USD", None, &pool).unwrap();
+    execute(
+        &pool,
+        r#"mutation($userId: Int!) {
+            setFeeSchedule(userId: $userId, schedule: { flatFee: "5", perShareFee: "0", percentageFee: "0", sellFeeRate: "0" }) { flatFee }
+        }"#,
+        json!({ "userId": other_user }),
+    )
+    .await;
+
+    buy(&pool, "AAPL", 10).await;
+    execute(
+        &pool,
+        r#"mutation($userId: Int!) {
+            buyStocks(userId: $userId, stock: { symbol: "AAPL", shares: 3, stopLoss: "90.00" }) { id }
+        }"#,
+        json!({ "userId": other_user }),
+    )
+    .await;
+
+    let mut conn = pool.get().unwrap();
+    let trades = repository::get_user_stocks_by_symbol(other_user, "AAPL".to_string(), &mut conn).unwrap();
+    assert_eq!(1, trades.len());
+    assert_eq!(BigDecimal::from(3), trades[0].shares);
+    assert_eq!(BigDecimal::from(5), trades[0].fee);
+    assert_eq!(BigDecimal::from(0), repository::get_user_stocks_by_symbol(1, "AAPL".to_string(), &mut conn).unwrap()[0].fee);
+    let summaries = |user_id: i32| repository::get_stocks_summary(user_id, &mut pool.get().unwrap()).unwrap();
+    assert_eq!(BigDecimal::from(10), summaries(1)[0].shares);
+    assert_eq!(BigDecimal::from(3), summaries(other_user)[0].shares);
+    assert_eq!(BigDecimal::from(DEPOSIT - 305), get_cash_balance(other_user, "USD", &pool).unwrap());
+    assert_eq!(BigDecimal::from(DEPOSIT - 1000), get_cash_balance(1, "USD", &pool).unwrap());
+    let legs = repository::get_orders_by_status(vec!["pending".to_string()], &mut conn).unwrap();
+    assert_eq!(vec![other_user], legs.iter().map(|leg| leg.user_id).collect::<Vec<_>>());
+}
+
+#[actix_rt::test]
+async fn test_concurrent_withdrawals_cant_overdraw() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    record_cash_movement(1, BigDecimal::from(100), "USD", None, &pool).unwrap();
+
+    let withdrawals = (0..5)
+        .map(|_| {
+            let pool = pool.clone();
+            std::thread::spawn(move || withdraw_cash(1, BigDecimal::from(30), "USD", None, &pool))
+        })
+        .collect::<Vec<_>>();
+    let results = withdrawals.into_iter().map(|withdrawal| withdrawal.join().unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(3, results.iter().filter(|result| result.is_ok()).count());
+    assert!(results.iter().all(|result| !matches!(result, Err(TradeError::Unavailable(_)))));
+    assert_eq!(BigDecimal::from(10), get_cash_balance(1, "USD", &pool).unwrap());
+}
+
+#[actix_rt::test]
+async fn test_concurrent_buys_cant_overdraw() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    record_cash_movement(1, BigDecimal::from(1000), "USD", None, &pool).unwrap();
+
+    let buys = (0..4)
+        .map(|_| {
+            let pool = pool.clone();
+            std::thread::spawn(move || {
+                save_stock(1, "AAPL".to_string(), BigDecimal::from(6), "100.00".to_string(), "0.5".to_string(), "buy".to_string(), &pool)
+            })
+        })
+        .collect::<Vec<_>>();
+    let results = buys.into_iter().map(|buy| buy.join().unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(1, results.iter().filter(|result| result.is_ok()).count());
+    assert_eq!(BigDecimal::from(400), get_cash_balance(1, "USD", &pool).unwrap());
+    assert_eq!(1, repository::get_stocks_by_symbol("AAPL".to_string(), &mut pool.get().unwrap()).len());
+}
+
+#[actix_rt::test]
+async fn test_sales_larger_than_the_position_are_rejected() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+    buy(&pool, "AAPL", 5).await;
+
+    let response = create_schema_with_context(pool.clone())
+        .execute(r#"mutation { placeOrder(order: { symbol: "AAPL", shares: 6, action: SALE, orderType: LIMIT, limitPrice: "110.00" }) { id } }"#)
+        .await;
+    assert!(response.errors[0].message.contains("Insufficient shares"), "{:?}", response.errors);
+
+    let sale = save_stock(1, "AAPL".to_string(), BigDecimal::from(6), "100.00".to_string(), "0.5".to_string(), "sale".to_string(), &pool);
+    assert!(matches!(sale, Err(TradeError::Rejected(_))), "{:?}", sale.err());
+    assert_eq!(1, repository::get_stocks_by_symbol("AAPL".to_string(), &mut pool.get().unwrap()).len());
+    assert_eq!(BigDecimal::from(5), decimal(&summary(&pool, "AAPL").await["shares"]));
+}
+
+#[actix_rt::test]
+async fn test_orders_without_a_known_action_are_rejected() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    common::fakes().quotes.set_price("AAPL", "100.00");
+    deposit(&pool).await;
+    buy(&pool, "AAPL", 5).await;
+
+    for action in ["", r#", action: "sale""#, ", action: SHORT"] {
+        let response = create_schema_with_context(pool.clone())
+            .execute(format!(
+                r#"mutation {{ placeOrder(order: {{ symbol: "AAPL", shares: 1{}, orderType: LIMIT, limitPrice: "110.00" }}) {{ id }} }}"#,
+                action
+            ))
+            .await;
+        assert!(!response.errors.is_empty(), "placed an order with {:?}", action);
+    }
+    let trade = save_stock(1, "AAPL".to_string(), BigDecimal::from(1), "100.00".to_string(), "0.5".to_string(), "short".to_string(), &pool);
+    assert!(matches!(trade, Err(TradeError::Rejected(_))), "{:?}", trade.err());
+    assert!(matches!(process_order_message(b"AAPL,1,short", &pool), Err(TradeError::Rejected(_))));
+
+    assert!(repository::get_orders(&mut pool.get().unwrap()).unwrap().is_empty());
+    assert_eq!(1, repository::get_stocks_by_symbol("AAPL".to_string(), &mut pool.get().unwrap()).len());
+    assert_eq!(BigDecimal::from(5), decimal(&summary(&pool, "AAPL").await["shares"]));
+}
+
+#[actix_rt::test]
+async fn test_withdrawals_are_limited_to_the_balance() {
+    let docker = Cli::default();
+    let (_pg_container, pool) = common::setup(&docker);
+    deposit(&pool).await;
+
+    let response = create_schema_with_context(pool.clone())
+        .execute(r#"mutation { withdraw(amount: "10000.01") { id } }"#)
+        .await;
+
+    assert!(response.errors[0].message.contains("Insufficient cash"), "{:?}", response.errors);
+    assert_eq!(BigDecimal::from(DEPOSIT), cash_balance(&pool).await);
+}